@@ -0,0 +1,373 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use clap::Args;
+
+use crate::{
+    bundle::extract,
+    error::RoutingError,
+    geo::DistanceModel,
+    graph::Sanitation,
+    memory::{fits_hl, parse_bytes, MemoryEstimate},
+    storage::{is_url, store_for},
+};
+
+#[derive(Args, Debug, Clone)]
+pub struct ArtifactArgs {
+    /// Directory in which artifacts not given explicitly are looked up. Of all basenames for
+    /// which every needed artifact exists, the most recently modified set is used
+    #[arg(short, long)]
+    pub data_dir: Option<PathBuf>,
+    /// Bundle written by `bundle create`, used instead of --data-dir. It is unpacked next to
+    /// itself on first use
+    #[arg(long)]
+    pub bundle: Option<PathBuf>,
+    /// Path or http(s) URL of .gr file
+    #[arg(short, long)]
+    pub gr_path: Option<PathBuf>,
+    /// Path or http(s) URL of .co file
+    #[arg(short, long)]
+    pub co_path: Option<PathBuf>,
+    /// Path or http(s) URL of .ch file
+    #[arg(short, long)]
+    pub ch_path: Option<PathBuf>,
+    /// Path or http(s) URL of .hl file. Without it, and without one in --data-dir, only CH is
+    /// available
+    #[arg(short, long)]
+    pub hl_path: Option<PathBuf>,
+    /// Also snap to points interpolated along the edges, spaced at most this many meters
+    #[arg(long)]
+    pub snap_spacing: Option<f64>,
+    /// Depth of the spatial index used for snapping
+    #[arg(long, default_value_t = 10)]
+    pub spatial_partition_depth: usize,
+    /// How edge lengths and reported distances are computed. Snapping always picks the
+    /// nearest point of the spatial index of osm_converter
+    #[arg(long, value_enum, default_value_t = DistanceModel::Haversine)]
+    pub distance_model: DistanceModel,
+    /// Memory budget, e.g. 4G. HL is not loaded if it would not fit
+    #[arg(long, value_parser = parse_bytes)]
+    pub max_memory: Option<u64>,
+    /// Handling of self-loops, parallel edges and zero or negative weights in the .gr file
+    #[arg(long, value_enum, default_value_t = Sanitation::Off)]
+    pub sanitize: Sanitation,
+}
+
+/// Artifact paths after `~` expansion and data directory lookup, plus how to load them.
+#[derive(Debug, Clone)]
+pub struct ArtifactPaths {
+    pub gr_path: PathBuf,
+    pub co_path: PathBuf,
+    pub ch_path: PathBuf,
+    /// `None` if there is no .hl file or it does not fit into --max-memory.
+    pub hl_path: Option<PathBuf>,
+    pub snap_spacing: Option<f64>,
+    pub spatial_partition_depth: usize,
+    pub distance_model: DistanceModel,
+    pub sanitation: Sanitation,
+}
+
+impl ArtifactArgs {
+    /// Collects every problem with the given paths instead of stopping at the first one.
+    pub fn resolve(&self) -> Result<ArtifactPaths, Vec<String>> {
+        let mut errors = Vec::new();
+        let missing_extensions: Vec<&str> = [
+            ("gr", &self.gr_path),
+            ("co", &self.co_path),
+            ("ch", &self.ch_path),
+        ]
+        .into_iter()
+        .filter(|(_, path)| path.is_none())
+        .map(|(extension, _)| extension)
+        .collect();
+        let data_dir = match (&self.bundle, &self.data_dir) {
+            (Some(_), Some(_)) => {
+                errors.push("--bundle: cannot be combined with --data-dir".to_string());
+                None
+            }
+            (Some(bundle), None) => {
+                let bundle = expand_tilde(bundle);
+                let mut dir = bundle.clone().into_os_string();
+                dir.push(".d");
+                match extract(&bundle, Path::new(&dir)) {
+                    Ok(_) => Some(PathBuf::from(dir)),
+                    Err(error) => {
+                        errors.push(format!("--bundle: {}", error));
+                        None
+                    }
+                }
+            }
+            (None, data_dir) => data_dir.as_deref().map(expand_tilde),
+        };
+        let artifact_set = data_dir
+            .as_deref()
+            .filter(|_| !missing_extensions.is_empty())
+            .map(|data_dir| find_artifact_set(data_dir, &missing_extensions));
+
+        let mut resolve = |flag: &str, extension: &str, path: &Option<PathBuf>| {
+            let path = match (path, &artifact_set) {
+                (Some(path), _) => match local_copy(path) {
+                    Ok(path) => path,
+                    Err(error) => {
+                        errors.push(format!("{}: {}", flag, error));
+                        return PathBuf::new();
+                    }
+                },
+                (None, Some(Ok(artifact_set))) => artifact_set.path(extension),
+                (None, Some(Err(error))) => {
+                    errors.push(format!("{}: {}", flag, error));
+                    return PathBuf::new();
+                }
+                (None, None) => {
+                    errors.push(format!("{}: missing, pass it or --data-dir", flag));
+                    return PathBuf::new();
+                }
+            };
+            if let Err(error) = check_file(&path) {
+                errors.push(format!("{}: {}", flag, error));
+            }
+            path
+        };
+
+        let paths = ArtifactPaths {
+            gr_path: resolve("--gr-path", "gr", &self.gr_path),
+            co_path: resolve("--co-path", "co", &self.co_path),
+            ch_path: resolve("--ch-path", "ch", &self.ch_path),
+            hl_path: None,
+            snap_spacing: self.snap_spacing,
+            spatial_partition_depth: self.spatial_partition_depth,
+            distance_model: self.distance_model,
+            sanitation: self.sanitize,
+        };
+        let mut paths = paths;
+        paths.hl_path = match (&self.hl_path, &artifact_set) {
+            (Some(path), _) => match local_copy(path) {
+                Ok(path) => {
+                    if let Err(error) = check_file(&path) {
+                        errors.push(format!("--hl-path: {}", error));
+                    }
+                    Some(path)
+                }
+                Err(error) => {
+                    errors.push(format!("--hl-path: {}", error));
+                    None
+                }
+            },
+            // optional, so only taken from the set if it is there
+            (None, Some(Ok(artifact_set))) => {
+                Some(artifact_set.path("hl")).filter(|path| check_file(path).is_ok())
+            }
+            (None, _) => None,
+        };
+
+        if let Some(spacing) = self.snap_spacing {
+            if spacing.is_nan() || spacing <= 0.0 {
+                errors.push("--snap-spacing: must be positive".to_string());
+            }
+        }
+
+        // the .gr/.co reader of osm_converter only takes &str
+        for (flag, path) in [("--gr-path", &paths.gr_path), ("--co-path", &paths.co_path)] {
+            if path.to_str().is_none() {
+                errors.push(format!("{}: '{}' is not valid UTF-8", flag, path.display()));
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        if let Some(max_memory) = self.max_memory {
+            match fits_hl(&MemoryEstimate::new(&paths), max_memory) {
+                Ok(true) => {}
+                Ok(false) => paths.hl_path = None,
+                Err(error) => errors.push(format!("--max-memory: {}", error)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(paths)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Checks the flags without extracting --bundle or downloading http(s) artifacts, which
+    /// only `resolve` does. What those produce is checked when resolving.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let required = [
+            ("--gr-path", "gr", &self.gr_path),
+            ("--co-path", "co", &self.co_path),
+            ("--ch-path", "ch", &self.ch_path),
+        ];
+        let missing: Vec<(&str, &str)> = required
+            .iter()
+            .filter(|(_, _, path)| path.is_none())
+            .map(|&(flag, extension, _)| (flag, extension))
+            .collect();
+        match (&self.bundle, &self.data_dir) {
+            (Some(_), Some(_)) => {
+                errors.push("--bundle: cannot be combined with --data-dir".to_string());
+            }
+            (Some(bundle), None) => {
+                if let Err(error) = check_file(&expand_tilde(bundle)) {
+                    errors.push(format!("--bundle: {}", error));
+                }
+            }
+            (None, Some(_)) if missing.is_empty() => {}
+            (None, Some(data_dir)) => {
+                let extensions: Vec<&str> =
+                    missing.iter().map(|&(_, extension)| extension).collect();
+                if let Err(error) = find_artifact_set(&expand_tilde(data_dir), &extensions) {
+                    for (flag, _) in missing.iter() {
+                        errors.push(format!("{}: {}", flag, error));
+                    }
+                }
+            }
+            (None, None) => {
+                for (flag, _) in missing.iter() {
+                    errors.push(format!("{}: missing, pass it or --data-dir", flag));
+                }
+            }
+        }
+
+        let explicit = required
+            .iter()
+            .map(|&(flag, _, path)| (flag, path))
+            .chain([("--hl-path", &self.hl_path)]);
+        for (flag, path) in explicit {
+            let Some(path) = path.as_deref().filter(|path| !is_url(path)) else {
+                continue;
+            };
+            if let Err(error) = check_file(&expand_tilde(path)) {
+                errors.push(format!("{}: {}", flag, error));
+            }
+            // the .gr/.co reader of osm_converter only takes &str
+            if (flag == "--gr-path" || flag == "--co-path") && path.to_str().is_none() {
+                errors.push(format!("{}: '{}' is not valid UTF-8", flag, path.display()));
+            }
+        }
+
+        if let Some(spacing) = self.snap_spacing {
+            if spacing.is_nan() || spacing <= 0.0 {
+                errors.push("--snap-spacing: must be positive".to_string());
+            }
+        }
+        errors
+    }
+
+    /// `resolve` for commands that cannot run without the artifacts, so after `validate` only
+    /// failed extractions and downloads are left to report.
+    pub fn resolve_or_exit(&self) -> ArtifactPaths {
+        self.resolve().unwrap_or_else(|errors| exit_invalid(errors))
+    }
+}
+
+/// Prints every error and exits.
+pub fn exit_invalid(errors: Vec<String>) -> ! {
+    eprintln!("invalid configuration:");
+    for error in errors {
+        eprintln!("  - {}", error);
+    }
+    std::process::exit(2);
+}
+
+/// Artifacts given as http(s) URLs are downloaded, or revalidated if cached, everything else
+/// is used in place.
+fn local_copy(path: &Path) -> Result<PathBuf, RoutingError> {
+    if is_url(path) {
+        store_for(path).local_path(path)
+    } else {
+        Ok(expand_tilde(path))
+    }
+}
+
+pub fn check_file(path: &Path) -> Result<(), RoutingError> {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => File::open(path).map(|_| ()).map_err(|error| {
+            RoutingError::File(format!("'{}' is not readable ({})", path.display(), error))
+        }),
+        Ok(_) => Err(RoutingError::File(format!(
+            "'{}' is not a file",
+            path.display()
+        ))),
+        Err(_) => Err(RoutingError::File(format!(
+            "'{}' does not exist",
+            path.display()
+        ))),
+    }
+}
+
+/// Replaces a leading `~` with the home directory.
+pub fn expand_tilde(path: &Path) -> PathBuf {
+    let Ok(rest) = path.strip_prefix("~") else {
+        return path.to_path_buf();
+    };
+    match env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+        Some(home) => PathBuf::from(home).join(rest),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Artifacts in one directory sharing a basename, e.g. `germany.gr`, `germany.co`, ...
+struct ArtifactSet {
+    dir: PathBuf,
+    basename: OsString,
+}
+
+impl ArtifactSet {
+    fn path(&self, extension: &str) -> PathBuf {
+        let mut file_name = self.basename.clone();
+        file_name.push(".");
+        file_name.push(extension);
+        self.dir.join(file_name)
+    }
+}
+
+fn find_artifact_set(dir: &Path, extensions: &[&str]) -> Result<ArtifactSet, RoutingError> {
+    let entries = fs::read_dir(dir).map_err(|error| {
+        RoutingError::File(format!("cannot read '{}' ({})", dir.display(), error))
+    })?;
+
+    // basename -> (found extensions, newest modification time)
+    let mut basenames: BTreeMap<OsString, (Vec<String>, SystemTime)> = BTreeMap::new();
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        let (Some(basename), Some(extension)) = (path.file_stem(), path.extension()) else {
+            continue;
+        };
+        let Some(extension) = extension.to_str().filter(|e| extensions.contains(e)) else {
+            continue;
+        };
+        let Ok(modified) = fs::metadata(&path).and_then(|metadata| metadata.modified()) else {
+            continue;
+        };
+        let entry = basenames
+            .entry(basename.to_os_string())
+            .or_insert((Vec::new(), SystemTime::UNIX_EPOCH));
+        entry.0.push(extension.to_string());
+        entry.1 = entry.1.max(modified);
+    }
+
+    basenames
+        .into_iter()
+        .filter(|(_, (found, _))| extensions.iter().all(|e| found.iter().any(|f| f == e)))
+        .max_by_key(|(_, (_, modified))| *modified)
+        .map(|(basename, _)| ArtifactSet {
+            dir: dir.to_path_buf(),
+            basename,
+        })
+        .ok_or_else(|| {
+            RoutingError::File(format!(
+                "no basename in '{}' has all of .{}",
+                dir.display(),
+                extensions.join(", .")
+            ))
+        })
+}
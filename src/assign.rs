@@ -0,0 +1,219 @@
+use std::collections::VecDeque;
+
+use serde::Deserialize;
+
+use crate::{error::RoutingError, geo::CoordinateOrder};
+
+/// Body of POST /assign.
+#[derive(Deserialize)]
+pub struct AssignRequest {
+    pub sources: Vec<Supply>,
+    pub sinks: Vec<Demand>,
+    #[serde(default)]
+    pub coordinate_order: Option<CoordinateOrder>,
+}
+
+#[derive(Deserialize)]
+pub struct Supply {
+    pub coordinate: (f64, f64),
+    pub capacity: u32,
+}
+
+#[derive(Deserialize)]
+pub struct Demand {
+    pub coordinate: (f64, f64),
+    pub demand: u32,
+}
+
+/// Amount shipped from one source to one sink.
+pub struct Pairing {
+    pub source: usize,
+    pub sink: usize,
+    pub amount: u32,
+}
+
+struct ResidualArc {
+    to: usize,
+    capacity: u64,
+    cost: i64,
+}
+
+/// Residual graph with every arc followed by its reverse, so `arc ^ 1` is the partner.
+struct FlowNetwork {
+    arcs: Vec<ResidualArc>,
+    out_arcs: Vec<Vec<usize>>,
+}
+
+impl FlowNetwork {
+    fn new(number_of_nodes: usize) -> FlowNetwork {
+        FlowNetwork {
+            arcs: Vec::new(),
+            out_arcs: vec![Vec::new(); number_of_nodes],
+        }
+    }
+
+    fn add_arc(&mut self, from: usize, to: usize, capacity: u64, cost: i64) -> usize {
+        let id = self.arcs.len();
+        self.arcs.push(ResidualArc { to, capacity, cost });
+        self.out_arcs[from].push(id);
+        self.arcs.push(ResidualArc {
+            to: from,
+            capacity: 0,
+            cost: -cost,
+        });
+        self.out_arcs[to].push(id + 1);
+        id
+    }
+
+    /// Cheapest path with residual capacity as arcs into every node (SPFA, as reverse arcs
+    /// have negative costs).
+    fn cheapest_paths(&self, source: usize) -> Vec<Option<usize>> {
+        let mut costs = vec![i64::MAX; self.out_arcs.len()];
+        let mut predecessors = vec![None; self.out_arcs.len()];
+        let mut queued = vec![false; self.out_arcs.len()];
+        let mut queue = VecDeque::from([source]);
+        costs[source] = 0;
+        while let Some(node) = queue.pop_front() {
+            queued[node] = false;
+            for &id in self.out_arcs[node].iter() {
+                let arc = &self.arcs[id];
+                let cost = costs[node] + arc.cost;
+                if arc.capacity > 0 && cost < costs[arc.to] {
+                    costs[arc.to] = cost;
+                    predecessors[arc.to] = Some(id);
+                    if !queued[arc.to] {
+                        queued[arc.to] = true;
+                        queue.push_back(arc.to);
+                    }
+                }
+            }
+        }
+        predecessors
+    }
+}
+
+/// Transportation problem as min-cost flow with successive shortest paths. `weights[i][j]` is
+/// the cost per unit from source `i` to sink `j`, `None` if there is no path. Fails if the
+/// demands cannot be met.
+pub fn assign(
+    capacities: &[u32],
+    demands: &[u32],
+    weights: &[Vec<Option<u32>>],
+) -> Result<Vec<Pairing>, RoutingError> {
+    let total_demand: u64 = demands.iter().map(|&demand| demand as u64).sum();
+    let total_capacity: u64 = capacities.iter().map(|&capacity| capacity as u64).sum();
+    if total_capacity < total_demand {
+        return Err(RoutingError::Unassignable(format!(
+            "total capacity {} is below total demand {}",
+            total_capacity, total_demand
+        )));
+    }
+
+    // super source, sources, sinks, super sink
+    let (super_source, super_sink) = (0, capacities.len() + demands.len() + 1);
+    let mut network = FlowNetwork::new(super_sink + 1);
+    for (i, &capacity) in capacities.iter().enumerate() {
+        network.add_arc(super_source, 1 + i, capacity as u64, 0);
+    }
+    let mut pairings = Vec::new();
+    for (i, row) in weights.iter().enumerate() {
+        for (j, weight) in row.iter().enumerate() {
+            if let Some(weight) = weight {
+                let id = network.add_arc(
+                    1 + i,
+                    1 + capacities.len() + j,
+                    total_demand,
+                    *weight as i64,
+                );
+                pairings.push((i, j, id));
+            }
+        }
+    }
+    for (j, &demand) in demands.iter().enumerate() {
+        network.add_arc(1 + capacities.len() + j, super_sink, demand as u64, 0);
+    }
+
+    let mut flow = 0;
+    while flow < total_demand {
+        let predecessors = network.cheapest_paths(super_source);
+        if predecessors[super_sink].is_none() {
+            return Err(RoutingError::Unassignable(format!(
+                "only {} of the demand of {} can be reached",
+                flow, total_demand
+            )));
+        }
+        let mut path = Vec::new();
+        let mut node = super_sink;
+        while let Some(id) = predecessors[node] {
+            path.push(id);
+            node = network.arcs[id ^ 1].to;
+        }
+        let amount = path
+            .iter()
+            .map(|&id| network.arcs[id].capacity)
+            .min()
+            .unwrap();
+        for &id in path.iter() {
+            network.arcs[id].capacity -= amount;
+            network.arcs[id ^ 1].capacity += amount;
+        }
+        flow += amount;
+    }
+
+    Ok(pairings
+        .into_iter()
+        .filter_map(|(source, sink, id)| {
+            // the flow on an arc is the capacity of its reverse
+            let amount = network.arcs[id ^ 1].capacity as u32;
+            (amount > 0).then_some(Pairing {
+                source,
+                sink,
+                amount,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amounts(pairings: &[Pairing]) -> Vec<(usize, usize, u32)> {
+        pairings
+            .iter()
+            .map(|pairing| (pairing.source, pairing.sink, pairing.amount))
+            .collect()
+    }
+
+    #[test]
+    fn ships_at_the_lowest_cost() {
+        let weights = vec![vec![Some(1), Some(4)], vec![Some(3), Some(1)]];
+        let pairings = assign(&[3, 2], &[2, 3], &weights).unwrap();
+        assert_eq!(amounts(&pairings), vec![(0, 0, 2), (0, 1, 1), (1, 1, 2)]);
+    }
+
+    #[test]
+    fn leaves_spare_capacity_unused() {
+        let weights = vec![vec![Some(5)], vec![Some(1)]];
+        let pairings = assign(&[10, 10], &[4], &weights).unwrap();
+        assert_eq!(amounts(&pairings), vec![(1, 0, 4)]);
+    }
+
+    #[test]
+    fn fails_without_enough_capacity() {
+        let weights = vec![vec![Some(1)]];
+        assert_eq!(
+            assign(&[1], &[2], &weights).err().unwrap().to_string(),
+            "total capacity 1 is below total demand 2"
+        );
+    }
+
+    #[test]
+    fn fails_if_a_sink_cannot_be_reached() {
+        let weights = vec![vec![Some(1), None]];
+        assert_eq!(
+            assign(&[5], &[2, 3], &weights).err().unwrap().to_string(),
+            "only 2 of the demand of 5 can be reached"
+        );
+    }
+}
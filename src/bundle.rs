@@ -0,0 +1,277 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    artifacts::{check_file, expand_tilde, ArtifactArgs},
+    error::RoutingError,
+    storage::{store_for, ArtifactStore, Fnv1a, LocalStore},
+};
+
+const MAGIC: &[u8; 8] = b"FAPRABN1";
+/// The entries a bundle may hold, they become part of a file name in `extract`.
+const NAMES: [&str; 5] = ["gr", "co", "ch", "hl", "index"];
+/// A manifest lists at most a handful of entries, anything longer is not one.
+const MAX_MANIFEST_LENGTH: u64 = 1 << 20;
+
+/// Layout: `MAGIC`, the manifest length as little-endian u64, the manifest as JSON, then the
+/// contents of all entries back to back in manifest order.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ManifestEntry {
+    /// Artifact extension, e.g. `gr`.
+    pub name: String,
+    pub length: u64,
+    /// FNV-1a of the contents.
+    pub hash: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct BundleArgs {
+    #[command(subcommand)]
+    pub command: BundleCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BundleCommand {
+    /// Packs gr, co, ch and, if present, hl into one file
+    Create(BundleCreateArgs),
+    /// Prints the manifest of a bundle and checks its contents
+    Inspect(BundleInspectArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct BundleCreateArgs {
+    #[command(flatten)]
+    pub artifacts: ArtifactArgs,
+    /// Path of the bundle
+    #[arg(short, long)]
+    pub out_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct BundleInspectArgs {
+    /// Path of the bundle
+    pub bundle_path: PathBuf,
+}
+
+impl BundleArgs {
+    pub fn validate(&self) -> Vec<String> {
+        match &self.command {
+            BundleCommand::Create(args) => args.artifacts.validate(),
+            BundleCommand::Inspect(args) => check_file(&expand_tilde(&args.bundle_path))
+                .err()
+                .map(|error| vec![format!("bundle path: {}", error)])
+                .unwrap_or_default(),
+        }
+    }
+}
+
+pub fn bundle(args: &BundleArgs) {
+    match &args.command {
+        BundleCommand::Create(args) => create(args),
+        BundleCommand::Inspect(args) => {
+            let path = expand_tilde(&args.bundle_path);
+            let (manifest, _) = read_manifest(&path).unwrap();
+            for entry in manifest.entries.iter() {
+                println!(
+                    "{:<3} {:>14} bytes  fnv1a {:016x}",
+                    entry.name, entry.length, entry.hash
+                );
+            }
+            match verify(&path) {
+                Ok(()) => println!("contents match the manifest"),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+fn create(args: &BundleCreateArgs) {
+    let paths = args.artifacts.resolve_or_exit();
+    let mut files = vec![
+        ("gr", paths.gr_path.clone()),
+        ("co", paths.co_path.clone()),
+        ("ch", paths.ch_path.clone()),
+    ];
+    if let Some(hl_path) = &paths.hl_path {
+        files.push(("hl", hl_path.clone()));
+    }
+
+    let mut entries = Vec::new();
+    for (name, path) in files.iter() {
+        let (length, hash) = hash_contents(&mut LocalStore.open(path).unwrap()).unwrap();
+        println!("{} {} ({} bytes)", name, path.display(), length);
+        entries.push(ManifestEntry {
+            name: name.to_string(),
+            length,
+            hash,
+        });
+    }
+    let manifest = serde_json::to_vec(&Manifest { entries }).unwrap();
+
+    let out_path = expand_tilde(&args.out_path);
+    let mut writer = store_for(&out_path).create(&out_path).unwrap();
+    writer.write_all(MAGIC).unwrap();
+    writer
+        .write_all(&(manifest.len() as u64).to_le_bytes())
+        .unwrap();
+    writer.write_all(&manifest).unwrap();
+    for (_, path) in files.iter() {
+        io::copy(&mut LocalStore.open(path).unwrap(), &mut writer).unwrap();
+    }
+    writer.flush().unwrap();
+    println!("wrote {}", out_path.display());
+}
+
+/// Returns the manifest and the offset at which the contents start.
+pub fn read_manifest(path: &Path) -> Result<(Manifest, u64), RoutingError> {
+    let mut reader = store_for(path).open(path)?;
+    let mut magic = [0; 8];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|error| RoutingError::File(error.to_string()))?;
+    if &magic != MAGIC {
+        return Err(RoutingError::File(format!(
+            "'{}' is not a bundle",
+            path.display()
+        )));
+    }
+    let mut length = [0; 8];
+    reader
+        .read_exact(&mut length)
+        .map_err(|error| RoutingError::File(error.to_string()))?;
+    let length = u64::from_le_bytes(length);
+    if length > MAX_MANIFEST_LENGTH {
+        return Err(RoutingError::File(format!(
+            "'{}': manifest of {} bytes, at most {} are allowed",
+            path.display(),
+            length,
+            MAX_MANIFEST_LENGTH
+        )));
+    }
+    let mut manifest = vec![0; length as usize];
+    reader
+        .read_exact(&mut manifest)
+        .map_err(|error| RoutingError::File(error.to_string()))?;
+    let manifest =
+        serde_json::from_slice(&manifest).map_err(|error| RoutingError::File(error.to_string()))?;
+    Ok((manifest, 16 + length))
+}
+
+/// Checks every entry against its length and hash.
+pub fn verify(path: &Path) -> Result<(), RoutingError> {
+    let (manifest, offset) = read_manifest(path)?;
+    let mut reader = store_for(path).open(path)?;
+    io::copy(&mut (&mut reader).take(offset), &mut io::sink())
+        .map_err(|error| RoutingError::File(error.to_string()))?;
+    for entry in manifest.entries.iter() {
+        let (length, hash) = hash_contents(&mut (&mut reader).take(entry.length))?;
+        if length != entry.length || hash != entry.hash {
+            return Err(RoutingError::File(format!(
+                "{}: contents do not match the manifest",
+                entry.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Unpacks the bundle into `dir` as `bundle.<name>` files, checking every entry on the way. A
+/// directory already holding this manifest is reused.
+pub fn extract(path: &Path, dir: &Path) -> Result<Vec<(String, PathBuf)>, RoutingError> {
+    let (manifest, offset) = read_manifest(path)?;
+    if let Some(entry) = manifest
+        .entries
+        .iter()
+        .find(|entry| !NAMES.contains(&entry.name.as_str()))
+    {
+        return Err(RoutingError::File(format!(
+            "'{}': unknown entry {:?}, expected one of {}",
+            path.display(),
+            entry.name,
+            NAMES.join(", ")
+        )));
+    }
+    let files: Vec<(String, PathBuf)> = manifest
+        .entries
+        .iter()
+        .map(|entry| {
+            (
+                entry.name.clone(),
+                dir.join(format!("bundle.{}", entry.name)),
+            )
+        })
+        .collect();
+
+    let manifest_path = dir.join("manifest.json");
+    let extracted = fs::read(&manifest_path)
+        .ok()
+        .and_then(|manifest| serde_json::from_slice::<Manifest>(&manifest).ok());
+    if extracted.as_ref() == Some(&manifest) && files.iter().all(|(_, path)| path.is_file()) {
+        return Ok(files);
+    }
+
+    tracing::info!("extracting {} to {}", path.display(), dir.display());
+    let _ = fs::remove_file(&manifest_path);
+    let mut reader = store_for(path).open(path)?;
+    io::copy(&mut (&mut reader).take(offset), &mut io::sink())
+        .map_err(|error| RoutingError::File(error.to_string()))?;
+    for (entry, (_, file_path)) in manifest.entries.iter().zip(files.iter()) {
+        let mut writer = LocalStore.create(file_path)?;
+        let mut hasher = HashingReader {
+            reader: (&mut reader).take(entry.length),
+            hash: Fnv1a::new(),
+        };
+        let length = io::copy(&mut hasher, &mut writer)
+            .map_err(|error| RoutingError::File(error.to_string()))?;
+        writer
+            .flush()
+            .map_err(|error| RoutingError::File(error.to_string()))?;
+        if length != entry.length || hasher.hash.finish() != entry.hash {
+            return Err(RoutingError::File(format!(
+                "{}: contents do not match the manifest",
+                entry.name
+            )));
+        }
+    }
+    // written last, so a partial extraction is never taken as complete
+    fs::write(&manifest_path, serde_json::to_vec(&manifest).unwrap())
+        .map_err(|error| RoutingError::File(error.to_string()))?;
+    Ok(files)
+}
+
+struct HashingReader<R> {
+    reader: R,
+    hash: Fnv1a,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buffer)?;
+        self.hash.update(&buffer[..read]);
+        Ok(read)
+    }
+}
+
+fn hash_contents(reader: &mut impl Read) -> Result<(u64, u64), RoutingError> {
+    let mut hasher = HashingReader {
+        reader,
+        hash: Fnv1a::new(),
+    };
+    let length = io::copy(&mut hasher, &mut io::sink())
+        .map_err(|error| RoutingError::File(error.to_string()))?;
+    Ok((length, hasher.hash.finish()))
+}
@@ -0,0 +1,359 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use clap::Args;
+use faster_paths::graphs::path::ShortestPathRequest;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    artifacts::{check_file, expand_tilde, ArtifactArgs, ArtifactPaths},
+    engine::Engine,
+    error::RoutingError,
+    storage::{store_for, ArtifactStore, Fnv1a, LocalStore},
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CachedRoute {
+    pub vertices: Vec<u32>,
+    pub weight: u32,
+}
+
+struct Entries {
+    version: u64,
+    routes: HashMap<(u32, u32), CachedRoute>,
+    /// Insertion order, oldest first.
+    order: VecDeque<(u32, u32)>,
+}
+
+/// Files written to the spill directory, oldest first.
+#[derive(Default)]
+struct Spilled {
+    files: VecDeque<(PathBuf, u64)>,
+    size: u64,
+}
+
+/// Caches shortest paths per `(source, target)` for one graph version. Entries evicted from
+/// memory are written to `dir` if given, one directory per graph version, so they survive a
+/// restart on the same artifacts. Once `dir` holds more than `dir_max_size` bytes, the oldest
+/// files are deleted, whatever version they belong to.
+pub struct RouteCache {
+    capacity: usize,
+    dir: Option<PathBuf>,
+    dir_max_size: u64,
+    spilled: Mutex<Spilled>,
+    entries: Mutex<Entries>,
+    /// Routes from a warm cache file, never evicted.
+    pinned: WarmCache,
+}
+
+/// Precomputed routes for popular queries, written by `warm-cache`.
+#[derive(Default, Serialize, Deserialize)]
+pub struct WarmCache {
+    pub version: u64,
+    pub routes: HashMap<(u32, u32), CachedRoute>,
+}
+
+impl RouteCache {
+    pub fn new(
+        capacity: usize,
+        dir: Option<PathBuf>,
+        dir_max_size: u64,
+        pinned: WarmCache,
+    ) -> RouteCache {
+        let spilled = dir.as_deref().map(spilled_files).unwrap_or_default();
+        let cache = RouteCache {
+            capacity,
+            dir,
+            dir_max_size,
+            spilled: Mutex::new(spilled),
+            pinned,
+            entries: Mutex::new(Entries {
+                version: 0,
+                routes: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        };
+        // files of earlier runs count against the limit as well
+        cache.prune_spilled();
+        cache
+    }
+
+    pub fn get(&self, version: u64, source: u32, target: u32) -> Option<CachedRoute> {
+        if self.pinned.version == version {
+            if let Some(route) = self.pinned.routes.get(&(source, target)) {
+                return Some(route.clone());
+            }
+        }
+        if self.capacity == 0 {
+            return None;
+        }
+        {
+            let mut entries = self.entries.lock().unwrap();
+            entries.invalidate_unless(version);
+            if let Some(route) = entries.routes.get(&(source, target)) {
+                return Some(route.clone());
+            }
+        }
+        let path = self.spill_path(version, source, target)?;
+        if !path.is_file() {
+            return None;
+        }
+        let reader = LocalStore.open(&path).ok()?;
+        bincode::deserialize_from(reader).ok()
+    }
+
+    pub fn insert(&self, version: u64, source: u32, target: u32, route: CachedRoute) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut evicted = Vec::new();
+        {
+            let mut entries = self.entries.lock().unwrap();
+            entries.invalidate_unless(version);
+            if entries.routes.insert((source, target), route).is_none() {
+                entries.order.push_back((source, target));
+            }
+            while entries.order.len() > self.capacity {
+                let key = entries.order.pop_front().unwrap();
+                evicted.push((key, entries.routes.remove(&key).unwrap()));
+            }
+        }
+        // written without the lock, so lookups never wait for the disk
+        for ((source, target), route) in evicted {
+            if let Some(path) = self.spill_path(version, source, target) {
+                match spill(&path, &route) {
+                    Ok(size) => {
+                        let mut spilled = self.spilled.lock().unwrap();
+                        spilled.files.push_back((path, size));
+                        spilled.size += size;
+                    }
+                    Err(error) => {
+                        tracing::warn!("route cache: cannot write '{}' ({})", path.display(), error)
+                    }
+                }
+            }
+        }
+        self.prune_spilled();
+    }
+
+    /// Deletes the oldest spilled files until `dir` holds at most `dir_max_size` bytes.
+    fn prune_spilled(&self) {
+        let mut spilled = self.spilled.lock().unwrap();
+        while spilled.size > self.dir_max_size {
+            let Some((path, size)) = spilled.files.pop_front() else {
+                break;
+            };
+            spilled.size -= size;
+            let _ = fs::remove_file(&path);
+            // only succeeds once the version directory is empty
+            if let Some(version_dir) = path.parent() {
+                let _ = fs::remove_dir(version_dir);
+            }
+        }
+    }
+
+    /// Capacity and number of cached routes, for diagnostics.
+    pub fn to_json(&self) -> Value {
+        let entries = self.entries.lock().unwrap();
+        json!({
+            "capacity": self.capacity,
+            "entries": entries.routes.len(),
+            "pinned": self.pinned.routes.len(),
+            "spill_dir": self.dir,
+        })
+    }
+
+    fn spill_path(&self, version: u64, source: u32, target: u32) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        Some(
+            dir.join(format!("{:016x}", version))
+                .join(format!("{}-{}.bin", source, target)),
+        )
+    }
+}
+
+impl Entries {
+    /// Drops everything cached for another graph version, e.g. after a reload.
+    fn invalidate_unless(&mut self, version: u64) {
+        if self.version != version {
+            self.version = version;
+            self.routes.clear();
+            self.order.clear();
+        }
+    }
+}
+
+/// Writes `route` to `path` and returns the size of the file.
+fn spill(path: &Path, route: &CachedRoute) -> Result<u64, RoutingError> {
+    let mut writer = LocalStore.create(path)?;
+    bincode::serialize_into(&mut writer, route)
+        .map_err(|error| RoutingError::File(error.to_string()))?;
+    writer
+        .flush()
+        .map_err(|error| RoutingError::File(error.to_string()))?;
+    drop(writer);
+    fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .map_err(|error| RoutingError::File(error.to_string()))
+}
+
+/// The route files in the version directories of `dir`, oldest first.
+fn spilled_files(dir: &Path) -> Spilled {
+    let mut files = Vec::new();
+    let version_dirs = fs::read_dir(dir).into_iter().flatten().flatten();
+    for version_dir in version_dirs {
+        for entry in fs::read_dir(version_dir.path())
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_file() {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                files.push((modified, entry.path(), metadata.len()));
+            }
+        }
+    }
+    files.sort();
+    Spilled {
+        size: files.iter().map(|(_, _, size)| size).sum(),
+        files: files
+            .into_iter()
+            .map(|(_, path, size)| (path, size))
+            .collect(),
+    }
+}
+
+/// Identifies a set of artifacts by their canonical paths, sizes and modification times. This
+/// is stable across runs, so spilled entries can be reused, and the same files reached by
+/// another path, e.g. through a symlink or with `..`, get the same version.
+pub fn graph_version(paths: &ArtifactPaths) -> u64 {
+    let mut hash = Fnv1a::new();
+    let artifacts = [
+        Some(&paths.gr_path),
+        Some(&paths.co_path),
+        Some(&paths.ch_path),
+        paths.hl_path.as_ref(),
+    ];
+    for path in artifacts.into_iter().flatten() {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+        hash.update(path.to_string_lossy().as_bytes());
+        if let Ok(metadata) = fs::metadata(&path) {
+            hash.update(&metadata.len().to_le_bytes());
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|modified| modified.as_nanos())
+                .unwrap_or(0);
+            hash.update(&modified.to_le_bytes());
+        }
+    }
+    hash.finish()
+}
+
+#[derive(Args, Debug)]
+pub struct WarmCacheArgs {
+    #[command(flatten)]
+    pub artifacts: ArtifactArgs,
+    /// Path of query file, one `from_lon,from_lat,to_lon,to_lat` per line
+    #[arg(short, long)]
+    pub queries_path: PathBuf,
+    /// Path of the cache file, loaded by `serve --warm-cache`
+    #[arg(short, long)]
+    pub out_path: PathBuf,
+}
+
+impl WarmCacheArgs {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = self.artifacts.validate();
+        if let Err(error) = check_file(&expand_tilde(&self.queries_path)) {
+            errors.push(format!("--queries-path: {}", error));
+        }
+        errors
+    }
+}
+
+/// Routes every query and writes the results for the graph version of the loaded artifacts.
+pub fn warm_cache(args: &WarmCacheArgs) {
+    let engine = Engine::load_or_exit(&args.artifacts.resolve_or_exit());
+    let path_finder = engine.hl.as_ref().unwrap_or(&engine.ch);
+
+    let mut warm_cache = WarmCache {
+        version: engine.version,
+        routes: HashMap::new(),
+    };
+    let queries = BufReader::new(File::open(expand_tilde(&args.queries_path)).unwrap());
+    let mut skipped = 0;
+    for (number, line) in queries.lines().enumerate() {
+        let line = line.unwrap();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let values: Result<Vec<f64>, _> = line
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect();
+        let values = match values {
+            Ok(values) if values.len() == 4 && values.iter().all(|value| value.is_finite()) => {
+                values
+            }
+            _ => {
+                println!(
+                    "warm cache: line {} is not from_lon,from_lat,to_lon,to_lat, skipped",
+                    number + 1
+                );
+                skipped += 1;
+                continue;
+            }
+        };
+        let from = engine.snapper.nearest((values[0], values[1]));
+        let to = engine.snapper.nearest((values[2], values[3]));
+
+        let request = ShortestPathRequest::new(from, to).unwrap();
+        match path_finder.get_shortest_path(&request) {
+            Some(path) => {
+                let route = CachedRoute {
+                    vertices: path.vertices,
+                    weight: path.weight,
+                };
+                warm_cache.routes.insert((from, to), route);
+            }
+            None => println!("warm cache: {:>7} -> {:>7}, no path, skipped", from, to),
+        }
+    }
+
+    let out_path = expand_tilde(&args.out_path);
+    let writer = store_for(&out_path).create(&out_path).unwrap();
+    bincode::serialize_into(writer, &warm_cache).unwrap();
+    println!(
+        "wrote {} routes, skipped {} malformed lines",
+        warm_cache.routes.len(),
+        skipped
+    );
+}
+
+/// Reads a warm cache file. One written for other artifacts is ignored, as its routes would be
+/// wrong.
+pub fn load_warm_cache(path: &Path, version: u64) -> WarmCache {
+    let reader = store_for(path).open(path).unwrap();
+    let warm_cache: WarmCache = bincode::deserialize_from(reader).unwrap();
+    if warm_cache.version != version {
+        tracing::warn!(
+            "warm cache '{}' was written for other artifacts, ignoring it",
+            path.display()
+        );
+        return WarmCache::default();
+    }
+    tracing::info!("loaded {} warm cache routes", warm_cache.routes.len());
+    warm_cache
+}
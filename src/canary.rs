@@ -0,0 +1,98 @@
+use std::{
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::storage::Fnv1a;
+
+/// Path finder answering a request, also selectable per request as `algorithm=ch|hl`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Arm {
+    Ch,
+    Hl,
+}
+
+#[derive(Default)]
+struct ArmStats {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    total_micros: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+impl ArmStats {
+    fn to_json(&self) -> Value {
+        let requests = self.requests.load(Ordering::Relaxed);
+        let total_micros = self.total_micros.load(Ordering::Relaxed);
+        json!({
+            "requests": requests,
+            "errors": self.errors.load(Ordering::Relaxed),
+            "mean_micros": total_micros.checked_div(requests).unwrap_or(0),
+            "max_micros": self.max_micros.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Serves a percentage of requests with HL and the rest with CH. The arm is chosen by hashing
+/// the snapped endpoints, so a repeated request always lands on the same arm, also across
+/// restarts and builds.
+pub struct Canary {
+    hl_percentage: AtomicU8,
+    ch: ArmStats,
+    hl: ArmStats,
+}
+
+impl Canary {
+    pub fn new(hl_percentage: u8) -> Canary {
+        Canary {
+            hl_percentage: AtomicU8::new(hl_percentage),
+            ch: ArmStats::default(),
+            hl: ArmStats::default(),
+        }
+    }
+
+    pub fn arm(&self, from: u32, to: u32) -> Arm {
+        let mut hasher = Fnv1a::new();
+        hasher.update(&from.to_le_bytes());
+        hasher.update(&to.to_le_bytes());
+        if hasher.finish() % 100 < self.hl_percentage() as u64 {
+            Arm::Hl
+        } else {
+            Arm::Ch
+        }
+    }
+
+    pub fn hl_percentage(&self) -> u8 {
+        self.hl_percentage.load(Ordering::Relaxed)
+    }
+
+    pub fn set_hl_percentage(&self, hl_percentage: u8) {
+        self.hl_percentage.store(hl_percentage, Ordering::Relaxed);
+    }
+
+    pub fn record(&self, arm: Arm, time: Duration, success: bool) {
+        let stats = match arm {
+            Arm::Ch => &self.ch,
+            Arm::Hl => &self.hl,
+        };
+        let micros = time.as_micros() as u64;
+        stats.requests.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            stats.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        stats.total_micros.fetch_add(micros, Ordering::Relaxed);
+        stats.max_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "hl_percentage": self.hl_percentage(),
+            "ch": self.ch.to_json(),
+            "hl": self.hl.to_json(),
+        })
+    }
+}
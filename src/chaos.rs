@@ -0,0 +1,44 @@
+use std::{thread, time::Duration};
+
+/// Faults injected into request handling, so clients can test their retries and error
+/// handling against a real server. Every fault is drawn independently per request.
+pub struct Chaos {
+    pub latency: Duration,
+    pub latency_rate: f64,
+    pub snap_failure_rate: f64,
+    pub algorithm_error_rate: f64,
+}
+
+impl Chaos {
+    pub fn is_enabled(&self) -> bool {
+        self.latency_rate > 0.0 || self.snap_failure_rate > 0.0 || self.algorithm_error_rate > 0.0
+    }
+
+    /// Sleeps for `latency` at `latency_rate`.
+    pub fn delay(&self) {
+        if hit(self.latency_rate) {
+            tracing::warn!(latency_ms = self.latency.as_millis() as u64, "chaos: delay");
+            thread::sleep(self.latency);
+        }
+    }
+
+    pub fn snap_fails(&self) -> bool {
+        let fails = hit(self.snap_failure_rate);
+        if fails {
+            tracing::warn!("chaos: snap failure");
+        }
+        fails
+    }
+
+    pub fn algorithm_fails(&self) -> bool {
+        let fails = hit(self.algorithm_error_rate);
+        if fails {
+            tracing::warn!("chaos: algorithm error");
+        }
+        fails
+    }
+}
+
+fn hit(rate: f64) -> bool {
+    rate > 0.0 && rand::random::<f64>() < rate
+}
@@ -0,0 +1,232 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::PathBuf,
+};
+
+use clap::{Args, ValueEnum};
+use faster_paths::graphs::path::ShortestPathRequest;
+use serde_json::{json, Value};
+
+use crate::{
+    artifacts::{check_file, expand_tilde, ArtifactArgs},
+    engine::Engine,
+    geo::{hausdorff_distance, path_length, vertex_coordinates},
+    polyline,
+};
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Provider {
+    Osrm,
+    Graphhopper,
+    Valhalla,
+}
+
+#[derive(Args, Debug)]
+pub struct CompareExternalArgs {
+    #[command(flatten)]
+    pub artifacts: ArtifactArgs,
+    /// Path of query file, one `from_lon,from_lat,to_lon,to_lat` per line
+    #[arg(short, long)]
+    pub queries_path: PathBuf,
+    /// Path of the .csv output
+    #[arg(short, long)]
+    pub out_path: PathBuf,
+    /// Kind of external routing service
+    #[arg(long, value_enum)]
+    pub provider: Provider,
+    /// Base URL of the external routing service, e.g. http://localhost:5000
+    #[arg(long)]
+    pub endpoint: String,
+    /// Profile or costing name passed to the external service
+    #[arg(long, default_value = "car")]
+    pub profile: String,
+}
+
+impl CompareExternalArgs {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = self.artifacts.validate();
+        if let Err(error) = check_file(&expand_tilde(&self.queries_path)) {
+            errors.push(format!("--queries-path: {}", error));
+        }
+        if !self.endpoint.starts_with("http://") && !self.endpoint.starts_with("https://") {
+            errors.push(format!(
+                "--endpoint: '{}' is not an http(s) URL",
+                self.endpoint
+            ));
+        }
+        errors
+    }
+}
+
+struct ExternalRoute {
+    distance: f64, // meters
+    duration: f64, // seconds
+    coordinates: Vec<(f64, f64)>,
+}
+
+/// Routes every query with CH and with the external service and writes lengths and the
+/// Hausdorff distance between both geometries as CSV.
+pub async fn compare_external(args: &CompareExternalArgs) {
+    let engine = Engine::load_or_exit(&args.artifacts.resolve_or_exit());
+    let client = reqwest::Client::new();
+
+    let queries = BufReader::new(File::open(expand_tilde(&args.queries_path)).unwrap());
+    let mut writer = BufWriter::new(File::create(expand_tilde(&args.out_path)).unwrap());
+    writeln!(
+        writer,
+        "from_lon,from_lat,to_lon,to_lat,weight,length,external_length,external_duration,hausdorff,error"
+    )
+    .unwrap();
+
+    let mut length_differences = Vec::new();
+    for line in queries.lines() {
+        let line = line.unwrap();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let values: Vec<f64> = line
+            .split(',')
+            .map(|value| value.trim().parse().unwrap())
+            .collect();
+        let (from, to) = ((values[0], values[1]), (values[2], values[3]));
+
+        let request =
+            ShortestPathRequest::new(engine.snapper.nearest(from), engine.snapper.nearest(to))
+                .unwrap();
+        let Some(path) = engine.ch.get_shortest_path(&request) else {
+            writeln!(
+                writer,
+                "{},{},{},{},,,,,,no path",
+                from.0, from.1, to.0, to.1
+            )
+            .unwrap();
+            continue;
+        };
+        let coordinates = vertex_coordinates(&engine.fmi, &path.vertices);
+        let length = path_length(engine.distance_model, &coordinates);
+
+        match external_route(&client, args, from, to).await {
+            Ok(external) => {
+                let hausdorff =
+                    hausdorff_distance(engine.distance_model, &coordinates, &external.coordinates);
+                length_differences.push((length - external.distance) / external.distance);
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{:.1},{:.1},{:.1},{:.1},",
+                    from.0,
+                    from.1,
+                    to.0,
+                    to.1,
+                    path.weight,
+                    length,
+                    external.distance,
+                    external.duration,
+                    hausdorff
+                )
+                .unwrap();
+            }
+            Err(error) => {
+                println!("external query {} failed: {}", line, error);
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{:.1},,,,{}",
+                    from.0,
+                    from.1,
+                    to.0,
+                    to.1,
+                    path.weight,
+                    length,
+                    error.replace(',', ";")
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    if !length_differences.is_empty() {
+        let mean = length_differences.iter().sum::<f64>() / length_differences.len() as f64;
+        println!(
+            "compared {} routes, mean length difference: {:+.2}%",
+            length_differences.len(),
+            mean * 100.0
+        );
+    }
+}
+
+async fn external_route(
+    client: &reqwest::Client,
+    args: &CompareExternalArgs,
+    from: (f64, f64),
+    to: (f64, f64),
+) -> Result<ExternalRoute, String> {
+    let endpoint = args.endpoint.trim_end_matches('/');
+    let request = match args.provider {
+        Provider::Osrm => client.get(format!(
+            "{}/route/v1/{}/{},{};{},{}?overview=full&geometries=geojson",
+            endpoint, args.profile, from.0, from.1, to.0, to.1
+        )),
+        Provider::Graphhopper => client.get(format!(
+            "{}/route?point={},{}&point={},{}&profile={}&points_encoded=false",
+            endpoint, from.1, from.0, to.1, to.0, args.profile
+        )),
+        Provider::Valhalla => client.post(format!("{}/route", endpoint)).json(&json!({
+            "locations": [{ "lon": from.0, "lat": from.1 }, { "lon": to.0, "lat": to.1 }],
+            "costing": args.profile,
+        })),
+    };
+    let response: Value = request
+        .send()
+        .await
+        .map_err(|error| error.to_string())?
+        .json()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    let number = |value: &Value| value.as_f64().ok_or("missing number in response");
+    match args.provider {
+        Provider::Osrm => {
+            let route = &response["routes"][0];
+            Ok(ExternalRoute {
+                distance: number(&route["distance"])?,
+                duration: number(&route["duration"])?,
+                coordinates: geojson_coordinates(&route["geometry"]["coordinates"])?,
+            })
+        }
+        Provider::Graphhopper => {
+            let path = &response["paths"][0];
+            Ok(ExternalRoute {
+                distance: number(&path["distance"])?,
+                duration: number(&path["time"])? / 1000.0,
+                coordinates: geojson_coordinates(&path["points"]["coordinates"])?,
+            })
+        }
+        Provider::Valhalla => {
+            let trip = &response["trip"];
+            let mut coordinates = Vec::new();
+            for leg in trip["legs"].as_array().ok_or("missing legs in response")? {
+                let shape = leg["shape"].as_str().ok_or("missing shape in response")?;
+                coordinates.extend(polyline::decode(shape, 6).map_err(|error| error.to_string())?);
+            }
+            Ok(ExternalRoute {
+                distance: number(&trip["summary"]["length"])? * 1000.0,
+                duration: number(&trip["summary"]["time"])?,
+                coordinates,
+            })
+        }
+    }
+}
+
+fn geojson_coordinates(value: &Value) -> Result<Vec<(f64, f64)>, String> {
+    value
+        .as_array()
+        .ok_or("missing coordinates in response")?
+        .iter()
+        .map(
+            |coordinate| match (coordinate[0].as_f64(), coordinate[1].as_f64()) {
+                (Some(lon), Some(lat)) => Ok((lon, lat)),
+                _ => Err("invalid coordinate in response".to_string()),
+            },
+        )
+        .collect()
+}
@@ -0,0 +1,80 @@
+use std::{ffi::OsString, fs, path::Path};
+
+use crate::artifacts::expand_tilde;
+
+/// Inserts the settings of the TOML file given with `--config` as flags right after the
+/// subcommand. A key `route_cache_size = 100` becomes `--route-cache-size 100`, `true`
+/// becomes a bare flag and arrays repeat the flag. As later flags override earlier ones,
+/// everything passed on the command line wins over the file.
+pub fn args_with_config(args: Vec<OsString>) -> Result<Vec<OsString>, String> {
+    let Some(path) = config_path(&args) else {
+        return Ok(args);
+    };
+    let path = expand_tilde(Path::new(&path));
+    let content = fs::read_to_string(&path)
+        .map_err(|error| format!("--config: cannot read '{}' ({})", path.display(), error))?;
+    let table: toml::Table = content.parse().map_err(|error| {
+        format!(
+            "--config: '{}' is not valid TOML ({})",
+            path.display(),
+            error
+        )
+    })?;
+
+    let mut flags = Vec::new();
+    for (key, value) in table {
+        let flag = format!("--{}", key.replace('_', "-"));
+        let values = match value {
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                toml::Value::Boolean(true) => flags.push(OsString::from(&flag)),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(value) => flags.extend([(&flag).into(), value.into()]),
+                toml::Value::Integer(value) => {
+                    flags.extend([(&flag).into(), value.to_string().into()])
+                }
+                toml::Value::Float(value) => {
+                    flags.extend([(&flag).into(), value.to_string().into()])
+                }
+                _ => return Err(format!("--config: '{}' is not a plain value", key)),
+            }
+        }
+    }
+
+    let position = subcommand_position(&args).map_or(args.len(), |position| position + 1);
+    let mut args = args;
+    args.splice(position..position, flags);
+    Ok(args)
+}
+
+/// Index of the first argument that is neither a flag nor the value of `--config`.
+fn subcommand_position(args: &[OsString]) -> Option<usize> {
+    let mut skip_value = false;
+    for (position, arg) in args.iter().enumerate().skip(1) {
+        if std::mem::take(&mut skip_value) {
+            continue;
+        }
+        if arg == "--config" {
+            skip_value = true;
+        } else if !arg.to_string_lossy().starts_with('-') {
+            return Some(position);
+        }
+    }
+    None
+}
+
+fn config_path(args: &[OsString]) -> Option<OsString> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().cloned();
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(path.into());
+        }
+    }
+    None
+}
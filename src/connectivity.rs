@@ -0,0 +1,181 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use clap::Args;
+use osm_converter::sphere::graph::graph::Fmi;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    artifacts::{check_file, expand_tilde},
+    geo::{haversine_distance, lon_lat},
+    geojson::{feature_collection, linestring_feature, round},
+    graph::{Graph, Sanitation},
+};
+
+/// Meters per degree of latitude.
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+#[derive(Args, Debug)]
+pub struct ConnectivityArgs {
+    /// Path of the .gr file
+    #[arg(long)]
+    pub gr_path: PathBuf,
+    /// Path of the .co file
+    #[arg(long)]
+    pub co_path: PathBuf,
+    /// Path of the GeoJSON output with one suggested connector per line feature
+    #[arg(short, long)]
+    pub out_path: PathBuf,
+    /// Meters. Components closer than this to another component get a suggestion
+    #[arg(long, default_value_t = 50.0)]
+    pub max_distance: f64,
+}
+
+impl ConnectivityArgs {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (flag, path) in [("--gr-path", &self.gr_path), ("--co-path", &self.co_path)] {
+            if let Err(error) = check_file(&expand_tilde(path)) {
+                errors.push(format!("{}: {}", flag, error));
+            }
+        }
+        if self.max_distance.is_nan() || self.max_distance <= 0.0 {
+            errors.push(format!(
+                "--max-distance: {} is not a positive number of meters",
+                self.max_distance
+            ));
+        }
+        errors
+    }
+}
+
+/// Finds the weakly connected components of the graph and, for every component but the
+/// largest, the closest vertex pair to a vertex of another component within
+/// `--max-distance`. Each pair is written as a LineString with both vertex ids, the distance
+/// and the component sizes, as a candidate edge to add in both directions.
+pub fn connectivity(args: &ConnectivityArgs) {
+    let (gr_path, co_path) = (expand_tilde(&args.gr_path), expand_tilde(&args.co_path));
+    let fmi = Fmi::from_gr_co_file(gr_path.to_str().unwrap(), co_path.to_str().unwrap());
+    let graph = Graph::from_gr_file(&gr_path, fmi.points.len(), Sanitation::Off).unwrap();
+    let coordinates: Vec<(f64, f64)> = fmi.points.iter().map(lon_lat).collect();
+
+    let components = components(&graph, coordinates.len());
+    let mut sizes: HashMap<u32, usize> = HashMap::new();
+    for &component in components.iter() {
+        *sizes.entry(component).or_default() += 1;
+    }
+    let largest = sizes
+        .iter()
+        .max_by_key(|&(&component, &size)| (size, std::cmp::Reverse(component)))
+        .map(|(&component, _)| component);
+    println!(
+        "{} vertices in {} components, the largest has {}",
+        coordinates.len(),
+        sizes.len(),
+        largest.map_or(0, |largest| sizes[&largest])
+    );
+
+    // grid of cells `max_distance` high, so a partner lies in the same or a neighboring row
+    let cell_size = args.max_distance / METERS_PER_DEGREE;
+    let cell = |(lon, lat): (f64, f64)| {
+        (
+            (lon / cell_size).floor() as i64,
+            (lat / cell_size).floor() as i64,
+        )
+    };
+    let mut grid: HashMap<(i64, i64), Vec<u32>> = HashMap::new();
+    for (vertex, &coordinate) in coordinates.iter().enumerate() {
+        grid.entry(cell(coordinate))
+            .or_default()
+            .push(vertex as u32);
+    }
+
+    // closest pair to another component for each component: (distance, from, to)
+    let mut best: HashMap<u32, (f64, u32, u32)> = HashMap::new();
+    for (vertex, &coordinate) in coordinates.iter().enumerate() {
+        let component = components[vertex];
+        if Some(component) == largest {
+            continue;
+        }
+        // cells get narrower towards the poles, so more of them cover `max_distance`
+        let lon_cells = (1.0 / coordinate.1.to_radians().cos().max(0.01)).ceil() as i64;
+        let (x, y) = cell(coordinate);
+        for dx in -lon_cells..=lon_cells {
+            for dy in -1..=1 {
+                for &other in grid.get(&(x + dx, y + dy)).into_iter().flatten() {
+                    if components[other as usize] == component {
+                        continue;
+                    }
+                    let distance = haversine_distance(coordinate, coordinates[other as usize]);
+                    let closer = !matches!(
+                        best.get(&component),
+                        Some(&(closest, _, _)) if closest <= distance
+                    );
+                    if distance <= args.max_distance && closer {
+                        best.insert(component, (distance, vertex as u32, other));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut suggestions: Vec<(u32, (f64, u32, u32))> = best.into_iter().collect();
+    suggestions.sort_by(|a, b| a.1 .0.total_cmp(&b.1 .0).then(a.0.cmp(&b.0)));
+    let features: Vec<Value> = suggestions
+        .iter()
+        .map(|&(component, (distance, from, to))| {
+            let mut properties = Map::new();
+            properties.insert("from".to_string(), from.into());
+            properties.insert("to".to_string(), to.into());
+            properties.insert("distance".to_string(), round(distance, 1).into());
+            properties.insert("component_size".to_string(), sizes[&component].into());
+            properties.insert(
+                "other_component_size".to_string(),
+                sizes[&components[to as usize]].into(),
+            );
+            linestring_feature(
+                &[coordinates[from as usize], coordinates[to as usize]],
+                properties,
+            )
+        })
+        .collect();
+    println!(
+        "{} components are within {}m of another one",
+        features.len(),
+        args.max_distance
+    );
+
+    let mut writer = BufWriter::new(File::create(expand_tilde(&args.out_path)).unwrap());
+    let mut collection = feature_collection(features);
+    collection["max_distance"] = json!(args.max_distance);
+    serde_json::to_writer(&mut writer, &collection).unwrap();
+    writer.flush().unwrap();
+}
+
+/// Component id of every vertex, ignoring edge directions.
+fn components(graph: &Graph, number_of_vertices: usize) -> Vec<u32> {
+    let mut parent: Vec<u32> = (0..number_of_vertices as u32).collect();
+    fn root(parent: &mut [u32], mut vertex: u32) -> u32 {
+        while parent[vertex as usize] != vertex {
+            parent[vertex as usize] = parent[parent[vertex as usize] as usize];
+            vertex = parent[vertex as usize];
+        }
+        vertex
+    }
+    for edge in graph.edges.iter() {
+        let (source, target) = (
+            root(&mut parent, edge.source),
+            root(&mut parent, edge.target),
+        );
+        if source != target {
+            parent[source.max(target) as usize] = source.min(target);
+        }
+    }
+    (0..number_of_vertices as u32)
+        .map(|vertex| root(&mut parent, vertex))
+        .collect()
+}
@@ -0,0 +1,115 @@
+use osm_converter::sphere::graph::graph::Fmi;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{dijkstra::shortest_path_tree, error::RoutingError, geo::lon_lat, graph::Graph};
+
+#[derive(Deserialize)]
+pub struct TreeRequest {
+    pub from: (f64, f64), // lon, lat
+    pub max_cost: Option<u32>,
+}
+
+pub fn vertex(id: u32, fmi: &Fmi, graph: &Graph) -> Result<Value, RoutingError> {
+    let Some(point) = fmi.points.get(id as usize) else {
+        return Err(RoutingError::NotFound(format!(
+            "vertex {} does not exist",
+            id
+        )));
+    };
+
+    let out_edges: Vec<Value> = graph
+        .out_edges(id)
+        .iter()
+        .map(|&edge_id| {
+            let edge = &graph.edges[edge_id as usize];
+            json!({ "id": graph.arc_id(edge_id), "target": edge.target, "weight": edge.weight })
+        })
+        .collect();
+    let in_edges: Vec<Value> = graph
+        .in_edges(id)
+        .iter()
+        .map(|&edge_id| {
+            let edge = &graph.edges[edge_id as usize];
+            json!({ "id": graph.arc_id(edge_id), "source": edge.source, "weight": edge.weight })
+        })
+        .collect();
+
+    let body = json!({
+        "id": id,
+        "coordinate": lon_lat(point),
+        "out_edges": out_edges,
+        "in_edges": in_edges,
+    });
+    Ok(body)
+}
+
+pub fn edge(id: u32, fmi: &Fmi, graph: &Graph) -> Result<Value, RoutingError> {
+    let Some(edge) = graph
+        .edge_of_arc(id)
+        .and_then(|edge| graph.edges.get(edge as usize))
+    else {
+        return Err(RoutingError::NotFound(format!(
+            "edge {} does not exist",
+            id
+        )));
+    };
+
+    let body = json!({
+        "id": id,
+        "source": edge.source,
+        "target": edge.target,
+        "weight": edge.weight,
+        "source_coordinate": lon_lat(&fmi.points[edge.source as usize]),
+        "target_coordinate": lon_lat(&fmi.points[edge.target as usize]),
+    });
+    Ok(body)
+}
+
+/// Exports the shortest path tree rooted at `source` as a GeoJSON FeatureCollection with one
+/// line per tree edge, colored from green (cheap) to red (expensive).
+pub fn tree(source: u32, max_cost: Option<u32>, fmi: &Fmi, graph: &Graph) -> Value {
+    let tree = shortest_path_tree(graph, source, max_cost);
+    let max_distance = tree
+        .distances
+        .iter()
+        .filter(|&&distance| distance != u32::MAX)
+        .max()
+        .copied()
+        .unwrap_or(0)
+        .max(1);
+
+    let features: Vec<Value> = tree
+        .predecessors
+        .iter()
+        .filter_map(|edge_id| {
+            let edge = &graph.edges[(*edge_id)? as usize];
+            let cost = tree.distances[edge.target as usize];
+            Some(json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": [
+                        lon_lat(&fmi.points[edge.source as usize]),
+                        lon_lat(&fmi.points[edge.target as usize]),
+                    ],
+                },
+                "properties": {
+                    "source": edge.source,
+                    "target": edge.target,
+                    "cost": cost,
+                    "stroke": cost_color(cost, max_distance),
+                },
+            }))
+        })
+        .collect();
+
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+fn cost_color(cost: u32, max_cost: u32) -> String {
+    let ratio = cost as f64 / max_cost as f64;
+    let red = (255.0 * ratio).round() as u8;
+    let green = (255.0 * (1.0 - ratio)).round() as u8;
+    format!("#{:02x}{:02x}00", red, green)
+}
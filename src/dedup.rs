@@ -0,0 +1,137 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::PathBuf,
+};
+
+use clap::Args;
+
+use crate::artifacts::{check_file, expand_tilde};
+
+#[derive(Args, Debug)]
+pub struct DedupArgs {
+    /// Path of the .gr file to clean
+    #[arg(long)]
+    pub gr_path: PathBuf,
+    /// Path of the .co file to clean
+    #[arg(long)]
+    pub co_path: PathBuf,
+    /// Path of the cleaned .gr file
+    #[arg(long)]
+    pub out_gr_path: PathBuf,
+    /// Path of the cleaned .co file
+    #[arg(long)]
+    pub out_co_path: PathBuf,
+}
+
+impl DedupArgs {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (flag, path) in [("--gr-path", &self.gr_path), ("--co-path", &self.co_path)] {
+            if let Err(error) = check_file(&expand_tilde(path)) {
+                errors.push(format!("{}: {}", flag, error));
+            }
+        }
+        errors
+    }
+}
+
+/// Maps every vertex to the smallest id with exactly the same coordinate.
+pub fn canonical_vertices<T: std::hash::Hash + Eq>(coordinates: &[T]) -> Vec<u32> {
+    let mut first: HashMap<&T, u32> = HashMap::new();
+    coordinates
+        .iter()
+        .enumerate()
+        .map(|(id, coordinate)| *first.entry(coordinate).or_insert(id as u32))
+        .collect()
+}
+
+/// Merges vertices with identical `v` lines in the .co file, renumbers the remaining ones
+/// without gaps and rewrites both files. Edges that become loops are dropped. CH and HL have to
+/// be built again from the output.
+pub fn dedup(args: &DedupArgs) {
+    // `v <id> <coordinate...>`, ids start at 1; the coordinate is compared textually
+    let co_lines: Vec<String> = BufReader::new(File::open(expand_tilde(&args.co_path)).unwrap())
+        .lines()
+        .map(|line| line.unwrap())
+        .collect();
+    let mut coordinates: Vec<String> = Vec::new();
+    for line in co_lines.iter() {
+        let mut values = line.split_whitespace();
+        if values.next() != Some("v") {
+            continue;
+        }
+        let id: usize = values.next().unwrap().parse().unwrap();
+        assert_eq!(
+            id,
+            coordinates.len() + 1,
+            "vertex ids in the .co file must be consecutive"
+        );
+        coordinates.push(values.collect::<Vec<_>>().join(" "));
+    }
+
+    let canonical = canonical_vertices(&coordinates);
+    // new 1-based id of every kept vertex
+    let mut new_ids = vec![0; coordinates.len()];
+    let mut number_of_vertices = 0;
+    for (id, &canonical_id) in canonical.iter().enumerate() {
+        if canonical_id == id as u32 {
+            number_of_vertices += 1;
+            new_ids[id] = number_of_vertices;
+        }
+    }
+    let new_id = |old: usize| new_ids[canonical[old - 1] as usize];
+
+    let mut edges = Vec::new();
+    let gr_reader = BufReader::new(File::open(expand_tilde(&args.gr_path)).unwrap());
+    let mut gr_comments = Vec::new();
+    for line in gr_reader.lines() {
+        let line = line.unwrap();
+        let values: Vec<&str> = line.split_whitespace().collect();
+        match values.first() {
+            Some(&"a") => {
+                let source = new_id(values[1].parse().unwrap());
+                let target = new_id(values[2].parse().unwrap());
+                if source != target {
+                    edges.push(format!("a {} {} {}", source, target, values[3..].join(" ")));
+                }
+            }
+            Some(&"c") => gr_comments.push(line.clone()),
+            _ => {}
+        }
+    }
+
+    let mut co_writer = BufWriter::new(File::create(expand_tilde(&args.out_co_path)).unwrap());
+    for line in co_lines.iter() {
+        let values: Vec<&str> = line.split_whitespace().collect();
+        match values.first() {
+            Some(&"v") => {
+                let id: usize = values[1].parse().unwrap();
+                if canonical[id - 1] as usize == id - 1 {
+                    writeln!(co_writer, "v {} {}", new_ids[id - 1], values[2..].join(" ")).unwrap();
+                }
+            }
+            Some(&"p") => {
+                let header = &values[..values.len() - 1];
+                writeln!(co_writer, "{} {}", header.join(" "), number_of_vertices).unwrap();
+            }
+            _ => writeln!(co_writer, "{}", line).unwrap(),
+        }
+    }
+
+    let mut gr_writer = BufWriter::new(File::create(expand_tilde(&args.out_gr_path)).unwrap());
+    for comment in gr_comments {
+        writeln!(gr_writer, "{}", comment).unwrap();
+    }
+    writeln!(gr_writer, "p sp {} {}", number_of_vertices, edges.len()).unwrap();
+    for edge in edges {
+        writeln!(gr_writer, "{}", edge).unwrap();
+    }
+
+    println!(
+        "merged {} duplicate vertices, {} vertices left",
+        coordinates.len() - number_of_vertices,
+        number_of_vertices
+    );
+}
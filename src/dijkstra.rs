@@ -0,0 +1,131 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::graph::Graph;
+
+pub struct ShortestPathTree {
+    pub distances: Vec<u32>,
+    /// Edge over which each vertex was reached, `None` for the source and unreached vertices.
+    pub predecessors: Vec<Option<u32>>,
+}
+
+/// One-to-all Dijkstra on the raw graph. Vertices farther away than `max_cost` are not settled.
+pub fn shortest_path_tree(graph: &Graph, source: u32, max_cost: Option<u32>) -> ShortestPathTree {
+    let max_cost = max_cost.unwrap_or(u32::MAX);
+    let mut distances = vec![u32::MAX; graph.number_of_vertices()];
+    let mut predecessors = vec![None; graph.number_of_vertices()];
+
+    let mut queue = BinaryHeap::new();
+    distances[source as usize] = 0;
+    queue.push(Reverse((0, source)));
+
+    while let Some(Reverse((distance, vertex))) = queue.pop() {
+        if distance > distances[vertex as usize] {
+            continue;
+        }
+        for &edge_id in graph.out_edges(vertex).iter() {
+            let edge = &graph.edges[edge_id as usize];
+            let alternative_distance = distance.saturating_add(edge.weight);
+            if alternative_distance <= max_cost
+                && alternative_distance < distances[edge.target as usize]
+            {
+                distances[edge.target as usize] = alternative_distance;
+                predecessors[edge.target as usize] = Some(edge_id);
+                queue.push(Reverse((alternative_distance, edge.target)));
+            }
+        }
+    }
+
+    ShortestPathTree {
+        distances,
+        predecessors,
+    }
+}
+
+/// Point-to-point Dijkstra on the raw graph, stopping once `target` is settled.
+pub fn shortest_path_weight(graph: &Graph, source: u32, target: u32) -> Option<u32> {
+    let mut distances = vec![u32::MAX; graph.number_of_vertices()];
+
+    let mut queue = BinaryHeap::new();
+    distances[source as usize] = 0;
+    queue.push(Reverse((0, source)));
+
+    while let Some(Reverse((distance, vertex))) = queue.pop() {
+        if vertex == target {
+            return Some(distance);
+        }
+        if distance > distances[vertex as usize] {
+            continue;
+        }
+        for &edge_id in graph.out_edges(vertex).iter() {
+            let edge = &graph.edges[edge_id as usize];
+            let alternative_distance = distance.saturating_add(edge.weight);
+            if alternative_distance < distances[edge.target as usize] {
+                distances[edge.target as usize] = alternative_distance;
+                queue.push(Reverse((alternative_distance, edge.target)));
+            }
+        }
+    }
+
+    None
+}
+
+/// Backward one-to-all Dijkstra, the distance of every vertex to `target`.
+pub fn distances_to(graph: &Graph, target: u32) -> Vec<u32> {
+    let mut distances = vec![u32::MAX; graph.number_of_vertices()];
+    let mut queue = BinaryHeap::new();
+    distances[target as usize] = 0;
+    queue.push(Reverse((0, target)));
+
+    while let Some(Reverse((distance, vertex))) = queue.pop() {
+        if distance > distances[vertex as usize] {
+            continue;
+        }
+        for &edge_id in graph.in_edges(vertex).iter() {
+            let edge = &graph.edges[edge_id as usize];
+            let alternative_distance = distance.saturating_add(edge.weight);
+            if alternative_distance < distances[edge.source as usize] {
+                distances[edge.source as usize] = alternative_distance;
+                queue.push(Reverse((alternative_distance, edge.source)));
+            }
+        }
+    }
+
+    distances
+}
+
+/// Distances from the nearest of `vertices` (forward) and to the nearest of them (backward),
+/// both bounded by `max_cost`, `u32::MAX` beyond it.
+pub fn distances_around(graph: &Graph, vertices: &[u32], max_cost: u32) -> (Vec<u32>, Vec<u32>) {
+    let search = |forward: bool| {
+        let mut distances = vec![u32::MAX; graph.number_of_vertices()];
+        let mut queue = BinaryHeap::new();
+        for &vertex in vertices {
+            distances[vertex as usize] = 0;
+            queue.push(Reverse((0, vertex)));
+        }
+
+        while let Some(Reverse((distance, vertex))) = queue.pop() {
+            if distance > distances[vertex as usize] {
+                continue;
+            }
+            let edges = if forward {
+                graph.out_edges(vertex)
+            } else {
+                graph.in_edges(vertex)
+            };
+            for &edge_id in edges.iter() {
+                let edge = &graph.edges[edge_id as usize];
+                let next = if forward { edge.target } else { edge.source };
+                let alternative_distance = distance.saturating_add(edge.weight);
+                if alternative_distance <= max_cost
+                    && alternative_distance < distances[next as usize]
+                {
+                    distances[next as usize] = alternative_distance;
+                    queue.push(Reverse((alternative_distance, next)));
+                }
+            }
+        }
+        distances
+    };
+    (search(true), search(false))
+}
@@ -0,0 +1,50 @@
+use serde::Deserialize;
+
+use crate::geo::DistanceModel;
+
+/// Query parameters of GET /debug/drive.
+#[derive(Deserialize)]
+pub struct DriveQuery {
+    pub from: u32,
+    pub to: u32,
+    /// Meters per second, 50 km/h by default.
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+    /// Milliseconds between two positions.
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+}
+
+fn default_speed() -> f64 {
+    13.9
+}
+
+fn default_interval_ms() -> u64 {
+    1000
+}
+
+/// Positions of a vehicle driving along `coordinates`, `step` meters apart, starting at the
+/// first and ending exactly at the last coordinate.
+pub fn positions(model: DistanceModel, coordinates: &[(f64, f64)], step: f64) -> Vec<(f64, f64)> {
+    let Some(&first) = coordinates.first() else {
+        return Vec::new();
+    };
+
+    let mut positions = vec![first];
+    // distance driven past the start of the current segment
+    let mut driven = step;
+    for pair in coordinates.windows(2) {
+        let length = model.distance(pair[0], pair[1]);
+        while driven < length {
+            let t = driven / length;
+            positions.push((
+                pair[0].0 + (pair[1].0 - pair[0].0) * t,
+                pair[0].1 + (pair[1].1 - pair[0].1) * t,
+            ));
+            driven += step;
+        }
+        driven -= length;
+    }
+    positions.push(*coordinates.last().unwrap());
+    positions
+}
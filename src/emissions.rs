@@ -0,0 +1,44 @@
+use std::{collections::BTreeMap, fs::File, io::BufReader, path::Path};
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::error::RoutingError;
+
+/// CO2 emitted per kilometer for each vehicle type, read from a JSON object like
+/// `{"car": 120.0, "van": 180.0, "truck": 900.0}`. The artifacts have no road classes or
+/// speeds, so the factor only depends on the vehicle.
+#[derive(Deserialize)]
+pub struct EmissionsModel {
+    #[serde(flatten)]
+    grams_per_km: BTreeMap<String, f64>,
+}
+
+impl EmissionsModel {
+    pub fn from_json_file(path: &Path) -> Result<EmissionsModel, RoutingError> {
+        let file = File::open(path).map_err(|error| RoutingError::File(error.to_string()))?;
+        let model: EmissionsModel = serde_json::from_reader(BufReader::new(file))
+            .map_err(|error| RoutingError::File(error.to_string()))?;
+        if let Some((vehicle, _)) = model
+            .grams_per_km
+            .iter()
+            .find(|(_, grams)| !grams.is_finite() || **grams < 0.0)
+        {
+            return Err(RoutingError::File(format!(
+                "'{}' needs a non-negative factor",
+                vehicle
+            )));
+        }
+        Ok(model)
+    }
+
+    /// Grams of CO2 per vehicle type for a route of `length` meters.
+    pub fn estimate(&self, length: f64) -> Value {
+        let estimates: Map<String, Value> = self
+            .grams_per_km
+            .iter()
+            .map(|(vehicle, grams)| (vehicle.clone(), (grams * length / 1000.0).round().into()))
+            .collect();
+        Value::Object(estimates)
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use faster_paths::{
+    ch::{
+        ch_path_finder::ChPathFinder,
+        shortcut_replacer::{
+            fast_shortcut_replacer::FastShortcutReplacer,
+            slow_shortcut_replacer::SlowShortcutReplacer, ShortcutReplacer,
+        },
+        ContractedGraphInformation,
+    },
+    graphs::path::{PathFinding, ShortestPathRequest},
+    hl::{hub_graph::HubGraph, hub_graph_path_finder::HubGraphPathFinder},
+};
+use osm_converter::sphere::graph::graph::Fmi;
+
+use crate::{
+    artifacts::ArtifactPaths,
+    cache::graph_version,
+    dedup::canonical_vertices,
+    error::RoutingError,
+    geo::{lon_lat, DistanceModel},
+    graph::Graph,
+    snap::Snapper,
+    storage::store_for,
+};
+
+/// Everything needed to answer queries, loaded once at startup.
+pub struct Engine {
+    /// Changes whenever the artifacts do, see `graph_version`.
+    pub version: u64,
+    pub fmi: Fmi,
+    pub graph: Graph,
+    pub distance_model: DistanceModel,
+    /// Length of every edge in meters under `distance_model`, rounded up.
+    pub edge_lengths: Vec<u32>,
+    pub snapper: Snapper,
+    pub ch: Box<dyn PathFinding>,
+    /// `None` if HL was not loaded.
+    pub hl: Option<Box<dyn PathFinding>>,
+    /// What this engine was loaded from, which differs from the flags after a reload.
+    pub paths: ArtifactPaths,
+}
+
+impl Engine {
+    /// Fails on unreadable artifacts and on a malformed .gr, .ch or .hl file. osm_converter
+    /// still panics on a malformed .co file.
+    pub fn load(paths: &ArtifactPaths) -> Result<Engine, RoutingError> {
+        tracing::info!(
+            "loading {}, {}, {}, {}",
+            paths.gr_path.display(),
+            paths.co_path.display(),
+            paths.ch_path.display(),
+            paths
+                .hl_path
+                .as_deref()
+                .map_or("no hl".into(), |path| path.display().to_string())
+        );
+        let fmi = Fmi::from_gr_co_file(utf8(&paths.gr_path)?, utf8(&paths.co_path)?);
+        let duplicates = canonical_vertices(&fmi.points)
+            .iter()
+            .enumerate()
+            .filter(|&(id, &canonical)| canonical != id as u32)
+            .count();
+        if duplicates > 0 {
+            tracing::warn!(
+                "{} vertices share their coordinate with a vertex of smaller id and are never \
+                 snapped to, see the dedup subcommand",
+                duplicates
+            );
+        }
+        let graph = Graph::from_gr_file(&paths.gr_path, fmi.points.len(), paths.sanitation)?;
+        let edge_lengths: Vec<u32> = graph
+            .edges
+            .iter()
+            .map(|edge| {
+                let source = lon_lat(&fmi.points[edge.source as usize]);
+                let target = lon_lat(&fmi.points[edge.target as usize]);
+                paths.distance_model.distance(source, target).ceil() as u32
+            })
+            .collect();
+        let snapper = Snapper::new(
+            &fmi,
+            &graph,
+            &edge_lengths,
+            paths.snap_spacing,
+            paths.spatial_partition_depth,
+        );
+
+        // ch
+        let reader = store_for(&paths.ch_path).open(&paths.ch_path)?;
+        let ch_information: ContractedGraphInformation = bincode::deserialize_from(reader)
+            .map_err(|error| {
+                RoutingError::File(format!("{}: {}", paths.ch_path.display(), error))
+            })?;
+        let shortcut_replacer: Box<dyn ShortcutReplacer + Send + Sync> =
+            Box::new(SlowShortcutReplacer::new(&ch_information.shortcuts));
+        let ch_path_finder = ChPathFinder::new(ch_information.ch_graph, shortcut_replacer);
+
+        // hl
+        let hl: Option<Box<dyn PathFinding>> = if let Some(hl_path) = &paths.hl_path {
+            let fast_shortcut_replacer: Box<dyn ShortcutReplacer + Send + Sync> =
+                Box::new(FastShortcutReplacer::new(&ch_information.shortcuts));
+            let reader = store_for(hl_path).open(hl_path)?;
+            let hl: HubGraph = bincode::deserialize_from(reader)
+                .map_err(|error| RoutingError::File(format!("{}: {}", hl_path.display(), error)))?;
+            Some(Box::new(HubGraphPathFinder::new(
+                hl,
+                fast_shortcut_replacer,
+            )))
+        } else {
+            tracing::info!("no .hl file or it exceeds --max-memory, serving with CH only");
+            None
+        };
+
+        Ok(Engine {
+            version: graph_version(paths),
+            fmi,
+            graph,
+            distance_model: paths.distance_model,
+            edge_lengths,
+            snapper,
+            ch: Box::new(ch_path_finder),
+            hl,
+            paths: paths.clone(),
+        })
+    }
+
+    /// `load` for commands that cannot run without the engine.
+    pub fn load_or_exit(paths: &ArtifactPaths) -> Engine {
+        Engine::load(paths).unwrap_or_else(|error| {
+            eprintln!("cannot load the artifacts: {}", error);
+            std::process::exit(2);
+        })
+    }
+
+    pub fn coordinate(&self, vertex: u32) -> (f64, f64) {
+        lon_lat(&self.fmi.points[vertex as usize])
+    }
+
+    /// Distance in meters between two `(lon, lat)` coordinates under `distance_model`.
+    pub fn distance(&self, from: (f64, f64), to: (f64, f64)) -> f64 {
+        self.distance_model.distance(from, to)
+    }
+}
+
+/// faster_paths has no request from a vertex to itself.
+pub fn path_request(from: u32, to: u32) -> Result<ShortestPathRequest, RoutingError> {
+    ShortestPathRequest::new(from, to).ok_or_else(|| {
+        RoutingError::InvalidRequest(format!("from and to are both vertex {}", from))
+    })
+}
+
+/// osm_converter takes its paths as `&str`.
+fn utf8(path: &Path) -> Result<&str, RoutingError> {
+    path.to_str()
+        .ok_or_else(|| RoutingError::File(format!("'{}' is not valid UTF-8", path.display())))
+}
+
+/// The engine new requests are answered with. A reload replaces it as a whole, requests that
+/// already hold the old one finish on it.
+pub struct SharedEngine {
+    current: RwLock<Arc<Engine>>,
+    reloading: AtomicBool,
+}
+
+impl SharedEngine {
+    pub fn new(engine: Arc<Engine>) -> SharedEngine {
+        SharedEngine {
+            current: RwLock::new(engine),
+            reloading: AtomicBool::new(false),
+        }
+    }
+
+    pub fn current(&self) -> Arc<Engine> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Marks a reload as running, false if one already is.
+    pub fn start_reload(&self) -> bool {
+        !self.reloading.swap(true, Ordering::SeqCst)
+    }
+
+    /// Ends the running reload, swapping in its engine if it loaded.
+    pub fn finish_reload(&self, engine: Option<Engine>) {
+        if let Some(engine) = engine {
+            *self.current.write().unwrap() = Arc::new(engine);
+        }
+        self.reloading.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_reloading(&self) -> bool {
+        self.reloading.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,126 @@
+use std::{fmt, time::Duration};
+
+/// Why a request could not be routed. The HTTP status of each variant is chosen in one place,
+/// see `RoutingError::status` in the server.
+#[derive(Debug)]
+pub enum RoutingError {
+    /// The snapped point of a coordinate is farther than --max-snap-distance.
+    SnapFailed {
+        name: String,
+        distance: f64,
+    },
+    Unreachable,
+    /// Leg `leg` of a multi-waypoint route, between two snapped vertices.
+    UnreachableLeg {
+        leg: usize,
+        from: u32,
+        to: u32,
+    },
+    /// A coordinate that is not a finite number pair.
+    InvalidCoordinate {
+        name: String,
+        coordinate: (f64, f64),
+    },
+    /// A coordinate outside --service-area.
+    OutsideServiceArea {
+        name: String,
+        coordinate: (f64, f64),
+    },
+    /// A malformed body or a parameter out of range.
+    InvalidRequest(String),
+    /// Every invalid setting of a request, reported together.
+    InvalidSettings(Vec<String>),
+    /// A named resource, like a place or a vertex, that does not exist.
+    NotFound(String),
+    /// The request clashes with one that is still running, like a second reload.
+    Conflict(String),
+    /// A file, like an artifact, that cannot be read, written or parsed.
+    File(String),
+    /// The request refers to vertices or edges the loaded graph does not have.
+    GraphMismatch(String),
+    /// The request took longer than --request-timeout-ms.
+    Timeout(Duration),
+    Internal(String),
+    ExceedsBudget {
+        weight: u32,
+        max_cost: u32,
+    },
+    /// No path between the endpoints is at most `max_length` meters long.
+    ExceedsLength {
+        max_length: u32,
+    },
+    /// A bounded search gave up before finishing.
+    SearchAborted(String),
+    /// The demands of an assignment cannot be met.
+    Unassignable(String),
+    /// A vertex sequence with no edge between positions `index` and `index + 1`.
+    BrokenPath {
+        index: usize,
+        from: u32,
+        to: u32,
+    },
+    /// No precomputed isochrone tile answers the request.
+    NoTile(String),
+    HlUnavailable,
+}
+
+impl fmt::Display for RoutingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoutingError::SnapFailed { name, distance } => {
+                write!(f, "{} is {:.0}m away from the nearest road", name, distance)
+            }
+            RoutingError::Unreachable => write!(f, "no path"),
+            RoutingError::UnreachableLeg { leg, from, to } => {
+                write!(f, "no path for leg {} ({} -> {})", leg, from, to)
+            }
+            RoutingError::InvalidCoordinate { name, coordinate } => {
+                write!(f, "{} {:?} is not a valid coordinate", name, coordinate)
+            }
+            RoutingError::OutsideServiceArea { name, coordinate } => write!(
+                f,
+                "outside_service_area: {} {:?} is outside the service area",
+                name, coordinate
+            ),
+            RoutingError::InvalidRequest(message)
+            | RoutingError::NotFound(message)
+            | RoutingError::Conflict(message)
+            | RoutingError::File(message) => write!(f, "{}", message),
+            RoutingError::InvalidSettings(errors) => write!(f, "{}", errors.join(", ")),
+            RoutingError::GraphMismatch(message) => write!(f, "{}", message),
+            RoutingError::Timeout(timeout) => {
+                write!(f, "no answer within {}ms", timeout.as_millis())
+            }
+            RoutingError::Internal(message) => write!(f, "internal error: {}", message),
+            RoutingError::ExceedsBudget { weight, max_cost } => {
+                write!(f, "exceeds budget: cost {} > max_cost {}", weight, max_cost)
+            }
+            RoutingError::ExceedsLength { max_length } => {
+                write!(f, "no route within {} meters", max_length)
+            }
+            RoutingError::SearchAborted(message)
+            | RoutingError::Unassignable(message)
+            | RoutingError::NoTile(message) => write!(f, "{}", message),
+            RoutingError::BrokenPath { from, to, .. } => {
+                write!(f, "no edge from {} to {}", from, to)
+            }
+            RoutingError::HlUnavailable => {
+                write!(f, "algorithm hl is not available, no .hl file is loaded")
+            }
+        }
+    }
+}
+
+impl RoutingError {
+    /// Checks that `coordinate` can be snapped at all.
+    pub fn check_coordinate(name: &str, coordinate: (f64, f64)) -> Result<(), RoutingError> {
+        if coordinate.0.is_finite() && coordinate.1.is_finite() {
+            Ok(())
+        } else {
+            Err(RoutingError::InvalidCoordinate {
+                name: name.to_string(),
+                coordinate,
+            })
+        }
+    }
+}
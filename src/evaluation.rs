@@ -0,0 +1,313 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    time::Instant,
+};
+
+use clap::Args;
+use faster_paths::graphs::path::{PathFinding, ShortestPathRequest};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde_json::{json, Value};
+
+use crate::{
+    artifacts::{expand_tilde, ArtifactArgs},
+    dijkstra::{shortest_path_tree, shortest_path_weight},
+    engine::Engine,
+};
+
+#[derive(Args, Debug)]
+pub struct DijkstraRankArgs {
+    #[command(flatten)]
+    pub artifacts: ArtifactArgs,
+    /// Path of the .csv output
+    #[arg(short, long)]
+    pub out_path: PathBuf,
+    /// Number of random sources
+    #[arg(short, long, default_value_t = 100)]
+    pub number_of_sources: u32,
+    /// Seed for choosing the sources
+    #[arg(short, long, default_value_t = 0)]
+    pub seed: u64,
+}
+
+impl DijkstraRankArgs {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = self.artifacts.validate();
+        if self.number_of_sources == 0 {
+            errors.push("--number-of-sources: must be at least 1".to_string());
+        }
+        errors
+    }
+}
+
+/// For random sources, picks the targets with Dijkstra rank 2^i and times each algorithm on
+/// them. Writes one CSV line per (algorithm, source, rank).
+pub fn dijkstra_rank(args: &DijkstraRankArgs) {
+    let engine = Engine::load_or_exit(&args.artifacts.resolve_or_exit());
+    let mut algorithms: Vec<(&str, &dyn PathFinding)> = vec![("ch", engine.ch.as_ref())];
+    if let Some(hl) = &engine.hl {
+        algorithms.push(("hl", hl.as_ref()));
+    }
+
+    let mut writer = BufWriter::new(File::create(expand_tilde(&args.out_path)).unwrap());
+    writeln!(writer, "algorithm,rank,source,target,weight,time_us").unwrap();
+
+    let mut rng = StdRng::seed_from_u64(args.seed);
+    for _ in 0..args.number_of_sources {
+        let source = rng.gen_range(0..engine.graph.number_of_vertices()) as u32;
+
+        let tree = shortest_path_tree(&engine.graph, source, None);
+        let mut settled: Vec<u32> = (0..engine.graph.number_of_vertices() as u32)
+            .filter(|&vertex| tree.distances[vertex as usize] != u32::MAX)
+            .collect();
+        settled.sort_by_key(|&vertex| (tree.distances[vertex as usize], vertex));
+
+        let mut rank = 1;
+        while rank < settled.len() {
+            let target = settled[rank];
+
+            let start = Instant::now();
+            let weight = shortest_path_weight(&engine.graph, source, target);
+            let time = start.elapsed();
+            write_line(
+                &mut writer,
+                "dijkstra",
+                rank,
+                source,
+                target,
+                weight,
+                time.as_micros(),
+            );
+
+            let request = ShortestPathRequest::new(source, target).unwrap();
+            for (name, path_finder) in algorithms.iter() {
+                let start = Instant::now();
+                let weight = path_finder
+                    .get_shortest_path(&request)
+                    .map(|path| path.weight);
+                let time = start.elapsed();
+                write_line(
+                    &mut writer,
+                    name,
+                    rank,
+                    source,
+                    target,
+                    weight,
+                    time.as_micros(),
+                );
+            }
+
+            rank *= 2;
+        }
+        println!("source {:>7} done", source);
+    }
+}
+
+fn write_line(
+    writer: &mut impl Write,
+    algorithm: &str,
+    rank: usize,
+    source: u32,
+    target: u32,
+    weight: Option<u32>,
+    time_us: u128,
+) {
+    let weight = weight.map(|weight| weight.to_string()).unwrap_or_default();
+    writeln!(
+        writer,
+        "{},{},{},{},{},{}",
+        algorithm, rank, source, target, weight, time_us
+    )
+    .unwrap();
+}
+
+#[derive(Args, Debug)]
+pub struct ReportArgs {
+    #[command(flatten)]
+    pub artifacts: ArtifactArgs,
+    /// Number of random queries
+    #[arg(short, long, default_value_t = 1000)]
+    pub number_of_queries: u32,
+    /// Seed for choosing the queries
+    #[arg(short, long, default_value_t = 0)]
+    pub seed: u64,
+    /// Path of the .json report
+    #[arg(long)]
+    pub json_path: Option<PathBuf>,
+    /// Path of the .md report, which is also printed
+    #[arg(long)]
+    pub markdown_path: Option<PathBuf>,
+    /// Path of a flamegraph .svg of the CH and HL queries
+    #[arg(long)]
+    pub profile: Option<PathBuf>,
+}
+
+impl ReportArgs {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = self.artifacts.validate();
+        if self.number_of_queries == 0 {
+            errors.push("--number-of-queries: must be at least 1".to_string());
+        }
+        errors
+    }
+}
+
+struct AlgorithmReport {
+    name: String,
+    /// Sorted query times in microseconds.
+    times: Vec<u128>,
+    /// Queries whose weight differs from Dijkstra.
+    mismatches: u32,
+}
+
+impl AlgorithmReport {
+    fn mean(&self) -> f64 {
+        self.times.iter().sum::<u128>() as f64 / self.times.len() as f64
+    }
+
+    fn percentile(&self, percentile: f64) -> u128 {
+        let index = ((self.times.len() - 1) as f64 * percentile).round() as usize;
+        self.times[index]
+    }
+}
+
+/// Times Dijkstra, CH and HL on the same random queries and compares their weights.
+fn run_queries(args: &ReportArgs) -> Vec<AlgorithmReport> {
+    let engine = Engine::load_or_exit(&args.artifacts.resolve_or_exit());
+    let mut algorithms: Vec<(&str, &dyn PathFinding)> = vec![("ch", engine.ch.as_ref())];
+    if let Some(hl) = &engine.hl {
+        algorithms.push(("hl", hl.as_ref()));
+    }
+    let mut reports: Vec<AlgorithmReport> = ["dijkstra"]
+        .into_iter()
+        .chain(algorithms.iter().map(|(name, _)| *name))
+        .map(|name| AlgorithmReport {
+            name: name.to_string(),
+            times: Vec::new(),
+            mismatches: 0,
+        })
+        .collect();
+
+    let mut rng = StdRng::seed_from_u64(args.seed);
+    let number_of_vertices = engine.graph.number_of_vertices();
+    let queries: Vec<(u32, u32)> = (0..args.number_of_queries)
+        .map(|_| {
+            let source = rng.gen_range(0..number_of_vertices) as u32;
+            let target = rng.gen_range(0..number_of_vertices) as u32;
+            (source, target)
+        })
+        .collect();
+
+    let mut expected = Vec::new();
+    for &(source, target) in queries.iter() {
+        let start = Instant::now();
+        expected.push(shortest_path_weight(&engine.graph, source, target));
+        reports[0].times.push(start.elapsed().as_micros());
+    }
+
+    // Dijkstra runs first, so the profile only shows the CH and HL queries
+    let profiler = args.profile.as_ref().map(|_| {
+        pprof::ProfilerGuardBuilder::default()
+            .frequency(1000)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .unwrap()
+    });
+    for (&(source, target), &expected) in queries.iter().zip(expected.iter()) {
+        let request = ShortestPathRequest::new(source, target).unwrap();
+        for (report, (_, path_finder)) in reports[1..].iter_mut().zip(algorithms.iter()) {
+            let start = Instant::now();
+            let weight = path_finder
+                .get_shortest_path(&request)
+                .map(|path| path.weight);
+            report.times.push(start.elapsed().as_micros());
+            if weight != expected {
+                report.mismatches += 1;
+            }
+        }
+    }
+    if let (Some(profiler), Some(path)) = (profiler, &args.profile) {
+        let report = profiler.report().build().unwrap();
+        let path = expand_tilde(path);
+        report.flamegraph(File::create(&path).unwrap()).unwrap();
+        println!("wrote flamegraph to {}", path.display());
+    }
+
+    for report in reports.iter_mut() {
+        report.times.sort_unstable();
+    }
+    reports
+}
+
+fn report_json(reports: &[AlgorithmReport], number_of_queries: u32) -> Value {
+    let dijkstra_mean = reports[0].mean();
+    let algorithms: Vec<Value> = reports
+        .iter()
+        .map(|report| {
+            json!({
+                "algorithm": report.name,
+                "mean_us": report.mean(),
+                "p50_us": report.percentile(0.5),
+                "p99_us": report.percentile(0.99),
+                "speedup": dijkstra_mean / report.mean().max(f64::MIN_POSITIVE),
+                "mismatches": report.mismatches,
+            })
+        })
+        .collect();
+    json!({ "queries": number_of_queries, "algorithms": algorithms })
+}
+
+fn report_markdown(reports: &[AlgorithmReport]) -> String {
+    let dijkstra_mean = reports[0].mean();
+    let mut markdown = String::from(
+        "| algorithm | mean [µs] | p50 [µs] | p99 [µs] | speedup vs. Dijkstra | mismatches |\n\
+         |---|---:|---:|---:|---:|---:|\n",
+    );
+    for report in reports.iter() {
+        markdown.push_str(&format!(
+            "| {} | {:.1} | {} | {} | {:.1} | {} |\n",
+            report.name,
+            report.mean(),
+            report.percentile(0.5),
+            report.percentile(0.99),
+            dijkstra_mean / report.mean().max(f64::MIN_POSITIVE),
+            report.mismatches
+        ));
+    }
+    markdown
+}
+
+fn write_reports(args: &ReportArgs, reports: &[AlgorithmReport]) {
+    let markdown = report_markdown(reports);
+    print!("{}", markdown);
+    if let Some(path) = &args.json_path {
+        let json = report_json(reports, args.number_of_queries);
+        std::fs::write(
+            expand_tilde(path),
+            serde_json::to_string_pretty(&json).unwrap(),
+        )
+        .unwrap();
+    }
+    if let Some(path) = &args.markdown_path {
+        std::fs::write(expand_tilde(path), markdown).unwrap();
+    }
+}
+
+/// Reports query times of all algorithms on random queries.
+pub fn bench(args: &ReportArgs) {
+    let reports = run_queries(args);
+    write_reports(args, &reports);
+}
+
+/// Like `bench`, but exits with 1 if CH or HL ever disagree with Dijkstra.
+pub fn verify(args: &ReportArgs) {
+    let reports = run_queries(args);
+    write_reports(args, &reports);
+    let mismatches: u32 = reports.iter().map(|report| report.mismatches).sum();
+    if mismatches > 0 {
+        eprintln!("{} queries differ from Dijkstra", mismatches);
+        std::process::exit(1);
+    }
+    println!("all weights match Dijkstra");
+}
@@ -0,0 +1,164 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{artifacts::expand_tilde, mirror::without_metadata};
+
+/// One recorded POST /route request and the answer it got.
+#[derive(Serialize, Deserialize)]
+pub struct Fixture {
+    /// Raw query string, e.g. `algorithm=ch&format=polyline`.
+    pub query: String,
+    pub accept: Option<String>,
+    pub body: Value,
+    pub status: u16,
+    pub response: String,
+}
+
+impl Fixture {
+    /// Compares an answer with the recorded one, ignoring `metadata`.
+    pub fn check(&self, status: u16, response: &str) -> Result<(), String> {
+        let same = match (without_metadata(response), without_metadata(&self.response)) {
+            (Some(actual), Some(expected)) => actual == expected,
+            _ => response == self.response,
+        };
+        if status != self.status || !same {
+            return Err(format!(
+                "status {} (recorded {}), body {}",
+                status,
+                self.status,
+                if same { "identical" } else { "differs" }
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The fixtures in `dir`, in file name order, which is the order they were recorded in.
+pub fn load_fixtures(dir: &Path) -> Result<Vec<(PathBuf, Fixture)>, String> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|error| format!("cannot read '{}' ({})", dir.display(), error))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let fixture = fs::read(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|json| serde_json::from_slice(&json).map_err(|error| error.to_string()))
+                .map_err(|error| format!("{}: {}", path.display(), error))?;
+            Ok((path, fixture))
+        })
+        .collect()
+}
+
+/// Writes every recorded request to its own numbered file in `dir`.
+pub struct Recorder {
+    dir: PathBuf,
+    next: AtomicU64,
+}
+
+impl Recorder {
+    /// Numbering continues after the files already in `dir`, so a restart adds to them.
+    pub fn new(dir: PathBuf) -> Result<Recorder, String> {
+        fs::create_dir_all(&dir).map_err(|error| error.to_string())?;
+        let existing = fs::read_dir(&dir)
+            .map_err(|error| error.to_string())?
+            .count();
+        Ok(Recorder {
+            dir,
+            next: AtomicU64::new(existing as u64),
+        })
+    }
+
+    pub fn record(&self, fixture: &Fixture) {
+        let n = self.next.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("{:08}.json", n));
+        let written = serde_json::to_vec_pretty(fixture)
+            .map_err(|error| error.to_string())
+            .and_then(|json| fs::write(&path, json).map_err(|error| error.to_string()));
+        if let Err(error) = written {
+            tracing::warn!("cannot record '{}' ({})", path.display(), error);
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ReplayArgs {
+    /// Directory written by `serve --record-dir`
+    #[arg(long)]
+    pub fixtures_dir: PathBuf,
+    /// Base URL of a running `serve`
+    #[arg(long, default_value = "http://localhost:3030")]
+    pub endpoint: String,
+}
+
+impl ReplayArgs {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if !expand_tilde(&self.fixtures_dir).is_dir() {
+            errors.push(format!(
+                "--fixtures-dir: '{}' is not a directory",
+                self.fixtures_dir.display()
+            ));
+        }
+        if !self.endpoint.starts_with("http://") && !self.endpoint.starts_with("https://") {
+            errors.push(format!(
+                "--endpoint: '{}' is not an http(s) URL",
+                self.endpoint
+            ));
+        }
+        errors
+    }
+}
+
+/// Sends every fixture in file name order and compares status and body with the recorded
+/// ones, ignoring `metadata`. Exits with 1 if any differs, so it can gate a deployment.
+pub async fn replay(args: &ReplayArgs) {
+    let fixtures = load_fixtures(&expand_tilde(&args.fixtures_dir)).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(2);
+    });
+
+    let url = format!("{}/route", args.endpoint.trim_end_matches('/'));
+    let client = reqwest::Client::new();
+    let mut failures = 0;
+    for (path, fixture) in fixtures.iter() {
+        let mut request = client.post(format!("{}?{}", url, fixture.query));
+        if let Some(accept) = &fixture.accept {
+            request = request.header("Accept", accept);
+        }
+        let (status, response) = match request.json(&fixture.body).send().await {
+            Ok(response) => (response.status().as_u16(), response.text().await.unwrap()),
+            Err(error) => {
+                println!("{}: {}", path.display(), error);
+                failures += 1;
+                continue;
+            }
+        };
+        if let Err(difference) = fixture.check(status, &response) {
+            println!("{}: {}", path.display(), difference);
+            failures += 1;
+        }
+    }
+
+    println!(
+        "{} of {} fixtures replayed identically",
+        fixtures.len() - failures,
+        fixtures.len()
+    );
+    if failures > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,483 @@
+use std::f64::consts::{PI, TAU};
+
+use clap::ValueEnum;
+use osm_converter::sphere::{geometry::point::Point, graph::graph::Fmi};
+use serde::Deserialize;
+
+/// Order of the two values of a coordinate pair in a request.
+#[derive(Clone, Copy, Debug, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum CoordinateOrder {
+    LonLat,
+    LatLon,
+}
+
+impl CoordinateOrder {
+    pub fn to_lon_lat(self, pair: (f64, f64)) -> (f64, f64) {
+        match self {
+            CoordinateOrder::LonLat => pair,
+            CoordinateOrder::LatLon => (pair.1, pair.0),
+        }
+    }
+}
+
+/// Bounding box in degrees.
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+impl Viewport {
+    /// Parses `min_lon,min_lat,max_lon,max_lat`.
+    pub fn parse(value: &str) -> Result<Viewport, String> {
+        let values: Vec<f64> = value
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("viewport '{}' is not four numbers", value))?;
+        let [min_lon, min_lat, max_lon, max_lat] = values[..] else {
+            return Err(format!("viewport '{}' is not four numbers", value));
+        };
+        if min_lon > max_lon || min_lat > max_lat {
+            return Err(format!("viewport '{}' has min above max", value));
+        }
+        Ok(Viewport {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        })
+    }
+
+    /// The parts of a `(lon, lat)` line inside the box, cutting segments at its border
+    /// (Liang-Barsky). Parts are split wherever the line leaves the box.
+    pub fn clip(&self, coordinates: &[(f64, f64)]) -> Vec<Vec<(f64, f64)>> {
+        let mut parts: Vec<Vec<(f64, f64)>> = Vec::new();
+        let mut current: Vec<(f64, f64)> = Vec::new();
+        for pair in coordinates.windows(2) {
+            let Some((start, end)) = self.clip_segment(pair[0], pair[1]) else {
+                if !current.is_empty() {
+                    parts.push(std::mem::take(&mut current));
+                }
+                continue;
+            };
+            if current.last() != Some(&start) {
+                if !current.is_empty() {
+                    parts.push(std::mem::take(&mut current));
+                }
+                current.push(start);
+            }
+            current.push(end);
+        }
+        if !current.is_empty() {
+            parts.push(current);
+        }
+        parts
+    }
+
+    fn clip_segment(&self, from: (f64, f64), to: (f64, f64)) -> Option<((f64, f64), (f64, f64))> {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let (mut t0, mut t1) = (0.0f64, 1.0f64);
+        for (p, q) in [
+            (-dx, from.0 - self.min_lon),
+            (dx, self.max_lon - from.0),
+            (-dy, from.1 - self.min_lat),
+            (dy, self.max_lat - from.1),
+        ] {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return None;
+                }
+            } else if p < 0.0 {
+                t0 = t0.max(q / p);
+            } else {
+                t1 = t1.min(q / p);
+            }
+        }
+        if t0 > t1 {
+            return None;
+        }
+        let at = |t: f64| (from.0 + t * dx, from.1 + t * dy);
+        Some((at(t0), at(t1)))
+    }
+}
+
+/// Distances in meters along a line, `from` before `to`.
+#[derive(Clone, Copy, Debug)]
+pub struct DistanceRange {
+    pub from: f64,
+    pub to: f64,
+}
+
+impl DistanceRange {
+    /// Parses `from_m,to_m`.
+    pub fn parse(value: &str) -> Result<DistanceRange, String> {
+        let values: Vec<f64> = value
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("range '{}' is not two numbers", value))?;
+        let [from, to] = values[..] else {
+            return Err(format!("range '{}' is not two numbers", value));
+        };
+        if from.is_nan() || to.is_nan() || from < 0.0 || from > to {
+            return Err(format!("range '{}' must satisfy 0 <= from <= to", value));
+        }
+        Ok(DistanceRange { from, to })
+    }
+
+    /// The part of a `(lon, lat)` line between `from` and `to` meters from its start, its ends
+    /// interpolated on the segments they fall on. Empty if the line ends before `from`.
+    pub fn slice(&self, model: DistanceModel, coordinates: &[(f64, f64)]) -> Vec<(f64, f64)> {
+        let mut slice = Vec::new();
+        // distance from the start of the line to the start of the current segment
+        let mut start = 0.0;
+        for pair in coordinates.windows(2) {
+            let length = model.distance(pair[0], pair[1]);
+            let end = start + length;
+            if end >= self.from && start <= self.to {
+                let at = |distance: f64| {
+                    let t = if length > 0.0 {
+                        ((distance - start) / length).clamp(0.0, 1.0)
+                    } else {
+                        0.0
+                    };
+                    (
+                        pair[0].0 + (pair[1].0 - pair[0].0) * t,
+                        pair[0].1 + (pair[1].1 - pair[0].1) * t,
+                    )
+                };
+                if slice.is_empty() {
+                    slice.push(at(self.from.max(start)));
+                }
+                slice.push(at(self.to.min(end)));
+            }
+            start = end;
+        }
+        slice
+    }
+}
+
+/// A `(lon, lat)` whose latitude is out of range while the longitude would be a valid
+/// latitude was most likely given in the other order.
+pub fn looks_swapped(coordinate: (f64, f64)) -> bool {
+    coordinate.1.abs() > 90.0 && coordinate.0.abs() <= 90.0
+}
+
+/// Returns `(lon, lat)` in degrees, the order used in all responses.
+pub fn lon_lat(point: &Point) -> (f64, f64) {
+    (point.lon(), point.lat())
+}
+
+pub fn vertex_coordinates(fmi: &Fmi, vertices: &[u32]) -> Vec<(f64, f64)> {
+    vertices
+        .iter()
+        .map(|&vertex| lon_lat(&fmi.points[vertex as usize]))
+        .collect()
+}
+
+/// How distances in meters are computed from coordinates.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum DistanceModel {
+    /// Great circle on a sphere, off by up to about 0.3%.
+    Haversine,
+    /// Shortest line on the WGS84 ellipsoid (Vincenty).
+    Geodesic,
+}
+
+impl DistanceModel {
+    pub fn distance(self, from: (f64, f64), to: (f64, f64)) -> f64 {
+        match self {
+            DistanceModel::Haversine => haversine_distance(from, to),
+            DistanceModel::Geodesic => geodesic_distance(from, to),
+        }
+    }
+}
+
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Great-circle distance in meters between two `(lon, lat)` coordinates.
+pub fn haversine_distance(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (from_lon, from_lat) = (from.0.to_radians(), from.1.to_radians());
+    let (to_lon, to_lat) = (to.0.to_radians(), to.1.to_radians());
+
+    let a = ((to_lat - from_lat) / 2.0).sin().powi(2)
+        + from_lat.cos() * to_lat.cos() * ((to_lon - from_lon) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+
+/// Geodesic distance in meters on the WGS84 ellipsoid between two `(lon, lat)` coordinates,
+/// using Vincenty's inverse formula. Falls back to haversine for the nearly antipodal pairs
+/// for which the iteration does not converge.
+pub fn geodesic_distance(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let b = WGS84_A * (1.0 - WGS84_F);
+    let l = (to.0 - from.0).to_radians();
+    let u1 = ((1.0 - WGS84_F) * from.1.to_radians().tan()).atan();
+    let u2 = ((1.0 - WGS84_F) * to.1.to_radians().tan()).atan();
+    let (sin_u1, cos_u1) = u1.sin_cos();
+    let (sin_u2, cos_u2) = u2.sin_cos();
+
+    let mut lambda = l;
+    for _ in 0..200 {
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let sin_sigma = ((cos_u2 * sin_lambda).powi(2)
+            + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2))
+        .sqrt();
+        if sin_sigma == 0.0 {
+            return 0.0;
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        let sigma = sin_sigma.atan2(cos_sigma);
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        let cos_sq_alpha = 1.0 - sin_alpha * sin_alpha;
+        // both points on the equator
+        let cos_2sigma_m = if cos_sq_alpha == 0.0 {
+            0.0
+        } else {
+            cos_sigma - 2.0 * sin_u1 * sin_u2 / cos_sq_alpha
+        };
+        let c = WGS84_F / 16.0 * cos_sq_alpha * (4.0 + WGS84_F * (4.0 - 3.0 * cos_sq_alpha));
+        let previous = lambda;
+        lambda = l
+            + (1.0 - c)
+                * WGS84_F
+                * sin_alpha
+                * (sigma
+                    + c * sin_sigma
+                        * (cos_2sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))));
+
+        if (lambda - previous).abs() < 1e-12 {
+            let u_sq = cos_sq_alpha * (WGS84_A * WGS84_A - b * b) / (b * b);
+            let big_a =
+                1.0 + u_sq / 16384.0 * (4096.0 + u_sq * (-768.0 + u_sq * (320.0 - 175.0 * u_sq)));
+            let big_b = u_sq / 1024.0 * (256.0 + u_sq * (-128.0 + u_sq * (74.0 - 47.0 * u_sq)));
+            let delta_sigma = big_b
+                * sin_sigma
+                * (cos_2sigma_m
+                    + big_b / 4.0
+                        * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))
+                            - big_b / 6.0
+                                * cos_2sigma_m
+                                * (-3.0 + 4.0 * sin_sigma.powi(2))
+                                * (-3.0 + 4.0 * cos_2sigma_m.powi(2))));
+            return b * big_a * (sigma - delta_sigma);
+        }
+    }
+    haversine_distance(from, to)
+}
+
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// Meters a vertex may lie off the line through its neighbors to be merged away.
+const COLLINEAR_TOLERANCE: f64 = 0.1;
+
+/// The line without the vertices that lie on the segment between the kept ones around them,
+/// e.g. the intermediate vertices of a straight street on a grid. Every dropped vertex is
+/// within `COLLINEAR_TOLERANCE` of the merged segment, so the drawn line looks the same.
+///
+/// Each dropped vertex narrows the range of bearings from the last kept vertex that pass
+/// close enough to it, so a vertex is checked once however long the straight run is.
+pub fn merge_collinear(coordinates: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let Some(&first) = coordinates.first() else {
+        return Vec::new();
+    };
+    let mut merged = vec![first];
+    let mut run = Run::new(first);
+    for i in 1..coordinates.len() {
+        if i + 1 == coordinates.len() {
+            merged.push(coordinates[i]);
+            break;
+        }
+        let mut extended = run;
+        extended.drop_vertex(coordinates[i]);
+        if extended.reaches(coordinates[i + 1]) {
+            run = extended;
+        } else {
+            merged.push(coordinates[i]);
+            run = Run::new(coordinates[i]);
+        }
+    }
+    merged
+}
+
+/// Vertices dropped since the last kept vertex `anchor`, as the bearings and the length a
+/// segment from `anchor` needs to pass within `COLLINEAR_TOLERANCE` of all of them.
+#[derive(Clone, Copy)]
+struct Run {
+    anchor: (f64, f64),
+    /// Bearing the range is relative to, `None` while every dropped vertex is within the
+    /// tolerance of `anchor`.
+    center: Option<f64>,
+    /// Allowed bearings relative to `center`.
+    min: f64,
+    max: f64,
+    /// Meters to the farthest dropped vertex.
+    reach: f64,
+}
+
+impl Run {
+    fn new(anchor: (f64, f64)) -> Run {
+        Run {
+            anchor,
+            center: None,
+            min: f64::NEG_INFINITY,
+            max: f64::INFINITY,
+            reach: 0.0,
+        }
+    }
+
+    fn drop_vertex(&mut self, point: (f64, f64)) {
+        let (distance, bearing) = polar(self.anchor, point);
+        self.reach = self.reach.max(distance);
+        if distance <= COLLINEAR_TOLERANCE {
+            return;
+        }
+        let center = *self.center.get_or_insert(bearing);
+        let relative = wrap_angle(bearing - center);
+        let spread = (COLLINEAR_TOLERANCE / distance).asin();
+        self.min = self.min.max(relative - spread);
+        self.max = self.max.min(relative + spread);
+    }
+
+    /// Whether the segment from `anchor` to `end` passes close to every dropped vertex. It
+    /// must reach at least as far as they do, so none lies beyond its end.
+    fn reaches(&self, end: (f64, f64)) -> bool {
+        let (distance, bearing) = polar(self.anchor, end);
+        if self.min > self.max || distance < self.reach {
+            return false;
+        }
+        match self.center {
+            Some(center) => {
+                let relative = wrap_angle(bearing - center);
+                self.min <= relative && relative <= self.max
+            }
+            None => true,
+        }
+    }
+}
+
+/// Meters and bearing from `a` to `point`, in a plane around `a`.
+fn polar(a: (f64, f64), point: (f64, f64)) -> (f64, f64) {
+    let scale = a.1.to_radians().cos() * METERS_PER_DEGREE;
+    let (x, y) = ((point.0 - a.0) * scale, (point.1 - a.1) * METERS_PER_DEGREE);
+    (x.hypot(y), y.atan2(x))
+}
+
+/// `angle` in radians brought to `-PI..PI`.
+fn wrap_angle(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+pub fn path_length(model: DistanceModel, coordinates: &[(f64, f64)]) -> f64 {
+    coordinates
+        .windows(2)
+        .map(|pair| model.distance(pair[0], pair[1]))
+        .sum()
+}
+
+/// Discrete Hausdorff distance in meters between the vertices of two lines.
+pub fn hausdorff_distance(model: DistanceModel, a: &[(f64, f64)], b: &[(f64, f64)]) -> f64 {
+    let directed = |from: &[(f64, f64)], to: &[(f64, f64)]| {
+        from.iter()
+            .map(|&x| {
+                to.iter()
+                    .map(|&y| model.distance(x, y))
+                    .fold(f64::INFINITY, f64::min)
+            })
+            .fold(0.0, f64::max)
+    };
+    directed(a, b).max(directed(b, a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_viewports() {
+        let viewport = Viewport::parse("8, 48,9,49").unwrap();
+        assert_eq!(
+            (
+                viewport.min_lon,
+                viewport.min_lat,
+                viewport.max_lon,
+                viewport.max_lat
+            ),
+            (8.0, 48.0, 9.0, 49.0)
+        );
+        assert!(Viewport::parse("8,48,9").is_err());
+        assert!(Viewport::parse("9,48,8,49").is_err());
+    }
+
+    #[test]
+    fn clips_lines_at_the_border() {
+        let viewport = Viewport::parse("0,0,10,10").unwrap();
+        assert_eq!(
+            viewport.clip(&[(-5.0, 5.0), (5.0, 5.0), (15.0, 5.0)]),
+            vec![vec![(0.0, 5.0), (5.0, 5.0), (10.0, 5.0)]]
+        );
+        assert_eq!(
+            viewport.clip(&[(1.0, 1.0), (2.0, 2.0)]),
+            vec![vec![(1.0, 1.0), (2.0, 2.0)]]
+        );
+        assert_eq!(
+            viewport.clip(&[(-1.0, -1.0), (-1.0, 20.0)]),
+            Vec::<Vec<(f64, f64)>>::new()
+        );
+    }
+
+    #[test]
+    fn splits_lines_that_leave_and_come_back() {
+        let viewport = Viewport::parse("0,0,10,10").unwrap();
+        assert_eq!(
+            viewport.clip(&[(5.0, 5.0), (15.0, 5.0), (5.0, 6.0)]),
+            vec![vec![(5.0, 5.0), (10.0, 5.0)], vec![(10.0, 5.5), (5.0, 6.0)]]
+        );
+    }
+
+    #[test]
+    fn merges_straight_runs() {
+        let line: Vec<(f64, f64)> = (0..=10).map(|i| (8.0 + i as f64 * 0.001, 48.0)).collect();
+        assert_eq!(merge_collinear(&line), vec![(8.0, 48.0), (8.01, 48.0)]);
+    }
+
+    #[test]
+    fn keeps_vertices_off_the_line() {
+        let corner = [(8.0, 48.0), (8.001, 48.0), (8.001, 48.001)];
+        assert_eq!(merge_collinear(&corner), corner);
+        // a meter off the line
+        let bend = [
+            (8.0, 48.0),
+            (8.001, 48.0 + 1.0 / METERS_PER_DEGREE),
+            (8.002, 48.0),
+        ];
+        assert_eq!(merge_collinear(&bend), bend);
+        // a centimeter off the line
+        let wiggle = [
+            (8.0, 48.0),
+            (8.001, 48.0 + 0.01 / METERS_PER_DEGREE),
+            (8.002, 48.0),
+        ];
+        assert_eq!(merge_collinear(&wiggle), [(8.0, 48.0), (8.002, 48.0)]);
+    }
+
+    #[test]
+    fn keeps_vertices_beyond_the_end() {
+        let back = [(8.0, 48.0), (8.002, 48.0), (8.001, 48.0)];
+        assert_eq!(merge_collinear(&back), back);
+    }
+
+    #[test]
+    fn merges_short_lines() {
+        assert_eq!(merge_collinear(&[]), Vec::new());
+        assert_eq!(merge_collinear(&[(8.0, 48.0)]), vec![(8.0, 48.0)]);
+    }
+}
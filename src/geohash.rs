@@ -0,0 +1,73 @@
+use crate::error::RoutingError;
+
+/// Digits of a geohash, five bits each.
+const ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Whether `value` can be a geohash: 1 to 12 digits of its alphabet, case-insensitive.
+pub fn is_geohash(value: &str) -> bool {
+    (1..=12).contains(&value.len())
+        && value
+            .bytes()
+            .all(|byte| ALPHABET.contains(&byte.to_ascii_lowercase()))
+}
+
+/// `(lon, lat)` of the center of the cell `hash` stands for.
+pub fn decode(hash: &str) -> Result<(f64, f64), RoutingError> {
+    if !is_geohash(hash) {
+        return Err(RoutingError::InvalidRequest(format!(
+            "'{}' is not a geohash",
+            hash
+        )));
+    }
+    let (mut lon, mut lat) = ((-180.0, 180.0), (-90.0, 90.0));
+    let mut is_lon = true;
+    for byte in hash.bytes() {
+        let digit = ALPHABET
+            .iter()
+            .position(|&digit| digit == byte.to_ascii_lowercase())
+            .unwrap();
+        for bit in (0..5).rev() {
+            // bits alternate between longitude and latitude, starting with longitude
+            let range: &mut (f64, f64) = if is_lon { &mut lon } else { &mut lat };
+            let middle = (range.0 + range.1) / 2.0;
+            if digit >> bit & 1 == 1 {
+                range.0 = middle;
+            } else {
+                range.1 = middle;
+            }
+            is_lon = !is_lon;
+        }
+    }
+    Ok(((lon.0 + lon.1) / 2.0, (lat.0 + lat.1) / 2.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_the_center_of_the_cell() {
+        assert_eq!(decode("ezs42").unwrap(), (-5.60302734375, 42.60498046875));
+        assert_eq!(decode("EZS42").unwrap(), decode("ezs42").unwrap());
+    }
+
+    #[test]
+    fn decodes_long_hashes_precisely() {
+        let (lon, lat) = decode("u4pruydqqvj").unwrap();
+        assert!((lon - 10.40744).abs() < 1e-5, "{}", lon);
+        assert!((lat - 57.64911).abs() < 1e-5, "{}", lat);
+    }
+
+    #[test]
+    fn recognizes_geohashes() {
+        assert!(is_geohash("u4pruydqqvj"));
+        assert!(!is_geohash(""));
+        assert!(!is_geohash("u4pruydqqvjxy"));
+        // a, i, l and o are not digits
+        assert!(!is_geohash("u4pa"));
+        assert_eq!(
+            decode("u4pa").unwrap_err().to_string(),
+            "'u4pa' is not a geohash"
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::error::RoutingError;
+
+/// Decimals of all coordinates in responses, about 1 cm. Rounding makes the output independent
+/// of how the last bits of a coordinate came about, and `Map` keeps keys sorted, so identical
+/// requests on the same artifacts give byte-identical responses.
+pub const COORDINATE_DECIMALS: i32 = 7;
+
+pub fn round_coordinate(coordinate: (f64, f64)) -> (f64, f64) {
+    (
+        round(coordinate.0, COORDINATE_DECIMALS),
+        round(coordinate.1, COORDINATE_DECIMALS),
+    )
+}
+
+/// Rounds to `decimals` places, mapping `-0.0` to `0.0` so both print the same.
+pub fn round(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    let rounded = (value * factor).round() / factor;
+    if rounded == 0.0 {
+        0.0
+    } else {
+        rounded
+    }
+}
+
+/// A line crossing the antimeridian becomes a MultiLineString, see RFC 7946, section 3.1.9.
+pub fn linestring_feature(coordinates: &[(f64, f64)], properties: Map<String, Value>) -> Value {
+    lines_feature(&[coordinates.to_vec()], properties)
+}
+
+/// One LineString if `lines` has a single part after splitting at the antimeridian, a
+/// MultiLineString otherwise.
+pub fn lines_feature(lines: &[Vec<(f64, f64)>], properties: Map<String, Value>) -> Value {
+    let mut parts: Vec<Vec<(f64, f64)>> = lines
+        .iter()
+        .flat_map(|line| {
+            let line: Vec<(f64, f64)> = line.iter().copied().map(round_coordinate).collect();
+            split_at_antimeridian(&line)
+        })
+        .collect();
+    let geometry = if parts.len() == 1 {
+        json!({ "type": "LineString", "coordinates": parts.pop().unwrap() })
+    } else {
+        json!({ "type": "MultiLineString", "coordinates": parts })
+    };
+    json!({
+        "type": "Feature",
+        "geometry": geometry,
+        "properties": properties,
+    })
+}
+
+/// Cuts a `(lon, lat)` line wherever a segment is shorter the other way around the globe,
+/// ending and starting the parts at ±180 with an interpolated latitude.
+pub fn split_at_antimeridian(coordinates: &[(f64, f64)]) -> Vec<Vec<(f64, f64)>> {
+    let mut parts = vec![Vec::new()];
+    for (i, &coordinate) in coordinates.iter().enumerate() {
+        if i > 0 {
+            let previous = coordinates[i - 1];
+            let delta = coordinate.0 - previous.0;
+            if delta.abs() > 180.0 {
+                let boundary = if previous.0 > 0.0 { 180.0 } else { -180.0 };
+                let unwrapped = coordinate.0 - 360.0 * delta.signum();
+                let t = (boundary - previous.0) / (unwrapped - previous.0);
+                let lat = previous.1 + t * (coordinate.1 - previous.1);
+                parts.last_mut().unwrap().push((boundary, lat));
+                parts.push(vec![(-boundary, lat)]);
+            }
+        }
+        parts.last_mut().unwrap().push(coordinate);
+    }
+    parts
+}
+
+pub fn feature_collection(features: Vec<Value>) -> Value {
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+/// Waypoints given as a GeoJSON Feature with a MultiPoint geometry or a FeatureCollection of
+/// Point features.
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub enum WaypointInput {
+    Feature(Feature),
+    FeatureCollection { features: Vec<Feature> },
+}
+
+/// `(lon, lat)` of a waypoint with the properties of its feature.
+pub type Waypoint = ((f64, f64), Value);
+
+#[derive(Deserialize)]
+pub struct Feature {
+    geometry: Geometry,
+    #[serde(default)]
+    properties: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "coordinates")]
+enum Geometry {
+    Point(Vec<f64>),
+    MultiPoint(Vec<Vec<f64>>),
+}
+
+impl WaypointInput {
+    /// Returns the `(lon, lat)` of every point together with the properties of its feature.
+    pub fn waypoints(self) -> Result<Vec<Waypoint>, RoutingError> {
+        let features = match self {
+            WaypointInput::Feature(feature) => vec![feature],
+            WaypointInput::FeatureCollection { features } => features,
+        };
+
+        let mut waypoints = Vec::new();
+        for feature in features {
+            let positions = match feature.geometry {
+                Geometry::Point(position) => vec![position],
+                Geometry::MultiPoint(positions) => positions,
+            };
+            for position in positions {
+                let [lon, lat, ..] = position[..] else {
+                    return Err(RoutingError::InvalidRequest(
+                        "a position needs at least two values".to_string(),
+                    ));
+                };
+                waypoints.push(((lon, lat), feature.properties.clone()));
+            }
+        }
+        Ok(waypoints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_coordinates_to_seven_decimals() {
+        assert_eq!(
+            round_coordinate((8.123456749, 48.00000006)),
+            (8.1234567, 48.0000001)
+        );
+        assert_eq!(round(179.99999996, COORDINATE_DECIMALS), 180.0);
+    }
+
+    #[test]
+    fn prints_rounded_coordinates_the_same_way() {
+        let printed = |coordinate| json!(round_coordinate(coordinate)).to_string();
+        assert_eq!(printed((8.123456749, -0.00000001)), "[8.1234567,0.0]");
+        assert_eq!(printed((8.12345670001, 0.00000001)), "[8.1234567,0.0]");
+    }
+
+    #[test]
+    fn renders_features_with_sorted_keys() {
+        let mut properties = Map::new();
+        properties.insert("weight".to_string(), json!(12));
+        properties.insert("distance".to_string(), json!(3.5));
+        let feature = linestring_feature(&[(8.00000001, 48.0), (8.5, 48.25)], properties);
+        assert_eq!(
+            feature.to_string(),
+            r#"{"geometry":{"coordinates":[[8.0,48.0],[8.5,48.25]],"type":"LineString"},"properties":{"distance":3.5,"weight":12},"type":"Feature"}"#
+        );
+    }
+
+    #[test]
+    fn splits_lines_at_the_antimeridian() {
+        assert_eq!(
+            split_at_antimeridian(&[(179.0, 10.0), (-179.0, 20.0)]),
+            vec![
+                vec![(179.0, 10.0), (180.0, 15.0)],
+                vec![(-180.0, 15.0), (-179.0, 20.0)]
+            ]
+        );
+        assert_eq!(
+            split_at_antimeridian(&[(-179.0, 20.0), (179.0, 10.0)]),
+            vec![
+                vec![(-179.0, 20.0), (-180.0, 15.0)],
+                vec![(180.0, 15.0), (179.0, 10.0)]
+            ]
+        );
+    }
+}
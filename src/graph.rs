@@ -0,0 +1,326 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{error::RoutingError, storage::store_for};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Edge {
+    pub source: u32,
+    pub target: u32,
+    pub weight: u32,
+}
+
+/// Plain adjacency view of the .gr file, used wherever the path finders do not expose the
+/// underlying edges.
+pub struct Graph {
+    pub edges: Vec<Edge>,
+    out_edges: Adjacency,
+    in_edges: Adjacency,
+    /// Index among the arc lines of the .gr file of every edge, `None` if it is the edge id.
+    arc_ids: Option<Vec<u32>>,
+}
+
+/// Edge ids of every vertex in one flat array (compressed sparse rows), so a search reads
+/// the neighbors of consecutive vertices without chasing one allocation per vertex.
+struct Adjacency {
+    /// Edges of vertex `v` are `edges[offsets[v]..offsets[v + 1]]`.
+    offsets: Vec<u32>,
+    edges: Vec<u32>,
+}
+
+impl Adjacency {
+    /// Groups the edge ids by `vertex_of(edge)`, in increasing id order within a vertex.
+    fn new(edges: &[Edge], number_of_vertices: usize, vertex_of: impl Fn(&Edge) -> u32) -> Self {
+        let mut offsets = vec![0u32; number_of_vertices + 1];
+        for edge in edges {
+            offsets[vertex_of(edge) as usize + 1] += 1;
+        }
+        for i in 1..offsets.len() {
+            offsets[i] += offsets[i - 1];
+        }
+        let mut next = offsets.clone();
+        let mut ids = vec![0u32; edges.len()];
+        for (id, edge) in edges.iter().enumerate() {
+            let slot = &mut next[vertex_of(edge) as usize];
+            ids[*slot as usize] = id as u32;
+            *slot += 1;
+        }
+        Adjacency {
+            offsets,
+            edges: ids,
+        }
+    }
+
+    fn of(&self, vertex: u32) -> &[u32] {
+        let vertex = vertex as usize;
+        &self.edges[self.offsets[vertex] as usize..self.offsets[vertex + 1] as usize]
+    }
+}
+
+/// What loading does with self-loops, parallel edges and weights below 1 or above 32 bits.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Sanitation {
+    /// Keep the edges and only report them, with weights clamped to 0 to `u32::MAX`.
+    #[default]
+    Off,
+    /// Drop self-loops, negative weights and all but the lightest of parallel edges, raise
+    /// zero weights to 1 and clamp weights above 32 bits.
+    Lenient,
+    /// Refuse to load a graph that has any of them.
+    Strict,
+}
+
+/// Counts of the malformed edges found while loading.
+#[derive(Default, Debug)]
+pub struct SanitationReport {
+    pub self_loops: usize,
+    pub parallel_edges: usize,
+    pub zero_weights: usize,
+    pub negative_weights: usize,
+    /// Weights above `u32::MAX`.
+    pub oversized_weights: usize,
+}
+
+impl SanitationReport {
+    fn is_clean(&self) -> bool {
+        self.self_loops
+            + self.parallel_edges
+            + self.zero_weights
+            + self.negative_weights
+            + self.oversized_weights
+            == 0
+    }
+}
+
+impl std::fmt::Display for SanitationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} self-loops, {} parallel edges, {} zero weights, {} negative weights, {} weights \
+             above {}",
+            self.self_loops,
+            self.parallel_edges,
+            self.zero_weights,
+            self.negative_weights,
+            self.oversized_weights,
+            u32::MAX
+        )
+    }
+}
+
+impl Graph {
+    /// Reads the `a <source> <target> <weight>` lines of a .gr file. Vertex ids in the file
+    /// start at 1, a malformed line fails with its line number. The CH and HL artifacts are
+    /// built from the file as it is, sanitation only changes this view of it; with `Lenient`,
+    /// edge ids count the kept edges and `arc_id` maps them back to the arc lines.
+    pub fn from_gr_file(
+        path: &Path,
+        number_of_vertices: usize,
+        sanitation: Sanitation,
+    ) -> Result<Graph, RoutingError> {
+        let reader = BufReader::new(store_for(path).open(path)?);
+
+        let mut report = SanitationReport::default();
+        // position in `edges` of the kept edge for every (source, target)
+        let mut kept: HashMap<(u32, u32), usize> = HashMap::new();
+        let mut edges: Vec<Edge> = Vec::new();
+        // index among the arc lines of every kept edge, only needed with `Lenient`
+        let mut arc_ids: Vec<u32> = Vec::new();
+        let mut arcs: u32 = 0;
+        for (number, line) in reader.lines().enumerate() {
+            let at = || format!("{}:{}", path.display(), number + 1);
+            let line = line.map_err(|error| RoutingError::File(format!("{}: {}", at(), error)))?;
+            let mut values = line.split_whitespace();
+            if values.next() != Some("a") {
+                continue;
+            }
+            let mut next = |name: &str| -> Result<i64, RoutingError> {
+                let value = values.next().ok_or_else(|| {
+                    RoutingError::File(format!("{}: the {} is missing", at(), name))
+                })?;
+                value.parse::<i64>().map_err(|_| {
+                    RoutingError::File(format!("{}: {} '{}' is not an integer", at(), name, value))
+                })
+            };
+            let (source, target, weight) = (next("source")?, next("target")?, next("weight")?);
+            let vertex = |id: i64| {
+                id.checked_sub(1)
+                    .and_then(|id| u32::try_from(id).ok())
+                    .ok_or_else(|| {
+                        RoutingError::File(format!(
+                            "{}: {} is not a vertex id, they start at 1",
+                            at(),
+                            id
+                        ))
+                    })
+            };
+            let (source, target) = (vertex(source)?, vertex(target)?);
+            let arc = arcs;
+            arcs += 1;
+
+            let self_loop = source == target;
+            let parallel = kept.get(&(source, target)).copied();
+            report.self_loops += self_loop as usize;
+            report.parallel_edges += parallel.is_some() as usize;
+            report.zero_weights += (weight == 0) as usize;
+            report.negative_weights += (weight < 0) as usize;
+            report.oversized_weights += (weight > u32::MAX as i64) as usize;
+
+            if sanitation != Sanitation::Lenient {
+                // strict fails on the report below, off keeps the edge with a clamped weight
+                kept.entry((source, target)).or_insert(edges.len());
+                edges.push(Edge {
+                    source,
+                    target,
+                    weight: weight.clamp(0, u32::MAX as i64) as u32,
+                });
+                continue;
+            }
+            if self_loop || weight < 0 {
+                continue;
+            }
+            let weight = weight.clamp(1, u32::MAX as i64) as u32;
+            match parallel {
+                Some(position) => {
+                    if weight < edges[position].weight {
+                        edges[position].weight = weight;
+                        arc_ids[position] = arc;
+                    }
+                }
+                None => {
+                    kept.insert((source, target), edges.len());
+                    edges.push(Edge {
+                        source,
+                        target,
+                        weight,
+                    });
+                    arc_ids.push(arc);
+                }
+            }
+        }
+
+        if !report.is_clean() {
+            match sanitation {
+                Sanitation::Off => {
+                    tracing::warn!("{}: {}, see --sanitize", path.display(), report);
+                    let clamped = report.negative_weights + report.oversized_weights;
+                    if clamped > 0 {
+                        tracing::warn!(
+                            "{}: clamped {} weights to the range 0 to {}",
+                            path.display(),
+                            clamped,
+                            u32::MAX
+                        );
+                    }
+                }
+                Sanitation::Lenient => tracing::info!("{}: fixed {}", path.display(), report),
+                Sanitation::Strict => {
+                    return Err(RoutingError::File(format!(
+                        "{}: {}, see --sanitize",
+                        path.display(),
+                        report
+                    )));
+                }
+            }
+        }
+
+        // path weights are u32 as well, searches saturate instead of wrapping around
+        let total_weight: u64 = edges.iter().map(|edge| edge.weight as u64).sum();
+        if total_weight > u32::MAX as u64 {
+            tracing::warn!(
+                "{}: the edge weights add up to {}, paths above {} are reported as {}",
+                path.display(),
+                total_weight,
+                u32::MAX,
+                u32::MAX
+            );
+        }
+
+        let mut graph = Graph::from_edges(edges, number_of_vertices);
+        if sanitation == Sanitation::Lenient {
+            graph.arc_ids = Some(arc_ids);
+        }
+        Ok(graph)
+    }
+
+    /// Graph of `edges`, with at least `number_of_vertices` vertices.
+    pub fn from_edges(edges: Vec<Edge>, number_of_vertices: usize) -> Graph {
+        let number_of_vertices = edges
+            .iter()
+            .map(|edge| edge.source.max(edge.target) as usize + 1)
+            .max()
+            .unwrap_or(0)
+            .max(number_of_vertices);
+
+        let out_edges = Adjacency::new(&edges, number_of_vertices, |edge| edge.source);
+        let in_edges = Adjacency::new(&edges, number_of_vertices, |edge| edge.target);
+
+        Graph {
+            edges,
+            out_edges,
+            in_edges,
+            arc_ids: None,
+        }
+    }
+
+    pub fn number_of_vertices(&self) -> usize {
+        self.out_edges.offsets.len() - 1
+    }
+
+    /// Ids of the edges leaving `vertex`.
+    pub fn out_edges(&self, vertex: u32) -> &[u32] {
+        self.out_edges.of(vertex)
+    }
+
+    /// Ids of the edges entering `vertex`.
+    pub fn in_edges(&self, vertex: u32) -> &[u32] {
+        self.in_edges.of(vertex)
+    }
+
+    /// Index among the arc lines of the .gr file of `edge`, the id routes report edges by.
+    pub fn arc_id(&self, edge: u32) -> u32 {
+        self.arc_ids
+            .as_ref()
+            .map_or(edge, |arc_ids| arc_ids[edge as usize])
+    }
+
+    /// The edge read from arc line `arc`, `None` if it was dropped while loading.
+    pub fn edge_of_arc(&self, arc: u32) -> Option<u32> {
+        match &self.arc_ids {
+            Some(arc_ids) => arc_ids
+                .iter()
+                .position(|&id| id == arc)
+                .map(|edge| edge as u32),
+            None => ((arc as usize) < self.edges.len()).then_some(arc),
+        }
+    }
+
+    /// The cheapest of the parallel edges from `source` to `target`.
+    pub fn edge_between(&self, source: u32, target: u32) -> Option<u32> {
+        if source as usize >= self.number_of_vertices() {
+            return None;
+        }
+        self.out_edges(source)
+            .iter()
+            .copied()
+            .filter(|&edge| self.edges[edge as usize].target == target)
+            .min_by_key(|&edge| self.edges[edge as usize].weight)
+    }
+
+    /// Weight of the path through `vertices` under the current edge weights, or the index of
+    /// the first vertex that has no edge to its successor.
+    pub fn path_weight(&self, vertices: &[u32]) -> Result<u32, usize> {
+        let mut weight: u32 = 0;
+        for (i, pair) in vertices.windows(2).enumerate() {
+            let edge = self.edge_between(pair[0], pair[1]).ok_or(i)?;
+            weight = weight.saturating_add(self.edges[edge as usize].weight);
+        }
+        Ok(weight)
+    }
+}
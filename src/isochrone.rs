@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+
+use h3o::{LatLng, Resolution};
+use osm_converter::sphere::graph::graph::Fmi;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    dijkstra::shortest_path_tree,
+    error::RoutingError,
+    geo::{lon_lat, CoordinateOrder},
+    geojson::{feature_collection, round_coordinate},
+    graph::Graph,
+};
+
+pub const MAX_BANDS: usize = 10;
+
+/// Body of POST /isochrone.
+#[derive(Deserialize)]
+pub struct IsochroneRequest {
+    pub from: (f64, f64),
+    /// Upper cost bound of every band.
+    pub costs: Vec<u32>,
+    #[serde(default)]
+    pub coordinate_order: Option<CoordinateOrder>,
+    /// Answer with H3 cells of this resolution (0 to 15) instead of polygons.
+    #[serde(default)]
+    pub h3_resolution: Option<u8>,
+}
+
+/// One polygon per cost band, the convex hull of all vertices reachable from `source` within
+/// that cost. A single Dijkstra bounded by the largest cost serves all bands. Bands reaching
+/// fewer than three distinct points have a `null` geometry.
+pub fn isochrones(source: u32, costs: &[u32], fmi: &Fmi, graph: &Graph) -> Value {
+    let mut costs = costs.to_vec();
+    costs.sort_unstable();
+    costs.dedup();
+    let tree = shortest_path_tree(graph, source, costs.last().copied());
+
+    // reached vertices sorted by cost, so every band is a prefix
+    let mut reached: Vec<(u32, (f64, f64))> = tree
+        .distances
+        .iter()
+        .enumerate()
+        .filter(|(_, &distance)| distance != u32::MAX)
+        .map(|(vertex, &distance)| (distance, lon_lat(&fmi.points[vertex])))
+        .collect();
+    reached.sort_by_key(|&(distance, _)| distance);
+
+    let features: Vec<Value> = costs
+        .iter()
+        .map(|&cost| {
+            let end = reached.partition_point(|&(distance, _)| distance <= cost);
+            let points: Vec<(f64, f64)> = reached[..end].iter().map(|&(_, point)| point).collect();
+            let hull = convex_hull(points);
+            let geometry = if hull.len() < 3 {
+                Value::Null
+            } else {
+                let mut ring: Vec<(f64, f64)> = hull.into_iter().map(round_coordinate).collect();
+                ring.push(ring[0]);
+                json!({ "type": "Polygon", "coordinates": [ring] })
+            };
+            json!({
+                "type": "Feature",
+                "geometry": geometry,
+                "properties": { "cost": cost, "vertices": end },
+            })
+        })
+        .collect();
+    feature_collection(features)
+}
+
+/// Counterclockwise convex hull in the lon/lat plane (monotone chain), without collinear
+/// points.
+fn convex_hull(mut points: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+    points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let cross = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    let mut hull: Vec<(f64, f64)> = Vec::with_capacity(2 * points.len());
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let start = hull.len();
+        for point in pass {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // the last point of each chain starts the other one
+        hull.pop();
+    }
+    hull
+}
+
+/// The H3 cells of the given resolution that contain a vertex reachable from `source` within
+/// the largest cost, each with the lowest cost of its vertices, as `{ cell id: cost }`.
+pub fn h3_cells(
+    source: u32,
+    costs: &[u32],
+    resolution: u8,
+    fmi: &Fmi,
+    graph: &Graph,
+) -> Result<Value, RoutingError> {
+    let resolution = Resolution::try_from(resolution).map_err(|_| {
+        RoutingError::InvalidRequest(format!(
+            "h3_resolution {} is not between 0 and 15",
+            resolution
+        ))
+    })?;
+    let tree = shortest_path_tree(graph, source, costs.iter().max().copied());
+
+    let mut cells: BTreeMap<String, u32> = BTreeMap::new();
+    for (vertex, &distance) in tree.distances.iter().enumerate() {
+        if distance == u32::MAX {
+            continue;
+        }
+        let (lon, lat) = lon_lat(&fmi.points[vertex]);
+        let Ok(point) = LatLng::new(lat, lon) else {
+            continue;
+        };
+        let cost = cells
+            .entry(point.to_cell(resolution).to_string())
+            .or_insert(distance);
+        *cost = (*cost).min(distance);
+    }
+    Ok(json!({
+        "resolution": u8::from(resolution),
+        "max_cost": costs.iter().max(),
+        "cells": cells,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hull_is_counterclockwise_without_inner_points() {
+        let points = vec![
+            (1.0, 1.0),
+            (0.0, 0.0),
+            (2.0, 0.0),
+            (2.0, 2.0),
+            (0.0, 2.0),
+            (1.0, 0.5),
+        ];
+        assert_eq!(
+            convex_hull(points),
+            vec![(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)]
+        );
+    }
+
+    #[test]
+    fn hull_drops_collinear_and_duplicate_points() {
+        let points = vec![(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (2.0, 0.0), (1.0, 1.0)];
+        assert_eq!(
+            convex_hull(points),
+            vec![(0.0, 0.0), (2.0, 0.0), (1.0, 1.0)]
+        );
+    }
+
+    #[test]
+    fn hull_of_fewer_than_three_points_is_the_points() {
+        assert_eq!(convex_hull(vec![(1.0, 1.0), (1.0, 1.0)]), vec![(1.0, 1.0)]);
+        assert_eq!(
+            convex_hull(vec![(1.0, 1.0), (0.0, 0.0)]),
+            vec![(0.0, 0.0), (1.0, 1.0)]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use serde_json::json;
+
+use crate::artifacts::{check_file, expand_tilde};
+
+#[derive(Args, Debug)]
+pub struct LoadTestArgs {
+    /// Path of query file, one `from_lon,from_lat,to_lon,to_lat` per line
+    #[arg(short, long)]
+    pub queries_path: PathBuf,
+    /// Base URL of a running `serve`
+    #[arg(long, default_value = "http://localhost:3030")]
+    pub endpoint: String,
+    /// Number of requests in flight at the same time
+    #[arg(long, default_value_t = 16)]
+    pub concurrency: usize,
+    /// Total number of requests, cycling through the queries
+    #[arg(long, default_value_t = 1000)]
+    pub requests: usize,
+}
+
+impl LoadTestArgs {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let Err(error) = check_file(&expand_tilde(&self.queries_path)) {
+            errors.push(format!("--queries-path: {}", error));
+        }
+        if !self.endpoint.starts_with("http://") && !self.endpoint.starts_with("https://") {
+            errors.push(format!(
+                "--endpoint: '{}' is not an http(s) URL",
+                self.endpoint
+            ));
+        }
+        if self.concurrency == 0 {
+            errors.push("--concurrency: must be at least 1".to_string());
+        }
+        errors
+    }
+}
+
+/// `(from, to)` of a query, both as `(lon, lat)`.
+type Query = ((f64, f64), (f64, f64));
+
+/// Sends POST /route requests from `concurrency` clients at once and prints throughput and
+/// latency percentiles. Running it with `--concurrency 1` and the default gives how much
+/// the server gains from answering requests in parallel.
+pub async fn load_test(args: &LoadTestArgs) {
+    let queries: Arc<Vec<Query>> = Arc::new(
+        BufReader::new(File::open(expand_tilde(&args.queries_path)).unwrap())
+            .lines()
+            .map(Result::unwrap)
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let values: Vec<f64> = line
+                    .split(',')
+                    .map(|value| value.trim().parse().unwrap())
+                    .collect();
+                ((values[0], values[1]), (values[2], values[3]))
+            })
+            .collect(),
+    );
+    assert!(!queries.is_empty(), "no queries in query file");
+
+    let url = format!("{}/route", args.endpoint.trim_end_matches('/'));
+    let client = reqwest::Client::new();
+    let next = Arc::new(AtomicUsize::new(0));
+    let requests = args.requests;
+
+    let start = Instant::now();
+    let workers: Vec<_> = (0..args.concurrency)
+        .map(|_| {
+            let (queries, url, client, next) =
+                (queries.clone(), url.clone(), client.clone(), next.clone());
+            tokio::spawn(async move {
+                let mut latencies = Vec::new();
+                let mut failures = 0;
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= requests {
+                        break;
+                    }
+                    let (from, to) = queries[i % queries.len()];
+                    let body = json!({ "from": from, "to": to, "coordinate_order": "lon_lat" });
+                    let sent = Instant::now();
+                    match client.post(&url).json(&body).send().await {
+                        Ok(response) if !response.status().is_server_error() => {
+                            // 404 for unconnected queries is still a computed answer
+                            let _ = response.bytes().await;
+                            latencies.push(sent.elapsed());
+                        }
+                        _ => failures += 1,
+                    }
+                }
+                (latencies, failures)
+            })
+        })
+        .collect();
+
+    let mut latencies = Vec::new();
+    let mut failures = 0;
+    for worker in workers {
+        let (worker_latencies, worker_failures) = worker.await.unwrap();
+        latencies.extend(worker_latencies);
+        failures += worker_failures;
+    }
+    let elapsed = start.elapsed();
+    latencies.sort();
+
+    println!(
+        "{} requests with concurrency {} in {:.2}s: {:.1} requests/s, {} failed",
+        requests,
+        args.concurrency,
+        elapsed.as_secs_f64(),
+        latencies.len() as f64 / elapsed.as_secs_f64(),
+        failures
+    );
+    if latencies.is_empty() {
+        return;
+    }
+    let percentile =
+        |p: f64| -> Duration { latencies[((latencies.len() - 1) as f64 * p).round() as usize] };
+    println!(
+        "latency p50 {:?}, p95 {:?}, p99 {:?}, max {:?}",
+        percentile(0.5),
+        percentile(0.95),
+        percentile(0.99),
+        latencies[latencies.len() - 1]
+    );
+}
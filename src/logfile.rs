@@ -0,0 +1,116 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+
+use crate::error::RoutingError;
+
+/// How often the log file is started anew, counted from when it was opened.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum LogRotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl LogRotation {
+    fn period(self) -> Option<Duration> {
+        match self {
+            LogRotation::Never => None,
+            LogRotation::Hourly => Some(Duration::from_secs(60 * 60)),
+            LogRotation::Daily => Some(Duration::from_secs(24 * 60 * 60)),
+        }
+    }
+}
+
+/// Log file `server.log` in `dir` that is renamed to `server.<unix milliseconds>.log` once it
+/// exceeds `max_bytes` or is older than the rotation period. Only the `retain` newest of
+/// those are kept.
+pub struct RollingLog {
+    dir: PathBuf,
+    max_bytes: Option<u64>,
+    period: Option<Duration>,
+    retain: usize,
+    file: File,
+    bytes: u64,
+    opened: Instant,
+}
+
+impl RollingLog {
+    pub fn open(
+        dir: &Path,
+        max_bytes: Option<u64>,
+        rotation: LogRotation,
+        retain: usize,
+    ) -> Result<RollingLog, RoutingError> {
+        fs::create_dir_all(dir).map_err(|error| RoutingError::File(error.to_string()))?;
+        let path = dir.join("server.log");
+        let file = append(&path)
+            .map_err(|error| RoutingError::File(format!("{}: {}", path.display(), error)))?;
+        let bytes = file.metadata().map_or(0, |metadata| metadata.len());
+        Ok(RollingLog {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            period: rotation.period(),
+            retain,
+            file,
+            bytes,
+            opened: Instant::now(),
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis());
+        let current = self.dir.join("server.log");
+        fs::rename(&current, self.dir.join(format!("server.{}.log", millis)))?;
+        self.file = append(&current)?;
+        self.bytes = 0;
+        self.opened = Instant::now();
+
+        let mut rotated: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| {
+                let name = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or("");
+                name.starts_with("server.") && name.ends_with(".log") && name != "server.log"
+            })
+            .collect();
+        // timestamps of equal length sort by time
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.retain);
+        for path in rotated.into_iter().take(excess) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for RollingLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let too_large = matches!(self.max_bytes, Some(max) if self.bytes + buf.len() as u64 > max);
+        let too_old = matches!(self.period, Some(period) if self.opened.elapsed() >= period);
+        if (too_large && self.bytes > 0) || too_old {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
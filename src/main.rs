@@ -1,128 +1,160 @@
-use std::{collections::HashMap, fs::File, io::BufReader, sync::Arc, time::Instant};
+use std::{path::PathBuf, sync::Arc};
+
+use bundle::BundleArgs;
+use cache::WarmCacheArgs;
+use clap::{Parser, Subcommand};
+use compare::CompareExternalArgs;
+use connectivity::ConnectivityArgs;
+use dedup::DedupArgs;
+use engine::Engine;
+use evaluation::{DijkstraRankArgs, ReportArgs};
+use fixtures::ReplayArgs;
+use loadtest::LoadTestArgs;
+use server::{LogFormat, ServeArgs};
+use tiles::IsochroneTilesArgs;
+
+mod artifacts;
+mod assign;
+mod bundle;
+mod cache;
+mod canary;
+mod chaos;
+mod compare;
+mod config;
+mod connectivity;
+mod debug;
+mod dedup;
+mod dijkstra;
+mod drive;
+mod emissions;
+mod engine;
+mod error;
+mod evaluation;
+mod fixtures;
+mod geo;
+mod geohash;
+mod geojson;
+mod graph;
+mod isochrone;
+mod loadtest;
+mod logfile;
+mod memory;
+mod metrics;
+mod mirror;
+mod pareto;
+mod places;
+mod polyline;
+mod pools;
+mod regions;
+mod response;
+mod server;
+mod snap;
+mod storage;
+mod tiles;
+mod vrp;
+mod yen;
 
-use faster_paths::{
-    ch::{
-        ch_path_finder::ChPathFinder,
-        shortcut_replacer::{
-            fast_shortcut_replacer::FastShortcutReplacer,
-            slow_shortcut_replacer::SlowShortcutReplacer, ShortcutReplacer,
-        },
-        ContractedGraphInformation,
-    },
-    graphs::path::{PathFinding, ShortestPathRequest},
-    hl::{hub_graph::HubGraph, hub_graph_path_finder::HubGraphPathFinder},
-};
-use osm_converter::sphere::{
-    geometry::{linestring::Linestring, planet::Planet, point::Point},
-    graph::graph::Fmi,
-    spatial_partition::point_spatial_partition::PointSpatialPartition,
-};
-use serde::{Deserialize, Serialize};
-use warp::{http::Response, Filter};
-
-use clap::Parser;
-
-/// Starts a routing service on localhost:3030/route
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-struct Args {
-    /// Path of .fmi file
-    #[arg(short, long)]
-    gr_path: String,
-    /// Path of .fmi file
-    #[arg(short, long)]
-    co_path: String,
-    /// Path of .fmi file
-    #[arg(short, long)]
-    ch_path: String,
-    /// Path of .fmi file
-    #[arg(short, long)]
-    hl_path: String,
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// TOML file of flags for the subcommand, e.g. `port = 8080`. Flags given on the command
+    /// line take precedence
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
 }
 
-#[derive(Deserialize, Serialize)]
-struct RouteRequest {
-    from: (f64, f64), // lon, lat
-    to: (f64, f64),   // lon, lat
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Starts a routing service, by default on localhost:3030/route
+    Serve(Box<ServeArgs>),
+    /// Writes query times by Dijkstra rank as CSV
+    DijkstraRank(DijkstraRankArgs),
+    /// Reports query times of Dijkstra, CH and HL as Markdown and JSON
+    Bench(ReportArgs),
+    /// Checks CH and HL against Dijkstra on random queries
+    Verify(ReportArgs),
+    /// Compares routes against an external OSRM, GraphHopper or Valhalla service
+    CompareExternal(CompareExternalArgs),
+    /// Precomputes routes for popular queries into a file for `serve --warm-cache`
+    WarmCache(WarmCacheArgs),
+    /// Creates or inspects a single-file bundle of all artifacts
+    Bundle(BundleArgs),
+    /// Merges vertices with duplicate coordinates and compacts vertex ids in .gr/.co files
+    Dedup(DedupArgs),
+    /// Measures throughput and latency of a running server under concurrent /route requests
+    LoadTest(LoadTestArgs),
+    /// Suggests connector edges between components that are almost connected, as GeoJSON
+    Connectivity(ConnectivityArgs),
+    /// Precomputes isochrones on a grid of origins into a file for `serve --isochrone-tiles`
+    IsochroneTiles(IsochroneTilesArgs),
+    /// Replays requests recorded by `serve --record-dir` and checks the answers are unchanged
+    Replay(ReplayArgs),
+}
+
+impl Command {
+    fn validate(&self) -> Vec<String> {
+        match self {
+            Command::Serve(args) => args.validate(),
+            Command::DijkstraRank(args) => args.validate(),
+            Command::Bench(args) | Command::Verify(args) => args.validate(),
+            Command::CompareExternal(args) => args.validate(),
+            Command::WarmCache(args) => args.validate(),
+            Command::Bundle(args) => args.validate(),
+            Command::Dedup(args) => args.validate(),
+            Command::LoadTest(args) => args.validate(),
+            Command::Connectivity(args) => args.validate(),
+            Command::IsochroneTiles(args) => args.validate(),
+            Command::Replay(args) => args.validate(),
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    let args = Args::parse();
-
-    let cors = warp::cors()
-        .allow_any_origin()
-        .allow_headers(vec!["Content-Type"])
-        .allow_methods(vec!["GET", "POST", "OPTIONS"]);
-
-    let coordinates_graph = Arc::new(Fmi::from_gr_co_file(
-        args.gr_path.as_str(),
-        args.co_path.as_str(),
-    ));
-
-    let mut point_grid = PointSpatialPartition::new_root(10);
-    point_grid.add_points(&coordinates_graph.points);
-
-    let mut point_id_map = HashMap::new();
-    for (id, point) in coordinates_graph.points.iter().cloned().enumerate() {
-        point_id_map.insert(point, id as usize);
+    let args = config::args_with_config(std::env::args_os().collect()).unwrap_or_else(|error| {
+        eprintln!("invalid configuration:");
+        eprintln!("  - {}", error);
+        std::process::exit(2);
+    });
+    let cli = Cli::parse_from(args);
+    if let Some(path) = &cli.config {
+        println!("flags from {}", path.display());
     }
-    let point_grid = Arc::new(point_grid);
-    let point_id_map = Arc::new(point_id_map);
-
-    // ch
-    let reader = BufReader::new(File::open(args.ch_path).unwrap());
-    let ch_information: ContractedGraphInformation = bincode::deserialize_from(reader).unwrap();
-    let shortcut_replacer: Box<dyn ShortcutReplacer + Send + Sync> =
-        Box::new(SlowShortcutReplacer::new(&ch_information.shortcuts));
-    let ch_path_finder = ChPathFinder::new(ch_information.ch_graph, shortcut_replacer);
 
-    // hl
-    let fast_shortcut_replacer: Box<dyn ShortcutReplacer> =
-        Box::new(FastShortcutReplacer::new(&ch_information.shortcuts));
-    let reader = BufReader::new(File::open(args.hl_path).unwrap());
-    let hl: HubGraph = bincode::deserialize_from(reader).unwrap();
-    let _hl_path_finder = HubGraphPathFinder::new(hl, fast_shortcut_replacer);
-
-    let path_finder: Arc<Box<dyn PathFinding>> = Arc::new(Box::new(ch_path_finder));
-
-    println!("ready");
-
-    let promote = {
-        warp::post()
-            .and(warp::path("route"))
-            .and(warp::body::json())
-            .map(move |route_request: RouteRequest| {
-                let from_point = Point::from_coordinate(route_request.from.1, route_request.from.0);
-                let nearest_from_proint = point_grid.get_nearest(&from_point).unwrap();
-                let from = *point_id_map.get(&nearest_from_proint).unwrap() as u32;
-
-                let to_point = Point::from_coordinate(route_request.to.1, route_request.to.0);
-                let nearest_to_proint = point_grid.get_nearest(&to_point).unwrap();
-                let to = *point_id_map.get(&nearest_to_proint).unwrap() as u32;
-
-                let request = ShortestPathRequest::new(from, to).unwrap();
-                let start = Instant::now();
-                let pathx = path_finder.get_shortest_path(&request).unwrap();
-                let time = start.elapsed();
-
-                let ids = pathx.vertices;
-                let path = coordinates_graph.convert_path(&ids);
-                let linestring = Linestring::new(path);
-                let mut planet = Planet::new();
-                planet.linestrings.push(linestring);
+    let errors = cli.command.validate();
+    if !errors.is_empty() {
+        artifacts::exit_invalid(errors);
+    }
 
-                println!(
-                    "route_request: {:>7} -> {:>7}, cost: {:>9}, took: {:>3}ms",
-                    from,
-                    to,
-                    pathx.weight,
-                    time.as_millis()
-                );
-                Response::builder().body(format!("{}", planet.to_geojson_str()))
-            })
-            .with(cors)
-    };
+    match &cli.command {
+        Command::Serve(args) => server::init_logging(args),
+        // loading the artifacts logs through tracing in every command
+        _ => server::init_tracing(LogFormat::Text, None),
+    }
 
-    warp::serve(promote).run(([127, 0, 0, 1], 3030)).await;
+    match cli.command {
+        Command::Serve(args) => {
+            let paths = args.artifacts.resolve_or_exit();
+            let errors = args.validate_hl(&paths);
+            if !errors.is_empty() {
+                artifacts::exit_invalid(errors);
+            }
+            let engine = Arc::new(Engine::load_or_exit(&paths));
+            println!("ready");
+            server::serve(engine, *args).await;
+        }
+        Command::DijkstraRank(args) => evaluation::dijkstra_rank(&args),
+        Command::Bench(args) => evaluation::bench(&args),
+        Command::Verify(args) => evaluation::verify(&args),
+        Command::CompareExternal(args) => compare::compare_external(&args).await,
+        Command::WarmCache(args) => cache::warm_cache(&args),
+        Command::Bundle(args) => bundle::bundle(&args),
+        Command::Dedup(args) => dedup::dedup(&args),
+        Command::LoadTest(args) => loadtest::load_test(&args).await,
+        Command::Connectivity(args) => connectivity::connectivity(&args),
+        Command::IsochroneTiles(args) => tiles::isochrone_tiles(&args),
+        Command::Replay(args) => fixtures::replay(&args).await,
+    }
 }
@@ -0,0 +1,64 @@
+use std::{fs, path::Path};
+
+use crate::artifacts::ArtifactPaths;
+
+/// Parses sizes like `512M`, `4G` or `1073741824`.
+pub fn parse_bytes(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, factor) = match value.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&value[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&value[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&value[..i], 1 << 30),
+        Some((i, 't' | 'T')) => (&value[..i], 1 << 40),
+        _ => (value, 1),
+    };
+    let number = number
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| format!("'{}' is not a size like 512M or 4G", value))?;
+    number
+        .checked_mul(factor)
+        .ok_or_else(|| format!("'{}' does not fit into 64 bits", value))
+}
+
+/// Rough resident size of the loaded artifacts, derived from the file sizes.
+pub struct MemoryEstimate {
+    /// Coordinates, the raw graph (read twice: for osm_converter and for our adjacency) and
+    /// the spatial partition.
+    pub base: u64,
+    pub ch: u64,
+    /// The hub labels plus the fast shortcut replacer only HL needs.
+    pub hl: u64,
+}
+
+impl MemoryEstimate {
+    pub fn new(paths: &ArtifactPaths) -> MemoryEstimate {
+        let size = |path: &Path| fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        let (gr, co, ch) = (
+            size(&paths.gr_path),
+            size(&paths.co_path),
+            size(&paths.ch_path),
+        );
+        let hl = paths.hl_path.as_deref().map(size).unwrap_or(0);
+        MemoryEstimate {
+            base: 2 * gr + 2 * co,
+            ch,
+            hl: if hl > 0 { hl + ch } else { 0 },
+        }
+    }
+}
+
+/// Decides whether HL fits into `max_memory`. The bincode artifacts deserialize into owned
+/// structures, so they can only be kept in RAM or left out; memory-mapping them is not
+/// possible.
+pub fn fits_hl(estimate: &MemoryEstimate, max_memory: u64) -> Result<bool, String> {
+    let required = estimate.base + estimate.ch;
+    if required > max_memory {
+        return Err(format!(
+            "graph and CH need about {} MiB, more than the budget of {} MiB",
+            required >> 20,
+            max_memory >> 20
+        ));
+    }
+    Ok(required + estimate.hl <= max_memory)
+}
@@ -0,0 +1,250 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+
+/// Seconds of request rate and latency kept for the dashboard.
+const HISTORY_SECONDS: usize = 120;
+const SLOW_REQUEST: Duration = Duration::from_millis(100);
+const MAX_SLOW_REQUESTS: usize = 20;
+/// Upper bounds of the histogram buckets in seconds.
+const BUCKETS: [f64; 12] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0,
+];
+
+/// Prometheus histogram of durations.
+#[derive(Default)]
+pub struct Histogram {
+    /// Count per bucket of `BUCKETS`, not cumulative, plus one for everything above.
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+    total_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(BUCKETS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.total_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn write_prometheus(&self, name: &str, help: &str, out: &mut String) {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} histogram\n",
+            name, help, name
+        ));
+        let mut count = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            count += bucket.load(Ordering::Relaxed);
+            let bound = BUCKETS
+                .get(i)
+                .map_or("+Inf".to_string(), |bound| bound.to_string());
+            out.push_str(&format!("{}_bucket{{le=\"{}\"}} {}\n", name, bound, count));
+        }
+        let seconds = self.total_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        out.push_str(&format!(
+            "{}_sum {}\n{}_count {}\n",
+            name, seconds, name, count
+        ));
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Second {
+    second: u64,
+    requests: u64,
+    total_micros: u64,
+    max_micros: u64,
+}
+
+struct SlowRequest {
+    /// Unix time in milliseconds.
+    at: u64,
+    method: String,
+    path: String,
+    status: u16,
+    micros: u64,
+}
+
+/// Request counts and latencies of the running server, recorded for every response.
+pub struct Metrics {
+    started: Instant,
+    requests: AtomicU64,
+    errors: AtomicU64,
+    seconds: Mutex<VecDeque<Second>>,
+    slow: Mutex<VecDeque<SlowRequest>>,
+    pub snap: Histogram,
+    /// Path searches including the shortcut unpacking the path finders do internally.
+    pub query: Histogram,
+    /// Building the GeoJSON feature of a found path.
+    pub geometry: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Metrics {
+        Metrics {
+            started: Instant::now(),
+            requests: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            seconds: Mutex::new(VecDeque::new()),
+            slow: Mutex::new(VecDeque::new()),
+            snap: Histogram::default(),
+            query: Histogram::default(),
+            geometry: Histogram::default(),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn record(&self, method: &str, path: &str, status: u16, elapsed: Duration) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if status >= 500 {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let micros = elapsed.as_micros() as u64;
+        let second = self.started.elapsed().as_secs();
+
+        let mut seconds = self.seconds.lock().unwrap();
+        if !matches!(seconds.back(), Some(last) if last.second == second) {
+            seconds.push_back(Second {
+                second,
+                ..Second::default()
+            });
+            while seconds.len() > HISTORY_SECONDS {
+                seconds.pop_front();
+            }
+        }
+        let current = seconds.back_mut().unwrap();
+        current.requests += 1;
+        current.total_micros += micros;
+        current.max_micros = current.max_micros.max(micros);
+        drop(seconds);
+
+        if elapsed >= SLOW_REQUEST {
+            let mut slow = self.slow.lock().unwrap();
+            slow.push_front(SlowRequest {
+                at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_millis() as u64),
+                method: method.to_string(),
+                path: path.to_string(),
+                status,
+                micros,
+            });
+            slow.truncate(MAX_SLOW_REQUESTS);
+        }
+    }
+
+    /// Totals, one history entry per second (oldest first, zeros for idle seconds) and the
+    /// latest slow requests.
+    pub fn to_json(&self) -> Value {
+        let now = self.started.elapsed().as_secs();
+        let seconds = self.seconds.lock().unwrap();
+        let first = now.saturating_sub(HISTORY_SECONDS as u64 - 1);
+        let history: Vec<Value> = (first..=now)
+            .map(|second| {
+                let entry = seconds
+                    .iter()
+                    .find(|entry| entry.second == second)
+                    .copied()
+                    .unwrap_or_default();
+                json!({
+                    "requests": entry.requests,
+                    "mean_micros": entry.total_micros.checked_div(entry.requests).unwrap_or(0),
+                    "max_micros": entry.max_micros,
+                })
+            })
+            .collect();
+        drop(seconds);
+
+        let slow: Vec<Value> = self
+            .slow
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                json!({
+                    "at": request.at,
+                    "method": request.method,
+                    "path": request.path,
+                    "status": request.status,
+                    "micros": request.micros,
+                })
+            })
+            .collect();
+
+        json!({
+            "uptime_seconds": now,
+            "requests": self.requests.load(Ordering::Relaxed),
+            "errors": self.errors.load(Ordering::Relaxed),
+            "resident_bytes": resident_bytes(),
+            "history": history,
+            "slow_threshold_micros": SLOW_REQUEST.as_micros() as u64,
+            "slow": slow,
+        })
+    }
+
+    /// Text exposition format for GET /metrics.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        for (name, help, value) in [
+            (
+                "fapra_requests_total",
+                "Answered HTTP requests.",
+                self.requests.load(Ordering::Relaxed),
+            ),
+            (
+                "fapra_errors_total",
+                "Requests answered with a 5xx status.",
+                self.errors.load(Ordering::Relaxed),
+            ),
+        ] {
+            out.push_str(&format!(
+                "# HELP {} {}\n# TYPE {} counter\n{} {}\n",
+                name, help, name, name, value
+            ));
+        }
+        self.snap.write_prometheus(
+            "fapra_snap_seconds",
+            "Time to snap a coordinate to the graph.",
+            &mut out,
+        );
+        self.query.write_prometheus(
+            "fapra_query_seconds",
+            "Time of a path search including shortcut unpacking.",
+            &mut out,
+        );
+        self.geometry.write_prometheus(
+            "fapra_geometry_seconds",
+            "Time to build the geometry and properties of a route.",
+            &mut out,
+        );
+        if let Some(bytes) = resident_bytes() {
+            out.push_str(&format!(
+                "# HELP fapra_resident_bytes Resident memory of the process.\n\
+                 # TYPE fapra_resident_bytes gauge\nfapra_resident_bytes {}\n",
+                bytes
+            ));
+        }
+        out
+    }
+}
+
+/// Resident set size of the process, `None` where /proc is not available.
+pub fn resident_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib << 10)
+}
@@ -0,0 +1,145 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::engine::{path_request, Engine};
+
+pub enum MirrorTarget {
+    /// Answers the mirrored requests with the hub labels.
+    Algorithm,
+    /// Sends the mirrored requests to a second server, e.g. one running on new artifacts.
+    Instance {
+        url: String,
+        client: reqwest::Client,
+    },
+}
+
+/// Replays a fraction of the /route requests against a second target in the background and
+/// logs every result that differs from the one served.
+pub struct Mirror {
+    /// Bits of the `f64` fraction, so it can be changed at runtime.
+    fraction: AtomicU64,
+    target: MirrorTarget,
+    counter: AtomicU64,
+}
+
+impl Mirror {
+    pub fn new(fraction: f64, url: Option<String>) -> Mirror {
+        let target = match url {
+            Some(url) => MirrorTarget::Instance {
+                url: url.trim_end_matches('/').to_string(),
+                client: reqwest::Client::new(),
+            },
+            None => MirrorTarget::Algorithm,
+        };
+        Mirror {
+            fraction: AtomicU64::new(fraction.to_bits()),
+            target,
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    /// Request n is mirrored iff floor((n + 1) * fraction) > floor(n * fraction), which spreads
+    /// the mirrored requests evenly instead of sampling them randomly.
+    fn should_mirror(&self) -> bool {
+        let fraction = self.fraction();
+        if fraction <= 0.0 {
+            return false;
+        }
+        let n = self.counter.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * fraction).floor() > (n * fraction).floor()
+    }
+
+    pub fn fraction(&self) -> f64 {
+        f64::from_bits(self.fraction.load(Ordering::Relaxed))
+    }
+
+    pub fn set_fraction(&self, fraction: f64) {
+        self.fraction.store(fraction.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Whether mirrored requests are answered locally with HL.
+    pub fn targets_algorithm(&self) -> bool {
+        matches!(self.target, MirrorTarget::Algorithm)
+    }
+
+    pub fn mirror(
+        &self,
+        engine: &Arc<Engine>,
+        route_request: &impl Serialize,
+        from: u32,
+        to: u32,
+        weight: u32,
+        body: &str,
+    ) {
+        if !self.should_mirror() {
+            return;
+        }
+
+        match &self.target {
+            MirrorTarget::Algorithm => {
+                let engine = engine.clone();
+                tokio::task::spawn_blocking(move || {
+                    let Some(hl) = &engine.hl else {
+                        return;
+                    };
+                    let request = match path_request(from, to) {
+                        Ok(request) => request,
+                        Err(error) => {
+                            tracing::warn!("mirror: {}", error);
+                            return;
+                        }
+                    };
+                    let mirror_weight = hl.get_shortest_path(&request).map(|path| path.weight);
+                    if mirror_weight != Some(weight) {
+                        tracing::info!(
+                            "mirror diff: {:>7} -> {:>7}, served: {:>9}, hl: {:>9?}",
+                            from,
+                            to,
+                            weight,
+                            mirror_weight
+                        );
+                    }
+                });
+            }
+            MirrorTarget::Instance { url, client } => {
+                let request = client.post(format!("{}/route", url)).json(route_request);
+                let body = body.to_string();
+                tokio::spawn(async move {
+                    let mirror_body = match request.send().await {
+                        Ok(response) => response.text().await,
+                        Err(error) => Err(error),
+                    };
+                    match mirror_body {
+                        Ok(mirror_body)
+                            if without_metadata(&mirror_body) == without_metadata(&body) => {}
+                        Ok(mirror_body) => tracing::info!(
+                            "mirror diff: {:>7} -> {:>7}, response differs ({} vs. {} bytes)",
+                            from,
+                            to,
+                            body.len(),
+                            mirror_body.len()
+                        ),
+                        Err(error) => {
+                            tracing::warn!("mirror failed: {:>7} -> {:>7}, {}", from, to, error)
+                        }
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// The response without its `metadata`, which differs between any two answers by compute
+/// time and between instances by graph version.
+pub fn without_metadata(body: &str) -> Option<Value> {
+    let mut value: Value = serde_json::from_str(body).ok()?;
+    if let Some(object) = value.as_object_mut() {
+        object.remove("metadata");
+    }
+    Some(value)
+}
@@ -0,0 +1,271 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::{dijkstra::distances_to, error::RoutingError, graph::Graph};
+
+/// Upper bound on created labels, multicriteria searches can explode on large graphs.
+const MAX_LABELS: usize = 5_000_000;
+
+#[derive(Clone, Debug)]
+pub struct ParetoRoute {
+    pub vertices: Vec<u32>,
+    pub weight: u32,
+    pub length: u32,
+}
+
+struct Label {
+    vertex: u32,
+    weight: u32,
+    length: u32,
+    predecessor: Option<usize>,
+    dominated: bool,
+}
+
+/// Minimizes the weight among all paths whose length is at most `max_length`.
+pub fn constrained_shortest_path(
+    graph: &Graph,
+    edge_lengths: &[u32],
+    length_bound: impl Fn(u32) -> u32,
+    source: u32,
+    target: u32,
+    max_length: u32,
+) -> Result<Option<ParetoRoute>, RoutingError> {
+    let routes = search(
+        graph,
+        edge_lengths,
+        length_bound,
+        source,
+        target,
+        max_length,
+        1,
+    )?;
+    Ok(routes.into_iter().next())
+}
+
+/// Returns up to `max_routes` routes of the Pareto frontier of (weight, length), ordered by
+/// increasing weight.
+pub fn pareto_routes(
+    graph: &Graph,
+    edge_lengths: &[u32],
+    length_bound: impl Fn(u32) -> u32,
+    source: u32,
+    target: u32,
+    max_routes: usize,
+) -> Result<Vec<ParetoRoute>, RoutingError> {
+    search(
+        graph,
+        edge_lengths,
+        length_bound,
+        source,
+        target,
+        u32::MAX,
+        max_routes,
+    )
+}
+
+/// Bicriteria label-setting search ordered by weight plus the exact remaining weight from a
+/// backward Dijkstra, so labels reach `target` in order of increasing weight. `length_bound`
+/// is a lower bound on the remaining length; with it, labels that exceed `max_length` or are
+/// dominated by an already found route are pruned early.
+fn search(
+    graph: &Graph,
+    edge_lengths: &[u32],
+    length_bound: impl Fn(u32) -> u32,
+    source: u32,
+    target: u32,
+    max_length: u32,
+    max_routes: usize,
+) -> Result<Vec<ParetoRoute>, RoutingError> {
+    let mut routes: Vec<ParetoRoute> = Vec::new();
+    let potentials = distances_to(graph, target);
+    if potentials[source as usize] == u32::MAX || max_routes == 0 {
+        return Ok(routes);
+    }
+
+    let mut labels = vec![Label {
+        vertex: source,
+        weight: 0,
+        length: 0,
+        predecessor: None,
+        dominated: false,
+    }];
+    let mut bags: Vec<Vec<usize>> = vec![Vec::new(); graph.number_of_vertices()];
+    bags[source as usize].push(0);
+    let mut queue = BinaryHeap::new();
+    queue.push(Reverse((potentials[source as usize], 0u32, 0usize)));
+
+    let is_dominated_by_routes = |routes: &[ParetoRoute], weight: u32, length: u32| {
+        routes
+            .iter()
+            .any(|route| route.weight <= weight && route.length <= length)
+    };
+
+    while let Some(Reverse((weight_estimate, length, label_id))) = queue.pop() {
+        let vertex = labels[label_id].vertex;
+        let length_estimate = length.saturating_add(length_bound(vertex));
+        if labels[label_id].dominated
+            || is_dominated_by_routes(&routes, weight_estimate, length_estimate)
+        {
+            continue;
+        }
+        if vertex == target {
+            routes.push(route(&labels, label_id));
+            if routes.len() == max_routes {
+                break;
+            }
+            continue;
+        }
+
+        let weight = labels[label_id].weight;
+        for &edge_id in graph.out_edges(vertex).iter() {
+            let edge = &graph.edges[edge_id as usize];
+            let potential = potentials[edge.target as usize];
+            if potential == u32::MAX {
+                continue;
+            }
+            let new_weight = weight.saturating_add(edge.weight);
+            let new_length = length.saturating_add(edge_lengths[edge_id as usize]);
+            let new_length_estimate = new_length.saturating_add(length_bound(edge.target));
+            if new_length_estimate > max_length
+                || is_dominated_by_routes(
+                    &routes,
+                    new_weight.saturating_add(potential),
+                    new_length_estimate,
+                )
+            {
+                continue;
+            }
+
+            let bag = &mut bags[edge.target as usize];
+            if bag.iter().any(|&other| {
+                labels[other].weight <= new_weight && labels[other].length <= new_length
+            }) {
+                continue;
+            }
+            bag.retain(|&other| {
+                let is_dominated =
+                    new_weight <= labels[other].weight && new_length <= labels[other].length;
+                if is_dominated {
+                    labels[other].dominated = true;
+                }
+                !is_dominated
+            });
+
+            if labels.len() >= MAX_LABELS {
+                return Err(RoutingError::SearchAborted(format!(
+                    "search aborted after {} labels",
+                    MAX_LABELS
+                )));
+            }
+            labels.push(Label {
+                vertex: edge.target,
+                weight: new_weight,
+                length: new_length,
+                predecessor: Some(label_id),
+                dominated: false,
+            });
+            bag.push(labels.len() - 1);
+            queue.push(Reverse((
+                new_weight.saturating_add(potential),
+                new_length,
+                labels.len() - 1,
+            )));
+        }
+    }
+
+    Ok(routes)
+}
+
+fn route(labels: &[Label], label_id: usize) -> ParetoRoute {
+    let mut vertices = Vec::new();
+    let mut current = Some(label_id);
+    while let Some(id) = current {
+        vertices.push(labels[id].vertex);
+        current = labels[id].predecessor;
+    }
+    vertices.reverse();
+
+    ParetoRoute {
+        vertices,
+        weight: labels[label_id].weight,
+        length: labels[label_id].length,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Edge;
+
+    /// Edges as `(source, target, weight, length)`.
+    fn graph(edges: &[(u32, u32, u32, u32)]) -> (Graph, Vec<u32>) {
+        let lengths = edges.iter().map(|edge| edge.3).collect();
+        let edges = edges
+            .iter()
+            .map(|&(source, target, weight, _)| Edge {
+                source,
+                target,
+                weight,
+            })
+            .collect();
+        (Graph::from_edges(edges, 0), lengths)
+    }
+
+    /// A fast long way and a slow short one, and a way worse than both.
+    fn two_ways() -> (Graph, Vec<u32>) {
+        graph(&[
+            (0, 1, 1, 10),
+            (1, 2, 1, 10),
+            (0, 2, 5, 5),
+            (0, 3, 3, 15),
+            (3, 2, 3, 15),
+        ])
+    }
+
+    #[test]
+    fn finds_the_frontier_by_weight() {
+        let (graph, lengths) = two_ways();
+        let routes = pareto_routes(&graph, &lengths, |_| 0, 0, 2, 10).unwrap();
+        let found: Vec<(Vec<u32>, u32, u32)> = routes
+            .into_iter()
+            .map(|route| (route.vertices, route.weight, route.length))
+            .collect();
+        assert_eq!(found, vec![(vec![0, 1, 2], 2, 20), (vec![0, 2], 5, 5)]);
+    }
+
+    #[test]
+    fn stops_after_max_routes() {
+        let (graph, lengths) = two_ways();
+        let routes = pareto_routes(&graph, &lengths, |_| 0, 0, 2, 1).unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].weight, 2);
+    }
+
+    #[test]
+    fn respects_the_length_limit() {
+        let (graph, lengths) = two_ways();
+        let within = |max_length| {
+            constrained_shortest_path(&graph, &lengths, |_| 0, 0, 2, max_length)
+                .unwrap()
+                .map(|route| route.vertices)
+        };
+        assert_eq!(within(20), Some(vec![0, 1, 2]));
+        assert_eq!(within(19), Some(vec![0, 2]));
+        assert_eq!(within(4), None);
+    }
+
+    #[test]
+    fn finds_nothing_without_a_path() {
+        let (graph, lengths) = two_ways();
+        assert!(constrained_shortest_path(&graph, &lengths, |_| 0, 2, 0, 10)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn finds_no_frontier_without_a_path() {
+        let (graph, lengths) = two_ways();
+        assert!(pareto_routes(&graph, &lengths, |_| 0, 2, 0, 10)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,87 @@
+use std::{collections::BTreeMap, fs::File, io::BufReader, path::Path, sync::RwLock};
+
+use serde_json::Value;
+
+use crate::{error::RoutingError, geo::CoordinateOrder};
+
+/// Named coordinates that requests can refer to as `@name`.
+#[derive(Default)]
+pub struct Places {
+    places: RwLock<BTreeMap<String, (f64, f64)>>,
+}
+
+impl Places {
+    /// Reads a JSON object of `name: [lon, lat]`.
+    pub fn from_json_file(path: &Path) -> Result<Places, RoutingError> {
+        let file = File::open(path).map_err(|error| RoutingError::File(error.to_string()))?;
+        let places: BTreeMap<String, (f64, f64)> = serde_json::from_reader(BufReader::new(file))
+            .map_err(|error| RoutingError::File(error.to_string()))?;
+        for name in places.keys() {
+            check_name(name).map_err(|error| RoutingError::File(error.to_string()))?;
+        }
+        Ok(Places {
+            places: RwLock::new(places),
+        })
+    }
+
+    /// Parses `@name` or a `x,y` pair in `order` into `(lon, lat)`.
+    pub fn resolve(&self, value: &str, order: CoordinateOrder) -> Result<(f64, f64), RoutingError> {
+        if let Some(name) = value.strip_prefix('@') {
+            return self
+                .places
+                .read()
+                .unwrap()
+                .get(name)
+                .copied()
+                .ok_or_else(|| RoutingError::InvalidRequest(format!("unknown place '@{}'", name)));
+        }
+        let values: Vec<f64> = value
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| {
+                RoutingError::InvalidRequest(format!(
+                    "'{}' is neither @name nor a coordinate pair",
+                    value
+                ))
+            })?;
+        match values.as_slice() {
+            [x, y] => Ok(order.to_lon_lat((*x, *y))),
+            _ => Err(RoutingError::InvalidRequest(format!(
+                "'{}' is neither @name nor a coordinate pair",
+                value
+            ))),
+        }
+    }
+
+    pub fn set(&self, name: &str, coordinate: (f64, f64)) -> Result<(), RoutingError> {
+        check_name(name)?;
+        self.places
+            .write()
+            .unwrap()
+            .insert(name.to_string(), coordinate);
+        Ok(())
+    }
+
+    pub fn remove(&self, name: &str) -> bool {
+        self.places.write().unwrap().remove(name).is_some()
+    }
+
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(&*self.places.read().unwrap()).unwrap()
+    }
+}
+
+fn check_name(name: &str) -> Result<(), RoutingError> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(RoutingError::InvalidRequest(format!(
+            "place name '{}' may only contain letters, digits, '_' and '-'",
+            name
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use crate::error::RoutingError;
+
+/// Decodes a Google encoded polyline into `(lon, lat)` coordinates. `precision` is the number
+/// of decimal places, 5 for the original format and 6 for polyline6.
+pub fn decode(encoded: &str, precision: u32) -> Result<Vec<(f64, f64)>, RoutingError> {
+    let factor = 10f64.powi(precision as i32);
+    let mut bytes = encoded.bytes();
+    let mut coordinates = Vec::new();
+    let (mut lat, mut lon) = (0i64, 0i64);
+
+    while let Some(delta_lat) = decode_value(&mut bytes)? {
+        let delta_lon = decode_value(&mut bytes)?.ok_or_else(|| {
+            RoutingError::InvalidRequest("polyline ends after a latitude".to_string())
+        })?;
+        lat += delta_lat;
+        lon += delta_lon;
+        coordinates.push((lon as f64 / factor, lat as f64 / factor));
+    }
+
+    Ok(coordinates)
+}
+
+fn decode_value(bytes: &mut impl Iterator<Item = u8>) -> Result<Option<i64>, RoutingError> {
+    let mut result = 0i64;
+    let mut shift = 0;
+    loop {
+        let Some(byte) = bytes.next() else {
+            return if shift == 0 {
+                Ok(None)
+            } else {
+                Err(RoutingError::InvalidRequest(
+                    "polyline ends inside a value".to_string(),
+                ))
+            };
+        };
+        if !(63..=126).contains(&byte) {
+            return Err(RoutingError::InvalidRequest(format!(
+                "invalid polyline character {:?}",
+                byte as char
+            )));
+        }
+        // seven chunks are 35 bits, more than any coordinate delta needs
+        if shift > 30 {
+            return Err(RoutingError::InvalidRequest(
+                "polyline value is too long".to_string(),
+            ));
+        }
+        let chunk = (byte - 63) as i64;
+        result |= (chunk & 0x1f) << shift;
+        shift += 5;
+        if chunk < 0x20 {
+            break;
+        }
+    }
+
+    Ok(Some(if result & 1 == 1 {
+        !(result >> 1)
+    } else {
+        result >> 1
+    }))
+}
+
+/// Encodes `(lon, lat)` coordinates as a Google encoded polyline, the inverse of `decode`.
+pub fn encode(coordinates: &[(f64, f64)], precision: u32) -> String {
+    let factor = 10f64.powi(precision as i32);
+    let mut encoded = String::new();
+    let (mut previous_lat, mut previous_lon) = (0i64, 0i64);
+    for &(lon, lat) in coordinates {
+        let (lat, lon) = ((lat * factor).round() as i64, (lon * factor).round() as i64);
+        encode_value(lat - previous_lat, &mut encoded);
+        encode_value(lon - previous_lon, &mut encoded);
+        (previous_lat, previous_lon) = (lat, lon);
+    }
+    encoded
+}
+
+fn encode_value(value: i64, encoded: &mut String) {
+    let mut value = if value < 0 { !(value << 1) } else { value << 1 };
+    while value >= 0x20 {
+        encoded.push((((value & 0x1f) | 0x20) as u8 + 63) as char);
+        value >>= 5;
+    }
+    encoded.push((value as u8 + 63) as char);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The example of the format description, as `(lon, lat)`.
+    const EXAMPLE: &str = "_p~iF~ps|U_ulLnnqC_mqNvxq`@";
+    const EXAMPLE_COORDINATES: [(f64, f64); 3] =
+        [(-120.2, 38.5), (-120.95, 40.7), (-126.453, 43.252)];
+
+    #[test]
+    fn decodes_the_example() {
+        assert_eq!(decode(EXAMPLE, 5).unwrap(), EXAMPLE_COORDINATES);
+    }
+
+    #[test]
+    fn encodes_the_example() {
+        assert_eq!(encode(&EXAMPLE_COORDINATES, 5), EXAMPLE);
+    }
+
+    #[test]
+    fn round_trips_with_six_decimals() {
+        let coordinates = [(9.123456, 48.654321), (-0.000001, -89.999999)];
+        assert_eq!(decode(&encode(&coordinates, 6), 6).unwrap(), coordinates);
+    }
+
+    #[test]
+    fn decodes_the_empty_polyline() {
+        assert_eq!(decode("", 5).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn rejects_malformed_polylines() {
+        assert_eq!(
+            decode("_p~iF", 5).unwrap_err().to_string(),
+            "polyline ends after a latitude"
+        );
+        assert_eq!(
+            decode("_p~", 5).unwrap_err().to_string(),
+            "polyline ends inside a value"
+        );
+        assert_eq!(
+            decode("_p iF~ps|U", 5).unwrap_err().to_string(),
+            "invalid polyline character ' '"
+        );
+    }
+
+    #[test]
+    fn rejects_values_that_overflow() {
+        // every chunk has the continuation bit set, so the shift would pass 63
+        let encoded = "~".repeat(20);
+        assert_eq!(
+            decode(&encoded, 5).unwrap_err().to_string(),
+            "polyline value is too long"
+        );
+    }
+}
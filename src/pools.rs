@@ -0,0 +1,114 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Condvar, Mutex,
+    },
+    thread,
+};
+
+use serde_json::{json, Value};
+
+/// Caps how many threads work on one stage of request handling at the same time. Requests
+/// beyond the size wait for a free slot, so a burst of one kind of work, e.g. matrix path
+/// searches, cannot take all cores from the others.
+pub struct Pool {
+    size: usize,
+    busy: Mutex<usize>,
+    freed: Condvar,
+    /// Callers waiting for a slot.
+    waiting: AtomicUsize,
+}
+
+impl Pool {
+    pub fn new(size: usize) -> Pool {
+        Pool {
+            size: size.max(1),
+            busy: Mutex::new(0),
+            freed: Condvar::new(),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Runs `work` on the calling thread once a slot is free.
+    pub fn run<R>(&self, work: impl FnOnce() -> R) -> R {
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let mut busy = self
+            .freed
+            .wait_while(self.busy.lock().unwrap(), |busy| *busy >= self.size)
+            .unwrap();
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+        *busy += 1;
+        drop(busy);
+
+        struct Slot<'a>(&'a Pool);
+        impl Drop for Slot<'_> {
+            // also frees the slot if `work` panics
+            fn drop(&mut self) {
+                *self.0.busy.lock().unwrap() -= 1;
+                self.0.freed.notify_one();
+            }
+        }
+        let _slot = Slot(self);
+        work()
+    }
+
+    /// Size, occupied slots and waiting callers.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "size": self.size,
+            "busy": *self.busy.lock().unwrap(),
+            "waiting": self.waiting.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Applies `work` to every item on up to `size` threads, keeping the order.
+    pub fn map<T: Sync, R: Send>(&self, items: &[T], work: impl Fn(&T) -> R + Sync) -> Vec<R> {
+        if items.len() <= 1 || self.size == 1 {
+            return items.iter().map(|item| self.run(|| work(item))).collect();
+        }
+        let chunk_size = items.len().div_ceil(self.size);
+        thread::scope(|scope| {
+            let handles: Vec<_> = items
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let work = &work;
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|item| self.run(|| work(item)))
+                            .collect::<Vec<R>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+}
+
+/// Pools for the stages of a request: snapping coordinates, searching paths and writing
+/// response bodies.
+pub struct Pools {
+    pub snap: Pool,
+    pub route: Pool,
+    pub serialize: Pool,
+}
+
+impl Pools {
+    /// A size of `None` means one thread per core.
+    pub fn new(snap: Option<usize>, route: Option<usize>, serialize: Option<usize>) -> Pools {
+        let cores = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        Pools {
+            snap: Pool::new(snap.unwrap_or(cores)),
+            route: Pool::new(route.unwrap_or(cores)),
+            serialize: Pool::new(serialize.unwrap_or(cores)),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use serde_json::{json, Value};
+
+use crate::{error::RoutingError, geo::DistanceModel};
+
+struct Region {
+    name: String,
+    /// Rings of all polygons, outer rings and holes alike, as `(lon, lat)`.
+    rings: Vec<Vec<(f64, f64)>>,
+    /// `(min_lon, min_lat, max_lon, max_lat)`
+    bounding_box: (f64, f64, f64, f64),
+}
+
+impl Region {
+    /// Even-odd rule over all rings, which also handles holes.
+    fn contains(&self, (lon, lat): (f64, f64)) -> bool {
+        let (min_lon, min_lat, max_lon, max_lat) = self.bounding_box;
+        if lon < min_lon || lon > max_lon || lat < min_lat || lat > max_lat {
+            return false;
+        }
+        let mut inside = false;
+        for ring in self.rings.iter() {
+            for (i, &(lon_i, lat_i)) in ring.iter().enumerate() {
+                let (lon_j, lat_j) = ring[(i + ring.len() - 1) % ring.len()];
+                if (lat_i > lat) != (lat_j > lat)
+                    && lon < (lon_j - lon_i) * (lat - lat_i) / (lat_j - lat_i) + lon_i
+                {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+}
+
+/// Administrative regions from a GeoJSON FeatureCollection of Polygon and MultiPolygon
+/// features, named by their `name` property.
+pub struct Regions {
+    regions: Vec<Region>,
+}
+
+impl Regions {
+    pub fn from_geojson_file(path: &Path) -> Result<Regions, RoutingError> {
+        let file = File::open(path).map_err(|error| RoutingError::File(error.to_string()))?;
+        let geojson: Value = serde_json::from_reader(BufReader::new(file))
+            .map_err(|error| RoutingError::File(error.to_string()))?;
+        let features = geojson["features"]
+            .as_array()
+            .ok_or_else(|| RoutingError::File("not a FeatureCollection".to_string()))?;
+
+        let mut regions = Vec::new();
+        for (i, feature) in features.iter().enumerate() {
+            let name = feature["properties"]["name"]
+                .as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("region {}", i));
+            let geometry = &feature["geometry"];
+            let polygons = match geometry["type"].as_str() {
+                Some("Polygon") => vec![geometry["coordinates"].clone()],
+                Some("MultiPolygon") => geometry["coordinates"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default(),
+                _ => {
+                    return Err(RoutingError::File(format!(
+                        "feature {} is not a (Multi)Polygon",
+                        i
+                    )))
+                }
+            };
+
+            let mut rings = Vec::new();
+            for polygon in polygons.iter() {
+                for ring in polygon.as_array().into_iter().flatten() {
+                    let ring: Vec<(f64, f64)> = ring
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|position| Some((position[0].as_f64()?, position[1].as_f64()?)))
+                        .collect();
+                    if ring.len() >= 3 {
+                        rings.push(ring);
+                    }
+                }
+            }
+            let bounding_box = rings.iter().flatten().fold(
+                (f64::INFINITY, f64::INFINITY, -f64::INFINITY, -f64::INFINITY),
+                |(min_lon, min_lat, max_lon, max_lat), &(lon, lat)| {
+                    (
+                        min_lon.min(lon),
+                        min_lat.min(lat),
+                        max_lon.max(lon),
+                        max_lat.max(lat),
+                    )
+                },
+            );
+            regions.push(Region {
+                name,
+                rings,
+                bounding_box,
+            });
+        }
+        Ok(Regions { regions })
+    }
+
+    /// Whether any of the regions contains the `(lon, lat)`.
+    pub fn contains(&self, coordinate: (f64, f64)) -> bool {
+        self.regions
+            .iter()
+            .any(|region| region.contains(coordinate))
+    }
+
+    /// Regions in the order the line passes through them, each with the length in meters
+    /// of the segments whose midpoint lies inside it. Segments outside every region are
+    /// left out. A region entered twice appears twice.
+    pub fn traversed(&self, model: DistanceModel, coordinates: &[(f64, f64)]) -> Value {
+        let mut traversed: Vec<(usize, f64)> = Vec::new();
+        for pair in coordinates.windows(2) {
+            let midpoint = ((pair[0].0 + pair[1].0) / 2.0, (pair[0].1 + pair[1].1) / 2.0);
+            let Some(region) = self.regions.iter().position(|r| r.contains(midpoint)) else {
+                continue;
+            };
+            let length = model.distance(pair[0], pair[1]);
+            match traversed.last_mut() {
+                Some((last, total)) if *last == region => *total += length,
+                _ => traversed.push((region, length)),
+            }
+        }
+        traversed
+            .into_iter()
+            .map(|(region, length)| {
+                json!({ "name": self.regions[region].name, "length": length.round() })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::polyline;
+
+/// Serialization of a route response, chosen with `format=` or the `Accept` header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// The FeatureCollection with all properties.
+    #[default]
+    Geojson,
+    /// Google encoded polyline with 5 decimals, one line per part of the geometry.
+    Polyline,
+    /// Like `Polyline`, with 6 decimals.
+    Polyline6,
+    /// GPX 1.1 with one track per feature and one segment per part of its geometry.
+    Gpx,
+    /// A single JSON array of `[lon, lat]` pairs.
+    Coordinates,
+}
+
+impl Format {
+    /// The format the `Accept` header asks for, if it names one besides JSON.
+    pub fn from_accept(accept: &str) -> Option<Format> {
+        let accept = accept.to_ascii_lowercase();
+        if accept.contains("application/gpx+xml") {
+            Some(Format::Gpx)
+        } else if accept.contains("application/geo+json") {
+            Some(Format::Geojson)
+        } else {
+            None
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Geojson | Format::Coordinates => "application/json",
+            Format::Polyline | Format::Polyline6 => "text/plain",
+            Format::Gpx => "application/gpx+xml",
+        }
+    }
+}
+
+/// Body of `collection` in `format`. Apart from GeoJSON only the geometry is kept; legs that
+/// continue where the previous one ended are joined into one line.
+pub fn render(collection: &Value, format: Format) -> String {
+    match format {
+        Format::Geojson => collection.to_string(),
+        Format::Polyline | Format::Polyline6 => {
+            let precision = if format == Format::Polyline { 5 } else { 6 };
+            joined_lines(collection)
+                .iter()
+                .map(|line| polyline::encode(line, precision))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        Format::Coordinates => {
+            let coordinates: Vec<(f64, f64)> =
+                joined_lines(collection).into_iter().flatten().collect();
+            serde_json::to_string(&coordinates).unwrap()
+        }
+        Format::Gpx => gpx(collection),
+    }
+}
+
+fn gpx(collection: &Value) -> String {
+    let mut gpx = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <gpx version=\"1.1\" creator=\"fapra_submission\" \
+         xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+    );
+    for feature in features(collection) {
+        gpx.push_str("<trk>\n");
+        for line in feature_lines(feature) {
+            gpx.push_str("<trkseg>\n");
+            for (lon, lat) in line {
+                gpx.push_str(&format!("<trkpt lat=\"{}\" lon=\"{}\"/>\n", lat, lon));
+            }
+            gpx.push_str("</trkseg>\n");
+        }
+        gpx.push_str("</trk>\n");
+    }
+    gpx.push_str("</gpx>\n");
+    gpx
+}
+
+fn features(collection: &Value) -> impl Iterator<Item = &Value> {
+    collection["features"].as_array().into_iter().flatten()
+}
+
+/// Parts of a LineString or MultiLineString geometry, none for a null geometry.
+fn feature_lines(feature: &Value) -> Vec<Vec<(f64, f64)>> {
+    let geometry = &feature["geometry"];
+    let line = |value: &Value| -> Vec<(f64, f64)> {
+        value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|pair| Some((pair[0].as_f64()?, pair[1].as_f64()?)))
+            .collect()
+    };
+    match geometry["type"].as_str() {
+        Some("LineString") => vec![line(&geometry["coordinates"])],
+        Some("MultiLineString") => geometry["coordinates"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(line)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn joined_lines(collection: &Value) -> Vec<Vec<(f64, f64)>> {
+    let mut lines: Vec<Vec<(f64, f64)>> = Vec::new();
+    for line in features(collection).flat_map(feature_lines) {
+        match lines.last_mut() {
+            Some(last) if !line.is_empty() && last.last() == line.first() => {
+                last.extend_from_slice(&line[1..])
+            }
+            _ if line.is_empty() => {}
+            _ => lines.push(line),
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Map;
+
+    use super::*;
+    use crate::geojson::{feature_collection, linestring_feature};
+
+    /// Two legs, the second starting where the first ends.
+    fn legs() -> Value {
+        feature_collection(vec![
+            linestring_feature(&[(8.0, 48.0), (8.5, 48.25)], Map::new()),
+            linestring_feature(&[(8.5, 48.25), (9.0, 48.5)], Map::new()),
+        ])
+    }
+
+    #[test]
+    fn renders_geojson() {
+        assert_eq!(
+            render(&legs(), Format::Geojson),
+            r#"{"features":[{"geometry":{"coordinates":[[8.0,48.0],[8.5,48.25]],"type":"LineString"},"properties":{},"type":"Feature"},{"geometry":{"coordinates":[[8.5,48.25],[9.0,48.5]],"type":"LineString"},"properties":{},"type":"Feature"}],"type":"FeatureCollection"}"#
+        );
+    }
+
+    #[test]
+    fn renders_joined_legs_as_one_polyline() {
+        assert_eq!(
+            render(&legs(), Format::Polyline),
+            "__~cH_oyo@oyo@_t`Boyo@_t`B"
+        );
+        assert_eq!(
+            render(&legs(), Format::Polyline6),
+            "__upzA__hgN_hgN_qo]_hgN_qo]"
+        );
+    }
+
+    #[test]
+    fn renders_separate_legs_as_separate_polylines() {
+        let collection = feature_collection(vec![
+            linestring_feature(&[(8.0, 48.0), (8.5, 48.25)], Map::new()),
+            linestring_feature(&[(9.0, 48.5), (8.5, 48.25)], Map::new()),
+        ]);
+        let rendered = render(&collection, Format::Polyline);
+        assert_eq!(rendered.lines().count(), 2);
+        assert_eq!(
+            polyline::decode(rendered.lines().nth(1).unwrap(), 5).unwrap(),
+            vec![(9.0, 48.5), (8.5, 48.25)]
+        );
+    }
+
+    #[test]
+    fn renders_coordinates() {
+        assert_eq!(
+            render(&legs(), Format::Coordinates),
+            "[[8.0,48.0],[8.5,48.25],[9.0,48.5]]"
+        );
+    }
+
+    #[test]
+    fn renders_gpx_with_one_track_per_feature() {
+        assert_eq!(
+            render(&legs(), Format::Gpx),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <gpx version=\"1.1\" creator=\"fapra_submission\" \
+             xmlns=\"http://www.topografix.com/GPX/1/1\">\n\
+             <trk>\n<trkseg>\n\
+             <trkpt lat=\"48\" lon=\"8\"/>\n\
+             <trkpt lat=\"48.25\" lon=\"8.5\"/>\n\
+             </trkseg>\n</trk>\n\
+             <trk>\n<trkseg>\n\
+             <trkpt lat=\"48.25\" lon=\"8.5\"/>\n\
+             <trkpt lat=\"48.5\" lon=\"9\"/>\n\
+             </trkseg>\n</trk>\n\
+             </gpx>\n"
+        );
+    }
+
+    #[test]
+    fn picks_the_format_from_the_accept_header() {
+        assert_eq!(
+            Format::from_accept("application/GPX+xml, */*"),
+            Some(Format::Gpx)
+        );
+        assert_eq!(
+            Format::from_accept("application/geo+json"),
+            Some(Format::Geojson)
+        );
+        assert_eq!(Format::from_accept("application/json"), None);
+    }
+}
@@ -0,0 +1,3299 @@
+use std::{
+    convert::Infallible,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use clap::{Args, ValueEnum};
+use faster_paths::graphs::path::ShortestPathRequest;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tokio_stream::{wrappers::IntervalStream, StreamExt};
+use warp::{
+    filters::BoxedFilter,
+    http::{Response, StatusCode},
+    sse::Event,
+    Filter, Rejection, Reply,
+};
+
+use crate::{
+    artifacts::{check_file, expand_tilde, ArtifactArgs, ArtifactPaths},
+    assign::{assign, AssignRequest},
+    cache::{load_warm_cache, CachedRoute, RouteCache},
+    canary::{Arm, Canary},
+    chaos::Chaos,
+    debug,
+    dijkstra::distances_around,
+    drive::{positions, DriveQuery},
+    emissions::EmissionsModel,
+    engine::{path_request, Engine, SharedEngine},
+    error::RoutingError,
+    fixtures::{Fixture, Recorder},
+    geo::{
+        lon_lat, looks_swapped, merge_collinear, path_length, vertex_coordinates, CoordinateOrder,
+        DistanceRange, Viewport,
+    },
+    geohash,
+    geojson::{
+        feature_collection, lines_feature, linestring_feature, round, round_coordinate, Waypoint,
+        WaypointInput,
+    },
+    isochrone::{h3_cells, isochrones, IsochroneRequest, MAX_BANDS},
+    logfile::{LogRotation, RollingLog},
+    memory::{parse_bytes, MemoryEstimate},
+    metrics::{resident_bytes, Metrics},
+    mirror::Mirror,
+    pareto::{constrained_shortest_path, pareto_routes},
+    places::Places,
+    polyline,
+    pools::Pools,
+    regions::Regions,
+    response::{render, Format},
+    snap::{Snap, SnapTarget},
+    tiles::{load_isochrone_tiles, IsochroneTiles},
+    vrp::{solve, VrpRequest, MAX_STOPS},
+    yen::k_shortest_paths,
+};
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    #[command(flatten)]
+    pub artifacts: ArtifactArgs,
+    /// Address to listen on, e.g. 0.0.0.0 inside a container
+    #[arg(long, default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST))]
+    pub host: IpAddr,
+    /// Port to listen on
+    #[arg(long, default_value_t = 3030)]
+    pub port: u16,
+    /// Origin allowed by CORS, can be repeated. Without it, any origin is allowed
+    #[arg(long)]
+    pub cors_origin: Vec<String>,
+    /// Largest accepted request body, e.g. 1M
+    #[arg(long, value_parser = parse_bytes, default_value = "1M")]
+    pub max_body_size: u64,
+    /// Fraction of /route requests that are mirrored and compared in the background
+    #[arg(long, default_value_t = 0.0)]
+    pub mirror_fraction: f64,
+    /// Base URL of a second instance to mirror to. Without it, requests are mirrored to HL
+    #[arg(long)]
+    pub mirror_url: Option<String>,
+    /// Percentage of /route requests without `algorithm` answered with HL instead of CH.
+    /// Defaults to 100 if an .hl file is loaded and 0 otherwise
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub hl_percentage: Option<u8>,
+    /// Order of coordinate pairs in /route bodies that do not set `coordinate_order`
+    #[arg(long, value_enum, default_value_t = CoordinateOrder::LonLat)]
+    pub coordinate_order: CoordinateOrder,
+    /// Number of routes kept in memory, 0 disables the cache
+    #[arg(long, default_value_t = 0)]
+    pub route_cache_size: usize,
+    /// Directory that routes evicted from the cache are written to and read back from
+    #[arg(long)]
+    pub route_cache_dir: Option<PathBuf>,
+    /// Size up to which --route-cache-dir is filled, the oldest routes are deleted first, e.g. 1G
+    #[arg(long, value_parser = parse_bytes, default_value = "1G")]
+    pub route_cache_dir_size: u64,
+    /// Cache file written by `warm-cache`, its routes are always answered from memory
+    #[arg(long)]
+    pub warm_cache: Option<PathBuf>,
+    /// Directory every POST /route request is written to with its response, for `replay`
+    #[arg(long)]
+    pub record_dir: Option<PathBuf>,
+    /// File written by `isochrone-tiles`, served by GET /isochrone/tile
+    #[arg(long)]
+    pub isochrone_tiles: Option<PathBuf>,
+    /// GeoJSON file of administrative boundaries. Routes then list the regions they pass
+    /// through with the distance in each
+    #[arg(long)]
+    pub regions_path: Option<PathBuf>,
+    /// JSON object of g CO2 per km by vehicle type. Routes then carry a `co2_g` estimate
+    #[arg(long)]
+    pub emissions_model: Option<PathBuf>,
+    /// JSON object of `name: [lon, lat]`, usable as `@name` in GET /route
+    #[arg(long)]
+    pub places_path: Option<PathBuf>,
+    /// GeoJSON (Multi)Polygon file. Requests with an endpoint outside of it are rejected
+    #[arg(long)]
+    pub service_area: Option<PathBuf>,
+    /// Meters. Coordinates whose snapped point is farther away are answered with 404
+    #[arg(long)]
+    pub max_snap_distance: Option<f64>,
+    /// Threads snapping coordinates at the same time. Defaults to one per core
+    #[arg(long)]
+    pub snap_threads: Option<usize>,
+    /// Threads searching paths at the same time, also the parallelism of /table, /assign and
+    /// /vrp matrices. Defaults to one per core
+    #[arg(long)]
+    pub route_threads: Option<usize>,
+    /// Threads writing route responses at the same time. Defaults to one per core
+    #[arg(long)]
+    pub serialize_threads: Option<usize>,
+    /// Milliseconds after which a request is answered with 504. Without it, requests may take
+    /// as long as they need
+    #[arg(long)]
+    pub request_timeout_ms: Option<u64>,
+    /// Log lines as text or JSON. The level is set with RUST_LOG and defaults to info
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+    /// File a diagnostic report is written to on SIGUSR1. Without it, the report is logged
+    #[arg(long)]
+    pub diagnostics_path: Option<PathBuf>,
+    /// Directory to write the log to instead of stdout, as server.log plus rotated files
+    #[arg(long)]
+    pub log_dir: Option<PathBuf>,
+    /// Size after which the log file is rotated, e.g. 100M
+    #[arg(long, value_parser = parse_bytes)]
+    pub log_max_size: Option<u64>,
+    /// Time after which the log file is rotated
+    #[arg(long, value_enum, default_value_t = LogRotation::Daily)]
+    pub log_rotation: LogRotation,
+    /// Number of rotated log files kept, older ones are deleted
+    #[arg(long, default_value_t = 7)]
+    pub log_retain: usize,
+    /// Milliseconds of latency added to a path search at --chaos-latency-rate
+    #[arg(long, hide = true, default_value_t = 0)]
+    pub chaos_latency_ms: u64,
+    /// Fraction of path searches delayed by --chaos-latency-ms
+    #[arg(long, hide = true, default_value_t = 0.0)]
+    pub chaos_latency_rate: f64,
+    /// Fraction of snaps answered as too far from the road
+    #[arg(long, hide = true, default_value_t = 0.0)]
+    pub chaos_snap_failure_rate: f64,
+    /// Fraction of path searches answered with a 500
+    #[arg(long, hide = true, default_value_t = 0.0)]
+    pub chaos_algorithm_error_rate: f64,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl ServeArgs {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (flag, threads) in [
+            ("--snap-threads", self.snap_threads),
+            ("--route-threads", self.route_threads),
+            ("--serialize-threads", self.serialize_threads),
+        ] {
+            if threads == Some(0) {
+                errors.push(format!("{}: must be at least 1", flag));
+            }
+        }
+        errors.extend(self.artifacts.validate());
+        for (flag, fraction) in [
+            ("--mirror-fraction", self.mirror_fraction),
+            ("--chaos-latency-rate", self.chaos_latency_rate),
+            ("--chaos-snap-failure-rate", self.chaos_snap_failure_rate),
+            (
+                "--chaos-algorithm-error-rate",
+                self.chaos_algorithm_error_rate,
+            ),
+        ] {
+            if !(0.0..=1.0).contains(&fraction) {
+                errors.push(format!("{}: must be between 0 and 1", flag));
+            }
+        }
+        if let Some(url) = &self.mirror_url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                errors.push(format!("--mirror-url: '{}' is not an http(s) URL", url));
+            }
+        }
+        for (flag, path) in [
+            ("--warm-cache", &self.warm_cache),
+            ("--isochrone-tiles", &self.isochrone_tiles),
+            ("--regions-path", &self.regions_path),
+            ("--emissions-model", &self.emissions_model),
+            ("--places-path", &self.places_path),
+            ("--service-area", &self.service_area),
+        ] {
+            if let Some(path) = path {
+                if let Err(error) = check_file(&expand_tilde(path)) {
+                    errors.push(format!("{}: {}", flag, error));
+                }
+            }
+        }
+        if let Some(distance) = self.max_snap_distance {
+            if distance.is_nan() || distance <= 0.0 {
+                errors.push("--max-snap-distance: must be positive".to_string());
+            }
+        }
+        if self.log_dir.is_none() && self.log_max_size.is_some() {
+            errors.push("--log-max-size: needs --log-dir".to_string());
+        }
+        if self.route_cache_dir.is_some() && self.route_cache_size == 0 {
+            errors.push("--route-cache-dir: needs a --route-cache-size above 0".to_string());
+        }
+        errors
+    }
+
+    /// Flags that need HL, checked once the artifacts are resolved, as --max-memory or a
+    /// bundle decide whether there is an .hl file.
+    pub fn validate_hl(&self, paths: &ArtifactPaths) -> Vec<String> {
+        let mut errors = Vec::new();
+        if paths.hl_path.is_some() {
+            return errors;
+        }
+        if self.hl_percentage.unwrap_or(0) > 0 {
+            errors.push("--hl-percentage: needs HL, but no .hl file is loaded".to_string());
+        }
+        if self.mirror_fraction > 0.0 && self.mirror_url.is_none() {
+            errors.push(
+                "--mirror-fraction: mirroring to HL needs an .hl file, pass --mirror-url or \
+                 --hl-path"
+                    .to_string(),
+            );
+        }
+        errors
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct RouteRequest {
+    from: (f64, f64),
+    to: (f64, f64),
+    #[serde(default, skip_serializing)]
+    coordinate_order: Option<CoordinateOrder>,
+}
+
+/// Query of GET /route. Endpoints are `@name` or a coordinate pair in the server's default
+/// order.
+#[derive(Deserialize)]
+struct PlaceRouteQuery {
+    from: String,
+    to: String,
+    max_cost: Option<u32>,
+    algorithm: Option<Arm>,
+    #[serde(default, deserialize_with = "deserialize_viewport")]
+    viewport: Option<Viewport>,
+    #[serde(default, deserialize_with = "deserialize_geometry_range")]
+    geometry_range: Option<DistanceRange>,
+    #[serde(default, deserialize_with = "deserialize_annotations")]
+    annotations: Annotations,
+    /// Response format, see `Format`. Defaults to the one the `Accept` header names, then
+    /// GeoJSON.
+    format: Option<Format>,
+    #[serde(default)]
+    full_geometry: bool,
+}
+
+#[derive(Deserialize)]
+struct IdRouteQuery {
+    from: u32,
+    to: u32,
+    max_cost: Option<u32>,
+    algorithm: Option<Arm>,
+    #[serde(default, deserialize_with = "deserialize_viewport")]
+    viewport: Option<Viewport>,
+    #[serde(default, deserialize_with = "deserialize_geometry_range")]
+    geometry_range: Option<DistanceRange>,
+    #[serde(default, deserialize_with = "deserialize_annotations")]
+    annotations: Annotations,
+    /// Response format, see `Format`. Defaults to the one the `Accept` header names, then
+    /// GeoJSON.
+    format: Option<Format>,
+    /// Keep every vertex in the geometry instead of merging collinear segments.
+    #[serde(default)]
+    full_geometry: bool,
+}
+
+const MAX_K: usize = 10;
+
+const MAX_PARETO_ROUTES: usize = 50;
+
+#[derive(Deserialize)]
+struct ParetoRouteQuery {
+    max_routes: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct ConstrainedRouteQuery {
+    /// Meters
+    max_length: u32,
+}
+
+#[derive(Deserialize)]
+struct KRouteQuery {
+    k: usize,
+}
+
+const MAX_SAMPLES: usize = 1000;
+
+/// Query of POST /route/sample, exactly one of the fields.
+#[derive(Deserialize)]
+struct SampleQuery {
+    /// 0 at the start of the route, 1 at its end.
+    fraction: Option<f64>,
+    /// Meters from the start of the route.
+    distance: Option<f64>,
+    /// Number of points splitting the route into `samples + 1` parts of equal length.
+    samples: Option<usize>,
+}
+
+/// Body of POST /route/evaluate, a vertex sequence as returned by /route/ids.
+#[derive(Deserialize)]
+struct EvaluateRequest {
+    vertices: Vec<u32>,
+}
+
+/// Body of POST /route/pois.
+#[derive(Deserialize)]
+struct PoiRequest {
+    from: (f64, f64),
+    to: (f64, f64),
+    pois: Vec<(f64, f64)>,
+    /// Largest cost of leaving the route to a POI and coming back.
+    max_detour: u32,
+    #[serde(default)]
+    coordinate_order: Option<CoordinateOrder>,
+}
+
+const MAX_POIS: usize = 10_000;
+
+/// Body of POST /detour.
+#[derive(Deserialize)]
+struct DetourRequest {
+    /// Stops of the existing route in order, the first and last stay where they are.
+    stops: Vec<(f64, f64)>,
+    candidate: (f64, f64),
+    #[serde(default)]
+    coordinate_order: Option<CoordinateOrder>,
+}
+
+const MAX_DETOUR_STOPS: usize = 100;
+
+/// Body of POST /table.
+#[derive(Deserialize)]
+struct TableRequest {
+    sources: Vec<(f64, f64)>,
+    targets: Vec<(f64, f64)>,
+    #[serde(default)]
+    coordinate_order: Option<CoordinateOrder>,
+}
+
+const MAX_TABLE_CELLS: usize = 10_000;
+
+/// Body of POST /admin/reload. Artifacts left out are looked up as at startup.
+#[derive(Deserialize)]
+struct ReloadRequest {
+    data_dir: Option<PathBuf>,
+    bundle: Option<PathBuf>,
+    gr_path: Option<PathBuf>,
+    co_path: Option<PathBuf>,
+    ch_path: Option<PathBuf>,
+    hl_path: Option<PathBuf>,
+}
+
+/// Body of PUT /admin/config. Settings left out stay as they are.
+#[derive(Deserialize)]
+struct ConfigUpdate {
+    hl_percentage: Option<u8>,
+    mirror_fraction: Option<f64>,
+}
+
+/// Body of POST /reroute.
+#[derive(Deserialize)]
+struct RerouteRequest {
+    /// Vertices of the active route still ahead, ending at the destination.
+    remaining: Vec<u32>,
+    /// Current `(lon, lat)` of the vehicle.
+    position: (f64, f64),
+}
+
+/// Query parameters of POST /route, independent of the body format.
+#[derive(Default, Deserialize)]
+struct RouteOptions {
+    max_cost: Option<u32>,
+    /// Overrides the canary split, e.g. to benchmark CH path unpacking.
+    algorithm: Option<Arm>,
+    /// `min_lon,min_lat,max_lon,max_lat`. Only the geometry is clipped, the weight stays
+    /// that of the whole route.
+    #[serde(default, deserialize_with = "deserialize_viewport")]
+    viewport: Option<Viewport>,
+    /// `from_m,to_m`. Only the geometry between these distances along the route is returned,
+    /// e.g. to load very long routes piece by piece.
+    #[serde(default, deserialize_with = "deserialize_geometry_range")]
+    geometry_range: Option<DistanceRange>,
+    /// Comma separated extra properties, see `Annotations`.
+    #[serde(default, deserialize_with = "deserialize_annotations")]
+    annotations: Annotations,
+    /// Response format, see `Format`. Defaults to the one the `Accept` header names, then
+    /// GeoJSON.
+    format: Option<Format>,
+    #[serde(default)]
+    full_geometry: bool,
+}
+
+fn deserialize_viewport<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Viewport>, D::Error> {
+    let Some(value) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    Viewport::parse(&value)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Optional route properties that are too large to always include.
+#[derive(Clone, Copy, Default)]
+struct Annotations {
+    /// Edges of the path by their index among the arc lines of the .gr file, to join routes
+    /// against edge level data.
+    edge_ids: bool,
+}
+
+fn deserialize_annotations<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Annotations, D::Error> {
+    let mut annotations = Annotations::default();
+    let Some(value) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(annotations);
+    };
+    for annotation in value.split(',').map(str::trim) {
+        match annotation {
+            "edge_ids" => annotations.edge_ids = true,
+            "" => {}
+            _ => {
+                return Err(serde::de::Error::custom(format!(
+                    "unknown annotation '{}'",
+                    annotation
+                )))
+            }
+        }
+    }
+    Ok(annotations)
+}
+
+fn deserialize_geometry_range<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DistanceRange>, D::Error> {
+    let Some(value) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    DistanceRange::parse(&value)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+impl RoutingError {
+    /// The only place routing errors are mapped to HTTP.
+    fn status(&self) -> StatusCode {
+        match self {
+            RoutingError::SnapFailed { .. }
+            | RoutingError::Unreachable
+            | RoutingError::UnreachableLeg { .. }
+            | RoutingError::ExceedsBudget { .. }
+            | RoutingError::ExceedsLength { .. }
+            | RoutingError::NoTile(_)
+            | RoutingError::NotFound(_) => StatusCode::NOT_FOUND,
+            RoutingError::InvalidCoordinate { .. }
+            | RoutingError::OutsideServiceArea { .. }
+            | RoutingError::InvalidRequest(_)
+            | RoutingError::InvalidSettings(_)
+            | RoutingError::GraphMismatch(_)
+            | RoutingError::HlUnavailable => StatusCode::BAD_REQUEST,
+            RoutingError::SearchAborted(_)
+            | RoutingError::Unassignable(_)
+            | RoutingError::BrokenPath { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            RoutingError::Conflict(_) => StatusCode::CONFLICT,
+            RoutingError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            RoutingError::Internal(_) | RoutingError::File(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn body(&self) -> Value {
+        if let RoutingError::InvalidSettings(errors) = self {
+            return json!({ "errors": errors });
+        }
+        let mut body = json!({ "error": self.to_string() });
+        if let RoutingError::BrokenPath { index, .. } = self {
+            body["broken_at"] = (*index).into();
+        }
+        body
+    }
+
+    fn into_response(self) -> Result<Response<String>, warp::http::Error> {
+        Response::builder()
+            .status(self.status())
+            .header("Content-Type", "application/json")
+            .body(self.body().to_string())
+    }
+
+    fn into_json_reply(self) -> warp::reply::WithStatus<warp::reply::Json> {
+        warp::reply::with_status(warp::reply::json(&self.body()), self.status())
+    }
+}
+
+/// Body with an ordered list of waypoints, routed leg by leg.
+#[derive(Deserialize)]
+struct PointsRequest {
+    points: Vec<(f64, f64)>,
+    #[serde(default)]
+    coordinate_order: Option<CoordinateOrder>,
+}
+
+/// Body with geohashes or encoded polylines instead of coordinate pairs, e.g.
+/// `{"from": "u0wt8", "to": "u0wt9"}` or `{"points": "_p~iF~ps|U_ulLnnqC"}`.
+#[derive(Deserialize)]
+struct EncodedRequest {
+    from: Option<String>,
+    to: Option<String>,
+    points: Option<String>,
+    /// Without it, strings of up to 12 geohash digits are geohashes, anything else is a
+    /// polyline with 5 decimals.
+    #[serde(default)]
+    input_format: Option<InputFormat>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum InputFormat {
+    Geohash,
+    Polyline,
+    Polyline6,
+}
+
+impl EncodedRequest {
+    fn waypoints(&self) -> Result<Vec<Waypoint>, RoutingError> {
+        let coordinates = match (&self.from, &self.to, &self.points) {
+            (Some(from), Some(to), None) => {
+                let mut coordinates = Vec::new();
+                for (name, value) in [("from", from), ("to", to)] {
+                    let decoded = self.decode(value)?;
+                    if decoded.len() != 1 {
+                        return Err(RoutingError::InvalidRequest(format!(
+                            "{} must be a single point, got {}",
+                            name,
+                            decoded.len()
+                        )));
+                    }
+                    coordinates.push(decoded[0]);
+                }
+                coordinates
+            }
+            (None, None, Some(points)) => self.decode(points)?,
+            _ => {
+                return Err(RoutingError::InvalidRequest(
+                    "expected `from` and `to` or `points`".to_string(),
+                ))
+            }
+        };
+        Ok(coordinates
+            .into_iter()
+            .map(|coordinate| (coordinate, Value::Null))
+            .collect())
+    }
+
+    fn decode(&self, value: &str) -> Result<Vec<(f64, f64)>, RoutingError> {
+        let format = self.input_format.unwrap_or(if geohash::is_geohash(value) {
+            InputFormat::Geohash
+        } else {
+            InputFormat::Polyline
+        });
+        match format {
+            InputFormat::Geohash => Ok(vec![geohash::decode(value)?]),
+            InputFormat::Polyline => polyline::decode(value, 5),
+            InputFormat::Polyline6 => polyline::decode(value, 6),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RouteBody {
+    Coordinates(RouteRequest),
+    Points(PointsRequest),
+    GeoJson(WaypointInput),
+    /// Last, as every JSON object matches it.
+    Encoded(EncodedRequest),
+}
+
+/// Server state that does not depend on the loaded graph.
+struct ServerState {
+    mirror: Mirror,
+    canary: Canary,
+    coordinate_order: CoordinateOrder,
+    route_cache: RouteCache,
+    isochrone_tiles: Option<IsochroneTiles>,
+    regions: Option<Regions>,
+    emissions_model: Option<EmissionsModel>,
+    places: Places,
+    service_area: Option<Regions>,
+    max_snap_distance: Option<f64>,
+    /// Flags the engine was loaded with, the base of POST /admin/reload.
+    artifacts: ArtifactArgs,
+    metrics: Metrics,
+    pools: Pools,
+    chaos: Chaos,
+    recorder: Option<Recorder>,
+}
+
+impl ServerState {
+    /// Exits with 2 if one of the files the flags name cannot be read.
+    fn new(args: &ServeArgs, engine: &Engine) -> ServerState {
+        ServerState {
+            mirror: Mirror::new(args.mirror_fraction, args.mirror_url.clone()),
+            canary: Canary::new(args.hl_percentage.unwrap_or(if engine.hl.is_some() {
+                100
+            } else {
+                0
+            })),
+            coordinate_order: args.coordinate_order,
+            route_cache: RouteCache::new(
+                args.route_cache_size,
+                args.route_cache_dir.as_deref().map(expand_tilde),
+                args.route_cache_dir_size,
+                args.warm_cache
+                    .as_deref()
+                    .map(|path| load_warm_cache(&expand_tilde(path), engine.version))
+                    .unwrap_or_default(),
+            ),
+            isochrone_tiles: args
+                .isochrone_tiles
+                .as_deref()
+                .and_then(|path| load_isochrone_tiles(&expand_tilde(path), engine.version)),
+            regions: args.regions_path.as_deref().map(|path| {
+                Regions::from_geojson_file(&expand_tilde(path)).unwrap_or_else(|error| {
+                    eprintln!("--regions-path: {}", error);
+                    std::process::exit(2);
+                })
+            }),
+            emissions_model: args.emissions_model.as_deref().map(|path| {
+                EmissionsModel::from_json_file(&expand_tilde(path)).unwrap_or_else(|error| {
+                    eprintln!("--emissions-model: {}", error);
+                    std::process::exit(2);
+                })
+            }),
+            places: match args.places_path.as_deref() {
+                Some(path) => Places::from_json_file(&expand_tilde(path)).unwrap_or_else(|error| {
+                    eprintln!("--places-path: {}", error);
+                    std::process::exit(2);
+                }),
+                None => Places::default(),
+            },
+            service_area: args.service_area.as_deref().map(|path| {
+                Regions::from_geojson_file(&expand_tilde(path)).unwrap_or_else(|error| {
+                    eprintln!("--service-area: {}", error);
+                    std::process::exit(2);
+                })
+            }),
+            max_snap_distance: args.max_snap_distance,
+            artifacts: args.artifacts.clone(),
+            metrics: Metrics::default(),
+            pools: Pools::new(
+                args.snap_threads,
+                args.route_threads,
+                args.serialize_threads,
+            ),
+            chaos: Chaos {
+                latency: Duration::from_millis(args.chaos_latency_ms),
+                latency_rate: args.chaos_latency_rate,
+                snap_failure_rate: args.chaos_snap_failure_rate,
+                algorithm_error_rate: args.chaos_algorithm_error_rate,
+            },
+            recorder: args.record_dir.as_deref().map(|path| {
+                Recorder::new(expand_tilde(path)).unwrap_or_else(|error| {
+                    eprintln!("--record-dir: {}", error);
+                    std::process::exit(2);
+                })
+            }),
+        }
+    }
+}
+
+fn with_state<T: Clone + Send + Sync>(
+    state: T,
+) -> impl Filter<Extract = (T,), Error = Infallible> + Clone {
+    warp::any().map(move || state.clone())
+}
+
+/// The engine at the time the request arrives, so a reload never changes it mid-request.
+fn with_engine(
+    engines: Arc<SharedEngine>,
+) -> impl Filter<Extract = (Arc<Engine>,), Error = Infallible> + Clone {
+    warp::any().map(move || engines.current())
+}
+
+/// Sets up tracing for `serve`, before the artifacts are resolved and loaded so their
+/// messages go to the same log.
+pub fn init_logging(args: &ServeArgs) {
+    let log_file = args.log_dir.as_deref().map(|dir| {
+        RollingLog::open(
+            &expand_tilde(dir),
+            args.log_max_size,
+            args.log_rotation,
+            args.log_retain,
+        )
+        .unwrap_or_else(|error| {
+            eprintln!("--log-dir: {}", error);
+            std::process::exit(2);
+        })
+    });
+    init_tracing(args.log_format, log_file);
+}
+
+pub async fn serve(engine: Arc<Engine>, args: ServeArgs) {
+    let state = Arc::new(ServerState::new(&args, &engine));
+    if state.chaos.is_enabled() {
+        tracing::warn!("chaos flags are set, requests will fail or be delayed on purpose");
+    }
+
+    let engines = Arc::new(SharedEngine::new(engine));
+    #[cfg(unix)]
+    let diagnostics_state = state.clone();
+
+    let routes = filters(engines.clone(), state, &args);
+
+    #[cfg(unix)]
+    tokio::spawn(dump_diagnostics_on_signal(
+        engines.clone(),
+        diagnostics_state,
+        args.diagnostics_path.as_deref().map(expand_tilde),
+    ));
+
+    let address = SocketAddr::new(args.host, args.port);
+    let (address, server) =
+        warp::serve(routes).bind_with_graceful_shutdown(address, shutdown_signal());
+    tracing::info!("listening on {}", address);
+    server.await;
+    tracing::info!("shut down");
+}
+
+/// Every endpoint, answering with the engine current at the time of each request.
+fn filters(
+    engines: Arc<SharedEngine>,
+    state: Arc<ServerState>,
+    args: &ServeArgs,
+) -> BoxedFilter<(Box<dyn Reply>,)> {
+    let cors = if args.cors_origin.is_empty() {
+        warp::cors().allow_any_origin()
+    } else {
+        warp::cors().allow_origins(args.cors_origin.iter().map(String::as_str))
+    };
+    let cors = cors
+        .allow_headers(vec!["Content-Type"])
+        .allow_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"]);
+
+    let timeout = args.request_timeout_ms.map(Duration::from_millis);
+
+    let vertex = warp::get()
+        .and(warp::path!("vertex" / u32))
+        .and(with_engine(engines.clone()))
+        .map(handle_vertex);
+
+    let preview = warp::get()
+        .and(warp::path!("preview"))
+        .and(warp::query::<PointQuery>())
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .map(handle_preview);
+
+    let debug_vertex = warp::get()
+        .and(warp::path!("debug" / "vertex" / u32))
+        .and(with_engine(engines.clone()))
+        .map(
+            |id: u32, engine: Arc<Engine>| match debug::vertex(id, &engine.fmi, &engine.graph) {
+                Ok(body) => warp::reply::with_status(warp::reply::json(&body), StatusCode::OK),
+                Err(error) => error.into_json_reply(),
+            },
+        );
+
+    let debug_edge = warp::get()
+        .and(warp::path!("debug" / "edge" / u32))
+        .and(with_engine(engines.clone()))
+        .map(
+            |id: u32, engine: Arc<Engine>| match debug::edge(id, &engine.fmi, &engine.graph) {
+                Ok(body) => warp::reply::with_status(warp::reply::json(&body), StatusCode::OK),
+                Err(error) => error.into_json_reply(),
+            },
+        );
+
+    let debug_tree = warp::post()
+        .and(warp::path!("debug" / "tree"))
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .then(
+            move |tree_request: debug::TreeRequest, engine: Arc<Engine>| {
+                blocking(timeout, move || {
+                    let source = engine.snapper.nearest(tree_request.from);
+                    let body =
+                        debug::tree(source, tree_request.max_cost, &engine.fmi, &engine.graph);
+                    warp::reply::json(&body)
+                })
+            },
+        );
+
+    let debug_drive = warp::get()
+        .and(warp::path!("debug" / "drive"))
+        .and(warp::query::<DriveQuery>())
+        .and(with_engine(engines.clone()))
+        .map(handle_debug_drive);
+
+    let admin_canary = warp::get()
+        .and(warp::path!("admin" / "canary"))
+        .and(with_state(state.clone()))
+        .map(|state: Arc<ServerState>| warp::reply::json(&state.canary.to_json()));
+
+    let admin_config = warp::get()
+        .and(warp::path!("admin" / "config"))
+        .and(with_state(state.clone()))
+        .map(|state: Arc<ServerState>| warp::reply::json(&config_json(&state)));
+
+    let admin_config_update = warp::put()
+        .and(warp::path!("admin" / "config"))
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .map(handle_config_update);
+
+    let metrics = warp::get()
+        .and(warp::path!("metrics"))
+        .and(with_state(state.clone()))
+        .map(|state: Arc<ServerState>| {
+            warp::reply::with_header(
+                state.metrics.to_prometheus(),
+                "Content-Type",
+                "text/plain; version=0.0.4",
+            )
+        });
+
+    let admin_dashboard = warp::get()
+        .and(warp::path!("admin" / "dashboard"))
+        .map(|| warp::reply::html(include_str!("dashboard.html")));
+
+    let admin_dashboard_data = warp::get()
+        .and(warp::path!("admin" / "dashboard" / "data"))
+        .and(with_state(state.clone()))
+        .map(|state: Arc<ServerState>| warp::reply::json(&state.metrics.to_json()));
+
+    let admin_reload_status = warp::get()
+        .and(warp::path!("admin" / "reload"))
+        .and(with_state(engines.clone()))
+        .map(|engines: Arc<SharedEngine>| {
+            let body = json!({
+                "version": format!("{:016x}", engines.current().version),
+                "reloading": engines.is_reloading(),
+            });
+            warp::reply::json(&body)
+        });
+
+    let admin_reload = warp::post()
+        .and(warp::path!("admin" / "reload"))
+        .and(json_body(args.max_body_size))
+        .and(with_state(engines.clone()))
+        .and(with_state(state.clone()))
+        .map(handle_reload);
+
+    let admin_places = warp::get()
+        .and(warp::path!("admin" / "places"))
+        .and(with_state(state.clone()))
+        .map(|state: Arc<ServerState>| warp::reply::json(&state.places.to_json()));
+
+    let admin_place_set = warp::put()
+        .and(warp::path!("admin" / "places" / String))
+        .and(json_body(args.max_body_size))
+        .and(with_state(state.clone()))
+        .map(
+            |name: String, coordinate: (f64, f64), state: Arc<ServerState>| match state
+                .places
+                .set(&name, coordinate)
+            {
+                Ok(()) => warp::reply::with_status(
+                    warp::reply::json(&state.places.to_json()),
+                    StatusCode::OK,
+                ),
+                Err(error) => error.into_json_reply(),
+            },
+        );
+
+    let admin_place_remove = warp::delete()
+        .and(warp::path!("admin" / "places" / String))
+        .and(with_state(state.clone()))
+        .map(|name: String, state: Arc<ServerState>| {
+            if !state.places.remove(&name) {
+                return RoutingError::NotFound(format!("unknown place '@{}'", name))
+                    .into_json_reply();
+            }
+            warp::reply::with_status(warp::reply::json(&state.places.to_json()), StatusCode::OK)
+        });
+
+    let route_places = warp::get()
+        .and(warp::path!("route"))
+        .and(warp::query::<PlaceRouteQuery>())
+        .and(accepted_format())
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |mut query: PlaceRouteQuery,
+                  accepted: Option<Format>,
+                  engine: Arc<Engine>,
+                  state: Arc<ServerState>| {
+                query.format = query.format.or(accepted);
+                blocking(timeout, move || handle_route_places(query, engine, state))
+            },
+        );
+
+    let route_ids = warp::get()
+        .and(warp::path!("route" / "ids"))
+        .and(warp::query::<IdRouteQuery>())
+        .and(accepted_format())
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |mut query: IdRouteQuery,
+                  accepted: Option<Format>,
+                  engine: Arc<Engine>,
+                  state: Arc<ServerState>| {
+                query.format = query.format.or(accepted);
+                blocking(timeout, move || handle_route_ids(query, engine, state))
+            },
+        );
+
+    let route_k = warp::post()
+        .and(warp::path!("route" / "k"))
+        .and(warp::query::<KRouteQuery>())
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |query: KRouteQuery,
+                  route_body: RouteBody,
+                  engine: Arc<Engine>,
+                  state: Arc<ServerState>| {
+                blocking(timeout, move || {
+                    handle_route_k(query, route_body, engine, state)
+                })
+            },
+        );
+
+    let route_sample = warp::post()
+        .and(warp::path!("route" / "sample"))
+        .and(warp::query::<SampleQuery>())
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |query: SampleQuery,
+                  route_body: RouteBody,
+                  engine: Arc<Engine>,
+                  state: Arc<ServerState>| {
+                blocking(timeout, move || {
+                    handle_route_sample(query, route_body, engine, state)
+                })
+            },
+        );
+
+    let route_pois = warp::post()
+        .and(warp::path!("route" / "pois"))
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |request: PoiRequest, engine: Arc<Engine>, state: Arc<ServerState>| {
+                blocking(timeout, move || handle_route_pois(request, engine, state))
+            },
+        );
+
+    let detour = warp::post()
+        .and(warp::path!("detour"))
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |request: DetourRequest, engine: Arc<Engine>, state: Arc<ServerState>| {
+                blocking(timeout, move || handle_detour(request, engine, state))
+            },
+        );
+
+    let route_constrained = warp::post()
+        .and(warp::path!("route" / "constrained"))
+        .and(warp::query::<ConstrainedRouteQuery>())
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |query: ConstrainedRouteQuery,
+                  route_body: RouteBody,
+                  engine: Arc<Engine>,
+                  state: Arc<ServerState>| {
+                blocking(timeout, move || {
+                    handle_route_constrained(query, route_body, engine, state)
+                })
+            },
+        );
+
+    let route_pareto = warp::post()
+        .and(warp::path!("route" / "pareto"))
+        .and(warp::query::<ParetoRouteQuery>())
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |query: ParetoRouteQuery,
+                  route_body: RouteBody,
+                  engine: Arc<Engine>,
+                  state: Arc<ServerState>| {
+                blocking(timeout, move || {
+                    handle_route_pareto(query, route_body, engine, state)
+                })
+            },
+        );
+
+    let table = warp::post()
+        .and(warp::path!("table"))
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |request: TableRequest, engine: Arc<Engine>, state: Arc<ServerState>| {
+                blocking(timeout, move || handle_table(request, engine, state))
+            },
+        );
+
+    let assign = warp::post()
+        .and(warp::path!("assign"))
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |request: AssignRequest, engine: Arc<Engine>, state: Arc<ServerState>| {
+                blocking(timeout, move || handle_assign(request, engine, state))
+            },
+        );
+
+    let vrp = warp::post()
+        .and(warp::path!("vrp"))
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |request: VrpRequest, engine: Arc<Engine>, state: Arc<ServerState>| {
+                blocking(timeout, move || handle_vrp(request, engine, state))
+            },
+        );
+
+    let isochrone = warp::post()
+        .and(warp::path!("isochrone"))
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |request: IsochroneRequest, engine: Arc<Engine>, state: Arc<ServerState>| {
+                blocking(timeout, move || handle_isochrone(request, engine, state))
+            },
+        );
+
+    let isochrone_tile = warp::get()
+        .and(warp::path!("isochrone" / "tile"))
+        .and(warp::query::<PointQuery>())
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .map(handle_isochrone_tile);
+
+    let route_evaluate = warp::post()
+        .and(warp::path!("route" / "evaluate"))
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .then(move |request: EvaluateRequest, engine: Arc<Engine>| {
+            blocking(timeout, move || handle_route_evaluate(request, engine))
+        });
+
+    let reroute = warp::post()
+        .and(warp::path!("reroute"))
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |request: RerouteRequest, engine: Arc<Engine>, state: Arc<ServerState>| {
+                blocking(timeout, move || handle_reroute(request, engine, state))
+            },
+        );
+
+    let route = warp::post()
+        .and(warp::path!("route"))
+        .and(warp::query::<RouteOptions>())
+        .and(raw_query())
+        .and(warp::header::optional::<String>("accept"))
+        .and(json_body(args.max_body_size))
+        .and(with_engine(engines.clone()))
+        .and(with_state(state.clone()))
+        .then(
+            move |mut options: RouteOptions,
+                  query: String,
+                  accept: Option<String>,
+                  body: Value,
+                  engine: Arc<Engine>,
+                  state: Arc<ServerState>| {
+                options.format = options
+                    .format
+                    .or(accept.as_deref().and_then(Format::from_accept));
+                blocking(timeout, move || {
+                    // parsed here instead of by the filter so the recorder sees the raw body
+                    let response = match serde_json::from_value::<RouteBody>(body.clone()) {
+                        Ok(route_body) => handle_route(options, route_body, engine, state.clone()),
+                        Err(error) => {
+                            RoutingError::InvalidRequest(format!("invalid body: {}", error))
+                                .into_response()
+                        }
+                    };
+                    if let (Some(recorder), Ok(response)) = (&state.recorder, &response) {
+                        recorder.record(&Fixture {
+                            query,
+                            accept,
+                            body,
+                            status: response.status().as_u16(),
+                            response: response.body().clone(),
+                        });
+                    }
+                    response
+                })
+            },
+        );
+
+    route
+        .or(route_places)
+        .or(route_ids)
+        .or(route_k)
+        .or(route_sample)
+        .or(route_pois)
+        .or(route_constrained)
+        .or(route_pareto)
+        .or(route_evaluate)
+        .or(table)
+        .or(isochrone)
+        .or(isochrone_tile)
+        .or(detour)
+        .or(assign)
+        .or(vrp)
+        .or(reroute)
+        .or(vertex)
+        .or(preview)
+        .or(debug_vertex)
+        .or(debug_edge)
+        .or(debug_tree)
+        .or(debug_drive)
+        .or(admin_canary)
+        .or(admin_config)
+        .or(admin_config_update)
+        .or(admin_reload)
+        .or(admin_reload_status)
+        .or(admin_places)
+        .or(admin_place_set)
+        .or(admin_place_remove)
+        .or(admin_dashboard)
+        .or(admin_dashboard_data)
+        .or(metrics)
+        .recover(handle_rejection)
+        .with(cors)
+        .with(warp::log::custom(move |info| {
+            let latency = info.elapsed();
+            state.metrics.record(
+                info.method().as_str(),
+                info.path(),
+                info.status().as_u16(),
+                latency,
+            );
+            tracing::info!(
+                status = info.status().as_u16(),
+                latency_ms = latency.as_secs_f64() * 1000.0,
+                "answered"
+            );
+        }))
+        .with(warp::trace(request_span))
+        .map(|reply| Box::new(reply) as Box<dyn Reply>)
+        .boxed()
+}
+
+pub fn init_tracing(format: LogFormat, log_file: Option<RollingLog>) {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match (format, log_file) {
+        (LogFormat::Text, None) => subscriber.init(),
+        (LogFormat::Json, None) => subscriber.json().init(),
+        (LogFormat::Text, Some(file)) => subscriber
+            .with_ansi(false)
+            .with_writer(Mutex::new(file))
+            .init(),
+        (LogFormat::Json, Some(file)) => subscriber.json().with_writer(Mutex::new(file)).init(),
+    }
+}
+
+/// Span around one request. Its id is taken from an `X-Request-Id` header or counted up, the
+/// route fields are filled in by `find_path`.
+fn request_span(info: warp::trace::Info) -> tracing::Span {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    let id = info
+        .request_headers()
+        .get("x-request-id")
+        .and_then(|id| id.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| NEXT_ID.fetch_add(1, Ordering::Relaxed).to_string());
+    tracing::info_span!(
+        "request",
+        id = %id,
+        method = %info.method(),
+        path = %info.path(),
+        from = tracing::field::Empty,
+        to = tracing::field::Empty,
+        algorithm = tracing::field::Empty,
+        weight = tracing::field::Empty,
+    )
+}
+
+/// Format named by the `Accept` header, if any.
+fn accepted_format() -> impl Filter<Extract = (Option<Format>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("accept")
+        .map(|accept: Option<String>| accept.as_deref().and_then(Format::from_accept))
+}
+
+/// The query string as sent, empty if there is none.
+fn raw_query() -> impl Filter<Extract = (String,), Error = Infallible> + Clone {
+    warp::query::raw().or(warp::any().map(String::new)).unify()
+}
+
+/// JSON body of at most `limit` bytes.
+fn json_body<T: DeserializeOwned + Send>(
+    limit: u64,
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    warp::body::content_length_limit(limit).and(warp::body::json())
+}
+
+/// Writes `diagnostics` to `path` or the log on every SIGUSR1.
+#[cfg(unix)]
+async fn dump_diagnostics_on_signal(
+    engines: Arc<SharedEngine>,
+    state: Arc<ServerState>,
+    path: Option<PathBuf>,
+) {
+    let mut signals =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1()).unwrap();
+    while signals.recv().await.is_some() {
+        let report = diagnostics(&engines, &state);
+        match &path {
+            Some(path) => match std::fs::write(path, report.to_string()) {
+                Ok(()) => tracing::info!("diagnostics written to '{}'", path.display()),
+                Err(error) => {
+                    tracing::warn!(
+                        "cannot write diagnostics to '{}' ({})",
+                        path.display(),
+                        error
+                    )
+                }
+            },
+            None => tracing::info!(report = %report, "diagnostics"),
+        }
+    }
+}
+
+/// State of a running server for inspecting a hung or slow instance: requests being
+/// handled, pool occupancy, cache fill and memory.
+#[cfg(unix)]
+fn diagnostics(engines: &SharedEngine, state: &ServerState) -> Value {
+    let engine = engines.current();
+    let estimate = MemoryEstimate::new(&engine.paths);
+    json!({
+        "active_requests": ACTIVE_REQUESTS.load(Ordering::Relaxed),
+        "pools": {
+            "snap": state.pools.snap.to_json(),
+            "route": state.pools.route.to_json(),
+            "serialize": state.pools.serialize.to_json(),
+        },
+        "route_cache": state.route_cache.to_json(),
+        "engine": {
+            "version": format!("{:016x}", engine.version),
+            "vertices": engine.graph.number_of_vertices(),
+            "edges": engine.graph.edges.len(),
+            "hl": engine.hl.is_some(),
+            "reloading": engines.is_reloading(),
+            "artifacts": {
+                "gr": engine.paths.gr_path.display().to_string(),
+                "co": engine.paths.co_path.display().to_string(),
+                "ch": engine.paths.ch_path.display().to_string(),
+                "hl": engine.paths.hl_path.as_ref().map(|path| path.display().to_string()),
+            },
+        },
+        "memory": {
+            "resident_bytes": resident_bytes(),
+            "estimated_bytes": {
+                "base": estimate.base,
+                "ch": estimate.ch,
+                "hl": estimate.hl,
+            },
+        },
+        "canary": state.canary.to_json(),
+    })
+}
+
+/// Resolves on SIGTERM or Ctrl-C, after which open requests are finished but no new ones
+/// accepted.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .unwrap()
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+    tracing::info!("shutting down");
+}
+
+fn handle_route(
+    options: RouteOptions,
+    route_body: RouteBody,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> Result<Response<String>, warp::http::Error> {
+    let parsed = into_waypoints(route_body, &state).and_then(|(waypoints, has_properties)| {
+        if waypoints.len() > 2 {
+            return Ok((waypoints, has_properties, None));
+        }
+        endpoints(waypoints, has_properties).map(|endpoints| (Vec::new(), false, Some(endpoints)))
+    });
+    let (route_request, properties) = match parsed {
+        Ok((_, _, Some(endpoints))) => endpoints,
+        Ok((waypoints, has_properties, None)) => {
+            return handle_route_legs(&options, waypoints, has_properties, &engine, &state);
+        }
+        Err(error) => return error.into_response(),
+    };
+
+    let warnings: Vec<String> = [("from", route_request.from), ("to", route_request.to)]
+        .into_iter()
+        .filter(|(_, coordinate)| looks_swapped(*coordinate))
+        .map(|(name, coordinate)| {
+            format!(
+                "{} {:?} has latitude out of range, lon/lat may be swapped",
+                name, coordinate
+            )
+        })
+        .collect();
+    for warning in warnings.iter() {
+        tracing::warn!("{}", warning);
+    }
+
+    let mut properties = properties;
+    for (name, coordinate) in [("from", route_request.from), ("to", route_request.to)] {
+        if let Err(error) = RoutingError::check_coordinate(name, coordinate) {
+            return error.into_response();
+        }
+    }
+    let (from_snap, to_snap) = state.pools.snap.run(|| {
+        let snap = |coordinate| {
+            let start = Instant::now();
+            let snap = engine.snapper.snap(coordinate);
+            state.metrics.snap.observe(start.elapsed());
+            snap
+        };
+        (snap(route_request.from), snap(route_request.to))
+    });
+    for (name, coordinate, snap) in [
+        ("from", route_request.from, from_snap),
+        ("to", route_request.to, to_snap),
+    ] {
+        if let Err(failure) =
+            check_snap_distance(&engine, &state, name, coordinate, snap.coordinate)
+        {
+            return failure.into_response();
+        }
+    }
+    for (name, coordinate, snap) in [
+        ("from_snap", route_request.from, from_snap),
+        ("to_snap", route_request.to, to_snap),
+    ] {
+        let mut value = json!({
+            "coordinate": round_coordinate(snap.coordinate),
+            "distance": round(engine.distance(coordinate, snap.coordinate), 1),
+        });
+        if let SnapTarget::Edge { edge, offset } = snap.target {
+            value["edge"] = engine.graph.arc_id(edge).into();
+            value["offset"] = round(offset, 6).into();
+        }
+        properties.insert(name.to_string(), value);
+    }
+    let (from, to) = (from_snap.vertex, to_snap.vertex);
+
+    let snaps = Some((from_snap, to_snap));
+    let route = compute_route(&engine, &state, from, to, &options, snaps, properties);
+    let (collection, weight) = match route {
+        Ok(route) => route,
+        Err(failure) => return failure.into_response(),
+    };
+    if state.mirror.fraction() > 0.0 {
+        let body = collection.to_string();
+        state
+            .mirror
+            .mirror(&engine, &route_request, from, to, weight, &body);
+    }
+
+    let format = options.format.unwrap_or_default();
+    let mut response = Response::builder().header("Content-Type", format.content_type());
+    for warning in warnings {
+        response = response.header("Warning", format!("199 - \"{}\"", warning));
+    }
+    response.body(state.pools.serialize.run(|| render(&collection, format)))
+}
+
+/// Routes through more than two waypoints in order, one LineString feature per leg. The
+/// collection carries the total `weight`, `max_cost` applies to it.
+fn handle_route_legs(
+    options: &RouteOptions,
+    waypoints: Vec<Waypoint>,
+    has_properties: bool,
+    engine: &Engine,
+    state: &ServerState,
+) -> Result<Response<String>, warp::http::Error> {
+    let vertices: Result<Vec<u32>, RoutingError> = waypoints
+        .iter()
+        .enumerate()
+        .map(|(i, (coordinate, _))| {
+            snap_vertex(engine, state, &format!("waypoint {}", i), *coordinate)
+        })
+        .collect();
+    let vertices = match vertices {
+        Ok(vertices) => vertices,
+        Err(failure) => return failure.into_response(),
+    };
+
+    let start = Instant::now();
+    let mut legs = Vec::new();
+    for (leg, pair) in vertices.windows(2).enumerate() {
+        match find_path(engine, state, pair[0], pair[1], options.algorithm) {
+            Ok(found) => legs.push(found),
+            Err(RoutingError::Unreachable) => {
+                return RoutingError::UnreachableLeg {
+                    leg,
+                    from: pair[0],
+                    to: pair[1],
+                }
+                .into_response();
+            }
+            Err(failure) => return failure.into_response(),
+        }
+    }
+
+    let weight = legs
+        .iter()
+        .fold(0u32, |weight, leg| weight.saturating_add(leg.route.weight));
+    if let Some(max_cost) = options.max_cost.filter(|&max_cost| weight > max_cost) {
+        return RoutingError::ExceedsBudget { weight, max_cost }.into_response();
+    }
+
+    let features: Vec<Value> = legs
+        .iter()
+        .enumerate()
+        .map(|(leg, found)| {
+            let mut properties = Map::new();
+            properties.insert("leg".to_string(), leg.into());
+            if has_properties {
+                properties.insert("from".to_string(), waypoints[leg].1.clone());
+                properties.insert("to".to_string(), waypoints[leg + 1].1.clone());
+            }
+            route_feature(engine, state, &found.route, options, None, properties)
+        })
+        .collect();
+    let mut route_geojson = feature_collection(features);
+    route_geojson["weight"] = weight.into();
+    route_geojson["metadata"] = route_metadata(engine, options, &legs, start.elapsed());
+    let format = options.format.unwrap_or_default();
+    Response::builder()
+        .header("Content-Type", format.content_type())
+        .body(state.pools.serialize.run(|| render(&route_geojson, format)))
+}
+
+/// GET /route with endpoints from the query, so they can name server-side places.
+fn handle_route_places(
+    query: PlaceRouteQuery,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> Result<Response<String>, warp::http::Error> {
+    let from = state.places.resolve(&query.from, state.coordinate_order);
+    let to = state.places.resolve(&query.to, state.coordinate_order);
+    let (from, to) = match (from, to) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(error), _) | (_, Err(error)) => return error.into_response(),
+    };
+    let route_body = RouteBody::Coordinates(RouteRequest {
+        from,
+        to,
+        coordinate_order: Some(CoordinateOrder::LonLat),
+    });
+    let options = RouteOptions {
+        max_cost: query.max_cost,
+        algorithm: query.algorithm,
+        viewport: query.viewport,
+        geometry_range: query.geometry_range,
+        annotations: query.annotations,
+        format: query.format,
+        full_geometry: query.full_geometry,
+    };
+    handle_route(options, route_body, engine, state)
+}
+
+/// Waypoints of any accepted body format as `(lon, lat)` with the properties of their GeoJSON
+/// feature, and whether the body had such properties at all.
+fn into_waypoints(
+    route_body: RouteBody,
+    state: &ServerState,
+) -> Result<(Vec<Waypoint>, bool), RoutingError> {
+    let (waypoints, has_properties) = match route_body {
+        RouteBody::Coordinates(route_request) => {
+            let order = route_request
+                .coordinate_order
+                .unwrap_or(state.coordinate_order);
+            let waypoints = [route_request.from, route_request.to]
+                .into_iter()
+                .map(|coordinate| (order.to_lon_lat(coordinate), Value::Null))
+                .collect();
+            (waypoints, false)
+        }
+        RouteBody::Points(points_request) => {
+            let order = points_request
+                .coordinate_order
+                .unwrap_or(state.coordinate_order);
+            let waypoints = points_request
+                .points
+                .into_iter()
+                .map(|coordinate| (order.to_lon_lat(coordinate), Value::Null))
+                .collect();
+            (waypoints, false)
+        }
+        RouteBody::GeoJson(input) => (input.waypoints()?, true),
+        RouteBody::Encoded(request) => (request.waypoints()?, false),
+    };
+    if waypoints.len() < 2 {
+        return Err(RoutingError::InvalidRequest(format!(
+            "expected at least 2 waypoints, got {}",
+            waypoints.len()
+        )));
+    }
+    let last = waypoints.len() - 1;
+    for (i, (coordinate, _)) in waypoints.iter().enumerate() {
+        let name = match i {
+            0 => "from".to_string(),
+            i if i == last => "to".to_string(),
+            i => format!("waypoint {}", i),
+        };
+        check_service_area(state, &name, *coordinate)?;
+    }
+    Ok((waypoints, has_properties))
+}
+
+/// Brings all accepted body formats with exactly two waypoints to `(lon, lat)` endpoints plus
+/// the properties that are copied into the response.
+fn parse_route_body(
+    route_body: RouteBody,
+    state: &ServerState,
+) -> Result<(RouteRequest, Map<String, Value>), RoutingError> {
+    let (waypoints, has_properties) = into_waypoints(route_body, state)?;
+    endpoints(waypoints, has_properties)
+}
+
+fn endpoints(
+    waypoints: Vec<Waypoint>,
+    has_properties: bool,
+) -> Result<(RouteRequest, Map<String, Value>), RoutingError> {
+    let [(from, from_properties), (to, to_properties)]: [Waypoint; 2] =
+        waypoints.try_into().map_err(|waypoints: Vec<_>| {
+            RoutingError::InvalidRequest(format!("expected 2 waypoints, got {}", waypoints.len()))
+        })?;
+    let mut properties = Map::new();
+    if has_properties {
+        properties.insert("from".to_string(), from_properties);
+        properties.insert("to".to_string(), to_properties);
+    }
+    let route_request = RouteRequest {
+        from,
+        to,
+        coordinate_order: None,
+    };
+    Ok((route_request, properties))
+}
+
+/// Rejects coordinates outside the service area instead of snapping them to its border.
+fn check_service_area(
+    state: &ServerState,
+    name: &str,
+    coordinate: (f64, f64),
+) -> Result<(), RoutingError> {
+    match &state.service_area {
+        Some(service_area) if !service_area.contains(coordinate) => {
+            Err(RoutingError::OutsideServiceArea {
+                name: name.to_string(),
+                coordinate,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Nearest vertex to `coordinate`, or a failure if its snapped point is farther than
+/// --max-snap-distance.
+fn snap_vertex(
+    engine: &Engine,
+    state: &ServerState,
+    name: &str,
+    coordinate: (f64, f64),
+) -> Result<u32, RoutingError> {
+    RoutingError::check_coordinate(name, coordinate)?;
+    let snap = state.pools.snap.run(|| {
+        let start = Instant::now();
+        let snap = engine.snapper.snap(coordinate);
+        state.metrics.snap.observe(start.elapsed());
+        snap
+    });
+    check_snap_distance(engine, state, name, coordinate, snap.coordinate)?;
+    Ok(snap.vertex)
+}
+
+fn check_snap_distance(
+    engine: &Engine,
+    state: &ServerState,
+    name: &str,
+    coordinate: (f64, f64),
+    snapped: (f64, f64),
+) -> Result<(), RoutingError> {
+    let distance = engine.distance(coordinate, snapped);
+    if state.chaos.snap_fails() {
+        return Err(RoutingError::SnapFailed {
+            name: format!("{} {:?}", name, coordinate),
+            distance,
+        });
+    }
+    match state.max_snap_distance {
+        Some(max_snap_distance) if distance > max_snap_distance => Err(RoutingError::SnapFailed {
+            name: format!("{} {:?}", name, coordinate),
+            distance,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Handlers running on the blocking pool, for `diagnostics`.
+static ACTIVE_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+/// Runs a handler on tokio's blocking pool, so that snapping and path searches neither stall
+/// the executor nor each other and requests are answered concurrently.
+/// Without an answer within `timeout` the client gets 504, the handler still runs to the end.
+async fn blocking<R: warp::Reply + 'static>(
+    timeout: Option<Duration>,
+    handler: impl FnOnce() -> R + Send + 'static,
+) -> warp::reply::Response {
+    let span = tracing::Span::current();
+    let task = tokio::task::spawn_blocking(move || {
+        ACTIVE_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        let response = span.in_scope(|| guarded(handler));
+        ACTIVE_REQUESTS.fetch_sub(1, Ordering::Relaxed);
+        response
+    });
+    let result = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, task).await {
+            Ok(result) => result,
+            Err(_) => {
+                return warp::Reply::into_response(RoutingError::Timeout(timeout).into_json_reply())
+            }
+        },
+        None => task.await,
+    };
+    result.unwrap_or_else(|error| {
+        warp::Reply::into_response(RoutingError::Internal(error.to_string()).into_json_reply())
+    })
+}
+
+/// The panic of a single request is answered with 500 instead of dropping the connection.
+fn guarded<R: warp::Reply>(handler: impl FnOnce() -> R) -> warp::reply::Response {
+    match panic::catch_unwind(AssertUnwindSafe(handler)) {
+        Ok(reply) => reply.into_response(),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            warp::Reply::into_response(RoutingError::Internal(message).into_json_reply())
+        }
+    }
+}
+
+/// JSON error bodies for requests that no filter accepted, e.g. malformed bodies.
+async fn handle_rejection(rejection: Rejection) -> Result<impl warp::Reply, Infallible> {
+    let (status, message) = if rejection.is_not_found() {
+        (StatusCode::NOT_FOUND, "not found".to_string())
+    } else if let Some(error) = rejection.find::<warp::filters::body::BodyDeserializeError>() {
+        (StatusCode::BAD_REQUEST, format!("invalid body: {}", error))
+    } else if let Some(error) = rejection.find::<warp::reject::InvalidQuery>() {
+        (StatusCode::BAD_REQUEST, error.to_string())
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        (
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method not allowed".to_string(),
+        )
+    } else if rejection
+        .find::<warp::reject::UnsupportedMediaType>()
+        .is_some()
+    {
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "expected a JSON body".to_string(),
+        )
+    } else if rejection.find::<warp::reject::PayloadTooLarge>().is_some() {
+        (StatusCode::PAYLOAD_TOO_LARGE, "body too large".to_string())
+    } else if rejection.find::<warp::reject::LengthRequired>().is_some() {
+        (
+            StatusCode::LENGTH_REQUIRED,
+            "Content-Length is required".to_string(),
+        )
+    } else {
+        tracing::error!("unhandled rejection: {:?}", rejection);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal error".to_string(),
+        )
+    };
+    let body = json!({ "error": message });
+    Ok(warp::reply::with_status(warp::reply::json(&body), status))
+}
+
+fn handle_route_k(
+    query: KRouteQuery,
+    route_body: RouteBody,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> Result<Response<String>, warp::http::Error> {
+    if !(1..=MAX_K).contains(&query.k) {
+        return RoutingError::InvalidRequest(format!("k must be between 1 and {}", MAX_K))
+            .into_response();
+    }
+    let (route_request, properties) = match parse_route_body(route_body, &state) {
+        Ok(parsed) => parsed,
+        Err(error) => return error.into_response(),
+    };
+
+    let (from, to) = match (
+        snap_vertex(&engine, &state, "from", route_request.from),
+        snap_vertex(&engine, &state, "to", route_request.to),
+    ) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(failure), _) | (_, Err(failure)) => return failure.into_response(),
+    };
+
+    let path_finder = engine.hl.as_ref().unwrap_or(&engine.ch);
+    let shortest_path = |from: u32, to: u32| {
+        let request = ShortestPathRequest::new(from, to)?;
+        path_finder
+            .get_shortest_path(&request)
+            .map(|path| path.vertices)
+    };
+    let start = Instant::now();
+    let routes = k_shortest_paths(&engine.graph, shortest_path, from, to, query.k);
+    let time = start.elapsed();
+    tracing::info!(
+        "k_route_request: {:>7} -> {:>7}, k: {:>2}, found: {:>2}, took: {:>3}ms",
+        from,
+        to,
+        query.k,
+        routes.len(),
+        time.as_millis()
+    );
+    if routes.is_empty() {
+        return RoutingError::Unreachable.into_response();
+    }
+
+    let features = routes
+        .iter()
+        .enumerate()
+        .map(|(rank, route)| {
+            let mut properties = properties.clone();
+            properties.insert("rank".to_string(), rank.into());
+            properties.insert("weight".to_string(), route.weight.into());
+            let coordinates = vertex_coordinates(&engine.fmi, &route.vertices);
+            linestring_feature(&coordinates, properties)
+        })
+        .collect();
+    Response::builder().body(feature_collection(features).to_string())
+}
+
+/// Points at given distances along the route between the snapped endpoints, each with the
+/// distance and the cost from the start, e.g. for labels or ETA markers.
+fn handle_route_sample(
+    query: SampleQuery,
+    route_body: RouteBody,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> Result<Response<String>, warp::http::Error> {
+    let fractions: Vec<Option<f64>> = match (query.fraction, query.distance, query.samples) {
+        (Some(fraction), None, None) if (0.0..=1.0).contains(&fraction) => vec![Some(fraction)],
+        (None, Some(distance), None) if distance >= 0.0 => vec![None],
+        (None, None, Some(samples)) if (1..=MAX_SAMPLES).contains(&samples) => (1..=samples)
+            .map(|i| Some(i as f64 / (samples + 1) as f64))
+            .collect(),
+        _ => {
+            return RoutingError::InvalidRequest(format!(
+                "expected one of fraction (0 to 1), distance (meters) or samples (1 to {})",
+                MAX_SAMPLES
+            ))
+            .into_response()
+        }
+    };
+    let (route_request, properties) = match parse_route_body(route_body, &state) {
+        Ok(parsed) => parsed,
+        Err(error) => return error.into_response(),
+    };
+    let (from, to) = match (
+        snap_vertex(&engine, &state, "from", route_request.from),
+        snap_vertex(&engine, &state, "to", route_request.to),
+    ) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(failure), _) | (_, Err(failure)) => return failure.into_response(),
+    };
+    let route = match find_path(&engine, &state, from, to, None) {
+        Ok(found) => found.route,
+        Err(failure) => return failure.into_response(),
+    };
+
+    // distance and cost from the start at every vertex of the route
+    let coordinates = vertex_coordinates(&engine.fmi, &route.vertices);
+    let mut distances = vec![0.0];
+    let mut costs = vec![0.0];
+    for (pair, vertices) in coordinates.windows(2).zip(route.vertices.windows(2)) {
+        let weight = engine
+            .graph
+            .edge_between(vertices[0], vertices[1])
+            .map_or(0, |edge| engine.graph.edges[edge as usize].weight);
+        distances.push(distances.last().unwrap() + engine.distance(pair[0], pair[1]));
+        costs.push(costs.last().unwrap() + weight as f64);
+    }
+    let length = *distances.last().unwrap();
+
+    let features: Vec<Value> = fractions
+        .into_iter()
+        .map(|fraction| {
+            let distance =
+                fraction.map_or(query.distance.unwrap_or(0.0), |fraction| fraction * length);
+            let distance = distance.min(length);
+            let (coordinate, cost) = if distances.len() == 1 {
+                (coordinates[0], 0.0)
+            } else {
+                // segment `i - 1..i` the distance falls on
+                let i = distances
+                    .partition_point(|&start| start < distance)
+                    .clamp(1, distances.len() - 1);
+                let segment = distances[i] - distances[i - 1];
+                let t = if segment > 0.0 {
+                    (distance - distances[i - 1]) / segment
+                } else {
+                    0.0
+                };
+                let (a, b) = (coordinates[i - 1], coordinates[i]);
+                (
+                    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t),
+                    costs[i - 1] + (costs[i] - costs[i - 1]) * t,
+                )
+            };
+            json!({
+                "type": "Feature",
+                "geometry": { "type": "Point", "coordinates": round_coordinate(coordinate) },
+                "properties": {
+                    "distance": round(distance, 1),
+                    "fraction": if length > 0.0 { round(distance / length, 6) } else { 0.0 },
+                    "cost": round(cost, 1),
+                },
+            })
+        })
+        .collect();
+
+    let mut collection = feature_collection(features);
+    collection["length"] = round(length, 1).into();
+    collection["weight"] = route.weight.into();
+    for (key, value) in properties {
+        collection[key] = value;
+    }
+    Response::builder().body(collection.to_string())
+}
+
+/// The POIs within `max_detour` of the route, sorted by detour. The detour of a POI is the
+/// cost from the closest route vertex to it plus the cost from it back to the closest route
+/// vertex, found with one bounded search from and one into all route vertices, however many
+/// POIs there are. It ignores the part of the route skipped or driven twice.
+fn handle_route_pois(
+    request: PoiRequest,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> Result<Response<String>, warp::http::Error> {
+    if request.pois.len() > MAX_POIS {
+        return RoutingError::InvalidRequest(format!("at most {} pois", MAX_POIS)).into_response();
+    }
+    let order = request.coordinate_order.unwrap_or(state.coordinate_order);
+    let (from, to) = (order.to_lon_lat(request.from), order.to_lon_lat(request.to));
+    for (name, coordinate) in [("from", from), ("to", to)] {
+        if let Err(error) = check_service_area(&state, name, coordinate) {
+            return error.into_response();
+        }
+    }
+    let (from, to) = match (
+        snap_vertex(&engine, &state, "from", from),
+        snap_vertex(&engine, &state, "to", to),
+    ) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(failure), _) | (_, Err(failure)) => return failure.into_response(),
+    };
+    let route = match find_path(&engine, &state, from, to, None) {
+        Ok(found) => found.route,
+        Err(failure) => return failure.into_response(),
+    };
+
+    let start = Instant::now();
+    let (from_route, to_route) =
+        distances_around(&engine.graph, &route.vertices, request.max_detour);
+    // (detour, index, vertex, coordinate) of every POI within the budget
+    let mut found: Vec<(u32, usize, u32, (f64, f64))> = Vec::new();
+    let mut unsnapped = Vec::new();
+    for (index, &poi) in request.pois.iter().enumerate() {
+        let poi = order.to_lon_lat(poi);
+        let Ok(vertex) = snap_vertex(&engine, &state, "poi", poi) else {
+            unsnapped.push(index);
+            continue;
+        };
+        let (out, back) = (from_route[vertex as usize], to_route[vertex as usize]);
+        let detour = out.saturating_add(back);
+        if out == u32::MAX || back == u32::MAX || detour > request.max_detour {
+            continue;
+        }
+        found.push((detour, index, vertex, poi));
+    }
+    found.sort_unstable_by_key(|&(detour, index, _, _)| (detour, index));
+    tracing::info!(
+        "poi_request: {:>7} -> {:>7}, pois: {}, found: {}, took: {:>3}ms",
+        from,
+        to,
+        request.pois.len(),
+        found.len(),
+        start.elapsed().as_millis()
+    );
+
+    let mut properties = Map::new();
+    properties.insert("weight".to_string(), route.weight.into());
+    let mut features = vec![linestring_feature(
+        &vertex_coordinates(&engine.fmi, &route.vertices),
+        properties,
+    )];
+    features.extend(found.iter().map(|&(detour, index, vertex, poi)| {
+        json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": round_coordinate(poi) },
+            "properties": { "index": index, "vertex": vertex, "detour": detour },
+        })
+    }));
+    let mut collection = feature_collection(features);
+    // POIs farther than --max-snap-distance from any road
+    collection["unsnapped"] = json!(unsnapped);
+    Response::builder().body(collection.to_string())
+}
+
+fn handle_route_constrained(
+    query: ConstrainedRouteQuery,
+    route_body: RouteBody,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> Result<Response<String>, warp::http::Error> {
+    let (route_request, mut properties) = match parse_route_body(route_body, &state) {
+        Ok(parsed) => parsed,
+        Err(error) => return error.into_response(),
+    };
+
+    let (from, to) = match (
+        snap_vertex(&engine, &state, "from", route_request.from),
+        snap_vertex(&engine, &state, "to", route_request.to),
+    ) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(failure), _) | (_, Err(failure)) => return failure.into_response(),
+    };
+    let target_coordinate = engine.coordinate(to);
+
+    let start = Instant::now();
+    let route = constrained_shortest_path(
+        &engine.graph,
+        &engine.edge_lengths,
+        |vertex| engine.distance(engine.coordinate(vertex), target_coordinate) as u32,
+        from,
+        to,
+        query.max_length,
+    );
+    let time = start.elapsed();
+
+    let route = match route {
+        Ok(Some(route)) => route,
+        Ok(None) => {
+            tracing::info!(
+                "constrained_route_request: {:>7} -> {:>7}, no route within {}m",
+                from,
+                to,
+                query.max_length
+            );
+            return RoutingError::ExceedsLength {
+                max_length: query.max_length,
+            }
+            .into_response();
+        }
+        Err(error) => return error.into_response(),
+    };
+    tracing::info!(
+        "constrained_route_request: {:>7} -> {:>7}, cost: {:>9}, length: {:>9}m, took: {:>3}ms",
+        from,
+        to,
+        route.weight,
+        route.length,
+        time.as_millis()
+    );
+
+    properties.insert("weight".to_string(), route.weight.into());
+    properties.insert("length".to_string(), route.length.into());
+    let coordinates = vertex_coordinates(&engine.fmi, &route.vertices);
+    let features = vec![linestring_feature(&coordinates, properties)];
+    Response::builder().body(feature_collection(features).to_string())
+}
+
+fn handle_route_pareto(
+    query: ParetoRouteQuery,
+    route_body: RouteBody,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> Result<Response<String>, warp::http::Error> {
+    let max_routes = query.max_routes.unwrap_or(10);
+    if !(1..=MAX_PARETO_ROUTES).contains(&max_routes) {
+        return RoutingError::InvalidRequest(format!(
+            "max_routes must be between 1 and {}",
+            MAX_PARETO_ROUTES
+        ))
+        .into_response();
+    }
+    let (route_request, properties) = match parse_route_body(route_body, &state) {
+        Ok(parsed) => parsed,
+        Err(error) => return error.into_response(),
+    };
+
+    let (from, to) = match (
+        snap_vertex(&engine, &state, "from", route_request.from),
+        snap_vertex(&engine, &state, "to", route_request.to),
+    ) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(failure), _) | (_, Err(failure)) => return failure.into_response(),
+    };
+    let target_coordinate = engine.coordinate(to);
+
+    let start = Instant::now();
+    let routes = pareto_routes(
+        &engine.graph,
+        &engine.edge_lengths,
+        |vertex| engine.distance(engine.coordinate(vertex), target_coordinate) as u32,
+        from,
+        to,
+        max_routes,
+    );
+    let time = start.elapsed();
+
+    let routes = match routes {
+        Ok(routes) if routes.is_empty() => return RoutingError::Unreachable.into_response(),
+        Ok(routes) => routes,
+        Err(error) => return error.into_response(),
+    };
+    tracing::info!(
+        "pareto_route_request: {:>7} -> {:>7}, routes: {:>2}, took: {:>3}ms",
+        from,
+        to,
+        routes.len(),
+        time.as_millis()
+    );
+
+    let features = routes
+        .iter()
+        .map(|route| {
+            let mut properties = properties.clone();
+            properties.insert("weight".to_string(), route.weight.into());
+            properties.insert("length".to_string(), route.length.into());
+            let coordinates = vertex_coordinates(&engine.fmi, &route.vertices);
+            linestring_feature(&coordinates, properties)
+        })
+        .collect();
+    Response::builder().body(feature_collection(features).to_string())
+}
+
+fn handle_route_ids(
+    query: IdRouteQuery,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> Result<Response<String>, warp::http::Error> {
+    let number_of_vertices = engine.graph.number_of_vertices() as u32;
+    if query.from >= number_of_vertices || query.to >= number_of_vertices {
+        return RoutingError::GraphMismatch(format!(
+            "vertex ids must be below {}",
+            number_of_vertices
+        ))
+        .into_response();
+    }
+
+    let options = RouteOptions {
+        max_cost: query.max_cost,
+        algorithm: query.algorithm,
+        viewport: query.viewport,
+        geometry_range: query.geometry_range,
+        annotations: query.annotations,
+        format: query.format,
+        full_geometry: query.full_geometry,
+    };
+    match compute_route(
+        &engine,
+        &state,
+        query.from,
+        query.to,
+        &options,
+        None,
+        Map::new(),
+    ) {
+        Ok((collection, _)) => {
+            let format = options.format.unwrap_or_default();
+            Response::builder()
+                .header("Content-Type", format.content_type())
+                .body(state.pools.serialize.run(|| render(&collection, format)))
+        }
+        Err(failure) => failure.into_response(),
+    }
+}
+
+/// Weights from every source to every target with HL, or CH if HL is not loaded, `None`
+/// where there is no path. Rows are computed in parallel on the route pool.
+fn weight_matrix(
+    engine: &Engine,
+    state: &ServerState,
+    sources: &[u32],
+    targets: &[u32],
+) -> Vec<Vec<Option<u32>>> {
+    let path_finder = engine.hl.as_ref().unwrap_or(&engine.ch);
+    state.pools.route.map(sources, |&source| {
+        targets
+            .iter()
+            .map(|&target| {
+                // the empty path, faster_paths has no request for it
+                if source == target {
+                    return Some(0);
+                }
+                let request = ShortestPathRequest::new(source, target)?;
+                path_finder
+                    .get_shortest_path(&request)
+                    .map(|path| path.weight)
+            })
+            .collect()
+    })
+}
+
+/// Added cost of visiting `candidate` between each pair of consecutive stops and the cheapest
+/// of them. Needs one query from every stop to the candidate, one from the candidate to every
+/// stop and one per leg.
+fn handle_detour(
+    request: DetourRequest,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> Result<Response<String>, warp::http::Error> {
+    if !(2..=MAX_DETOUR_STOPS).contains(&request.stops.len()) {
+        return RoutingError::InvalidRequest(format!(
+            "stops must have 2 to {} coordinates",
+            MAX_DETOUR_STOPS
+        ))
+        .into_response();
+    }
+    let order = request.coordinate_order.unwrap_or(state.coordinate_order);
+    let mut vertices = Vec::new();
+    let named = request
+        .stops
+        .iter()
+        .enumerate()
+        .map(|(i, &stop)| (format!("stop {}", i), stop))
+        .chain([("candidate".to_string(), request.candidate)]);
+    for (name, coordinate) in named {
+        let coordinate = order.to_lon_lat(coordinate);
+        if let Err(error) = check_service_area(&state, &name, coordinate) {
+            return error.into_response();
+        }
+        match snap_vertex(&engine, &state, &name, coordinate) {
+            Ok(vertex) => vertices.push(vertex),
+            Err(failure) => return failure.into_response(),
+        }
+    }
+    let candidate = vertices.pop().unwrap();
+    let stops = vertices;
+
+    let to_candidate = weight_matrix(&engine, &state, &stops, &[candidate]);
+    let from_candidate = weight_matrix(&engine, &state, &[candidate], &stops);
+    let legs: Vec<Option<u32>> = stops
+        .windows(2)
+        .map(|pair| weight_matrix(&engine, &state, &pair[..1], &pair[1..])[0][0])
+        .collect();
+
+    // added cost when the candidate comes right after stop `i`
+    let added: Vec<Option<i64>> = legs
+        .iter()
+        .enumerate()
+        .map(|(i, &leg)| {
+            let (to, from) = (to_candidate[i][0]?, from_candidate[0][i + 1]?);
+            Some(to as i64 + from as i64 - leg? as i64)
+        })
+        .collect();
+    let Some((after, added_cost)) = added
+        .iter()
+        .enumerate()
+        .filter_map(|(i, added)| Some((i, (*added)?)))
+        .min_by_key(|&(i, added)| (added, i))
+    else {
+        return RoutingError::Unreachable.into_response();
+    };
+    let weight: Option<u32> = legs.iter().copied().sum();
+
+    let body = json!({
+        "position": after + 1,
+        "added_cost": added_cost,
+        "weight": weight,
+        "weight_with_candidate": weight.map(|weight| weight as i64 + added_cost),
+        "added_costs": added,
+    });
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+}
+
+/// Ships the demand of every sink from the sources at minimum total cost and returns one
+/// route feature per used source/sink pair with the `amount` shipped over it.
+fn handle_assign(
+    request: AssignRequest,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> impl warp::Reply {
+    let cells = request.sources.len() * request.sinks.len();
+    if cells == 0 || cells > MAX_TABLE_CELLS {
+        return RoutingError::InvalidRequest(format!(
+            "sources x sinks must be between 1 and {}",
+            MAX_TABLE_CELLS
+        ))
+        .into_json_reply();
+    }
+
+    let order = request.coordinate_order.unwrap_or(state.coordinate_order);
+    let snap = |name: &str, coordinate: (f64, f64)| -> Result<u32, RoutingError> {
+        let coordinate = order.to_lon_lat(coordinate);
+        check_service_area(&state, name, coordinate)?;
+        snap_vertex(&engine, &state, name, coordinate)
+    };
+    let sources: Result<Vec<u32>, _> = request
+        .sources
+        .iter()
+        .map(|supply| snap("source", supply.coordinate))
+        .collect();
+    let sinks: Result<Vec<u32>, _> = request
+        .sinks
+        .iter()
+        .map(|demand| snap("sink", demand.coordinate))
+        .collect();
+    let (sources, sinks) = match (sources, sinks) {
+        (Ok(sources), Ok(sinks)) => (sources, sinks),
+        (Err(error), _) | (_, Err(error)) => return error.into_json_reply(),
+    };
+
+    let start = Instant::now();
+    let weights = weight_matrix(&engine, &state, &sources, &sinks);
+    let capacities: Vec<u32> = request
+        .sources
+        .iter()
+        .map(|supply| supply.capacity)
+        .collect();
+    let demands: Vec<u32> = request.sinks.iter().map(|demand| demand.demand).collect();
+    let pairings = match assign(&capacities, &demands, &weights) {
+        Ok(pairings) => pairings,
+        Err(error) => return error.into_json_reply(),
+    };
+    tracing::info!(
+        "assign_request: {} x {}, {} pairings, took: {:>3}ms",
+        sources.len(),
+        sinks.len(),
+        pairings.len(),
+        start.elapsed().as_millis()
+    );
+
+    let mut cost: u64 = 0;
+    let mut features = Vec::new();
+    for pairing in pairings {
+        let (from, to) = (sources[pairing.source], sinks[pairing.sink]);
+        let route = if from == to {
+            CachedRoute {
+                vertices: vec![from],
+                weight: 0,
+            }
+        } else {
+            // assign only pairs what the weight matrix has a path for
+            match find_path(&engine, &state, from, to, None) {
+                Ok(FoundPath { route, .. }) => route,
+                Err(error) => {
+                    return RoutingError::Internal(format!(
+                        "no path for the pairing of source {} and sink {}: {}",
+                        pairing.source, pairing.sink, error
+                    ))
+                    .into_json_reply();
+                }
+            }
+        };
+        cost += route.weight as u64 * pairing.amount as u64;
+        let mut properties = Map::new();
+        properties.insert("source".to_string(), pairing.source.into());
+        properties.insert("sink".to_string(), pairing.sink.into());
+        properties.insert("amount".to_string(), pairing.amount.into());
+        let options = RouteOptions::default();
+        features.push(route_feature(
+            &engine, &state, &route, &options, None, properties,
+        ));
+    }
+    let mut body = feature_collection(features);
+    body["cost"] = cost.into();
+    warp::reply::with_status(warp::reply::json(&body), StatusCode::OK)
+}
+
+/// Plans one tour per used vehicle from the depot through its stops and back, returned as
+/// one route feature per vehicle.
+fn handle_vrp(
+    request: VrpRequest,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> impl warp::Reply {
+    if request.vehicles.is_empty() {
+        return RoutingError::InvalidRequest("no vehicles".to_string()).into_json_reply();
+    }
+    if request.stops.is_empty() || request.stops.len() > MAX_STOPS {
+        return RoutingError::InvalidRequest(format!("stops must have 1 to {} entries", MAX_STOPS))
+            .into_json_reply();
+    }
+
+    let mut paired = vec![false; request.stops.len()];
+    for &(pickup, delivery) in request.pairs.iter() {
+        for stop in [pickup, delivery] {
+            if stop >= request.stops.len() || std::mem::replace(&mut paired[stop], true) {
+                return RoutingError::InvalidRequest(format!(
+                    "pair ({}, {}): stop {} is out of range or paired twice",
+                    pickup, delivery, stop
+                ))
+                .into_json_reply();
+            }
+        }
+    }
+
+    let order = request.coordinate_order.unwrap_or(state.coordinate_order);
+    let coordinates = std::iter::once(("depot".to_string(), request.depot)).chain(
+        request
+            .stops
+            .iter()
+            .enumerate()
+            .map(|(i, stop)| (format!("stop {}", i), stop.coordinate)),
+    );
+    let vertices: Result<Vec<u32>, RoutingError> = coordinates
+        .map(|(name, coordinate)| {
+            let coordinate = order.to_lon_lat(coordinate);
+            check_service_area(&state, &name, coordinate)?;
+            snap_vertex(&engine, &state, &name, coordinate)
+        })
+        .collect();
+    let vertices = match vertices {
+        Ok(vertices) => vertices,
+        Err(error) => return error.into_json_reply(),
+    };
+
+    let start = Instant::now();
+    let weights = weight_matrix(&engine, &state, &vertices, &vertices);
+    let solution = solve(&weights, &request.stops, &request.pairs, &request.vehicles);
+    tracing::info!(
+        "vrp_request: {} stops, {} vehicles, {} tours, {} unassigned, took: {:>3}ms",
+        request.stops.len(),
+        request.vehicles.len(),
+        solution.tours.len(),
+        solution.unassigned.len(),
+        start.elapsed().as_millis()
+    );
+
+    let mut features = Vec::new();
+    for tour in solution.tours.iter() {
+        let nodes: Vec<u32> = std::iter::once(vertices[0])
+            .chain(tour.stops.iter().map(|&stop| vertices[stop + 1]))
+            .chain(std::iter::once(vertices[0]))
+            .collect();
+        let mut route = CachedRoute {
+            vertices: vec![vertices[0]],
+            weight: 0,
+        };
+        for (leg, pair) in nodes.windows(2).enumerate() {
+            // consecutive stops at the same vertex need no path
+            if pair[0] == pair[1] {
+                continue;
+            }
+            let path = match find_path(&engine, &state, pair[0], pair[1], None) {
+                Ok(FoundPath { route: path, .. }) => path,
+                Err(RoutingError::Unreachable) => {
+                    return RoutingError::UnreachableLeg {
+                        leg,
+                        from: pair[0],
+                        to: pair[1],
+                    }
+                    .into_json_reply();
+                }
+                Err(error) => return error.into_json_reply(),
+            };
+            route.vertices.extend(path.vertices.iter().skip(1));
+            route.weight = route.weight.saturating_add(path.weight);
+        }
+        let mut properties = Map::new();
+        properties.insert("vehicle".to_string(), tour.vehicle.into());
+        properties.insert("stops".to_string(), json!(tour.stops));
+        properties.insert("load".to_string(), tour.load.into());
+        // breaks are zero-distance legs
+        properties.insert("legs".to_string(), json!(tour.legs));
+        let options = RouteOptions::default();
+        features.push(route_feature(
+            &engine, &state, &route, &options, None, properties,
+        ));
+    }
+    let mut body = feature_collection(features);
+    // each with the reason it could not be planned
+    body["unassigned"] = json!(solution.unassigned);
+    warp::reply::with_status(warp::reply::json(&body), StatusCode::OK)
+}
+
+fn handle_isochrone(
+    request: IsochroneRequest,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> impl warp::Reply {
+    if request.costs.is_empty() || request.costs.len() > MAX_BANDS {
+        return RoutingError::InvalidRequest(format!("costs must have 1 to {} bands", MAX_BANDS))
+            .into_json_reply();
+    }
+    let from = request
+        .coordinate_order
+        .unwrap_or(state.coordinate_order)
+        .to_lon_lat(request.from);
+    if let Err(error) = check_service_area(&state, "from", from) {
+        return error.into_json_reply();
+    }
+
+    let source = match snap_vertex(&engine, &state, "from", from) {
+        Ok(source) => source,
+        Err(failure) => return failure.into_json_reply(),
+    };
+    let body = match request.h3_resolution {
+        Some(resolution) => {
+            match h3_cells(
+                source,
+                &request.costs,
+                resolution,
+                &engine.fmi,
+                &engine.graph,
+            ) {
+                Ok(body) => body,
+                Err(error) => return error.into_json_reply(),
+            }
+        }
+        None => isochrones(source, &request.costs, &engine.fmi, &engine.graph),
+    };
+    warp::reply::with_status(warp::reply::json(&body), StatusCode::OK)
+}
+
+/// Query of GET /isochrone/tile and GET /preview.
+#[derive(Deserialize)]
+struct PointQuery {
+    lon: f64,
+    lat: f64,
+}
+
+/// Answers with the isochrones precomputed for the grid origin closest to the query, without
+/// snapping or searching.
+fn handle_isochrone_tile(
+    query: PointQuery,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> Result<Response<String>, warp::http::Error> {
+    let tiles = match &state.isochrone_tiles {
+        Some(tiles) if tiles.version == engine.version => tiles,
+        Some(_) => {
+            return RoutingError::NoTile(
+                "the isochrone tiles are for the artifacts before the last reload, use POST \
+                 /isochrone"
+                    .to_string(),
+            )
+            .into_response()
+        }
+        None => {
+            return RoutingError::NoTile(
+                "no --isochrone-tiles loaded, use POST /isochrone".to_string(),
+            )
+            .into_response()
+        }
+    };
+    match tiles.get((query.lon, query.lat)) {
+        Some(body) => Response::builder()
+            .header("Content-Type", "application/json")
+            .body(body.to_string()),
+        None => RoutingError::NoTile(format!(
+            "no tile with costs {:?} near ({}, {}), use POST /isochrone",
+            tiles.costs, query.lon, query.lat
+        ))
+        .into_response(),
+    }
+}
+
+/// Snaps every coordinate once and answers all source-target pairs, unreachable pairs are
+/// `null`.
+fn handle_table(
+    request: TableRequest,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> impl warp::Reply {
+    let cells = request.sources.len() * request.targets.len();
+    if cells == 0 || cells > MAX_TABLE_CELLS {
+        return RoutingError::InvalidRequest(format!(
+            "sources x targets must be between 1 and {}",
+            MAX_TABLE_CELLS
+        ))
+        .into_json_reply();
+    }
+
+    let order = request.coordinate_order.unwrap_or(state.coordinate_order);
+    let snap = |name: &str, coordinates: &[(f64, f64)]| -> Result<Vec<u32>, RoutingError> {
+        coordinates
+            .iter()
+            .map(|&coordinate| {
+                let coordinate = order.to_lon_lat(coordinate);
+                check_service_area(&state, name, coordinate)?;
+                snap_vertex(&engine, &state, name, coordinate)
+            })
+            .collect()
+    };
+    let (sources, targets) = match (
+        snap("source", &request.sources),
+        snap("target", &request.targets),
+    ) {
+        (Ok(sources), Ok(targets)) => (sources, targets),
+        (Err(error), _) | (_, Err(error)) => return error.into_json_reply(),
+    };
+
+    let start = Instant::now();
+    let weights = weight_matrix(&engine, &state, &sources, &targets);
+    tracing::info!(
+        "table_request: {} x {}, took: {:>3}ms",
+        sources.len(),
+        targets.len(),
+        start.elapsed().as_millis()
+    );
+
+    let body = json!({ "sources": sources, "targets": targets, "weights": weights });
+    warp::reply::with_status(warp::reply::json(&body), StatusCode::OK)
+}
+
+/// Recomputes the cost of a previously returned route and compares it with the current
+/// optimum between its endpoints, so clients can decide whether to re-route.
+fn handle_route_evaluate(request: EvaluateRequest, engine: Arc<Engine>) -> impl warp::Reply {
+    let (Some(&from), Some(&to)) = (request.vertices.first(), request.vertices.last()) else {
+        return RoutingError::InvalidRequest("vertices must not be empty".to_string())
+            .into_json_reply();
+    };
+    let number_of_vertices = engine.graph.number_of_vertices() as u32;
+    if let Some(&vertex) = request.vertices.iter().find(|&&v| v >= number_of_vertices) {
+        return RoutingError::GraphMismatch(format!("vertex {} does not exist", vertex))
+            .into_json_reply();
+    }
+
+    let weight = match engine.graph.path_weight(&request.vertices) {
+        Ok(weight) => weight,
+        Err(index) => {
+            return RoutingError::BrokenPath {
+                index,
+                from: request.vertices[index],
+                to: request.vertices[index + 1],
+            }
+            .into_json_reply();
+        }
+    };
+
+    // from a vertex to itself, e.g. for a single vertex, the empty path is optimal
+    let optimal_weight = if from == to {
+        Some(0)
+    } else {
+        match path_request(from, to) {
+            Ok(request) => engine
+                .ch
+                .get_shortest_path(&request)
+                .map(|path| path.weight),
+            Err(error) => return error.into_json_reply(),
+        }
+    };
+    let body = json!({
+        "weight": weight,
+        "optimal_weight": optimal_weight,
+        "is_optimal": optimal_weight == Some(weight),
+    });
+    warp::reply::with_status(warp::reply::json(&body), StatusCode::OK)
+}
+
+/// Answers `on_route` without any search if the snapped position is one of the remaining
+/// vertices, and a new route from the position to the destination otherwise.
+fn handle_reroute(
+    request: RerouteRequest,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> Result<Response<String>, warp::http::Error> {
+    let number_of_vertices = engine.graph.number_of_vertices() as u32;
+    let Some(&destination) = request.remaining.last() else {
+        return RoutingError::InvalidRequest("remaining must not be empty".to_string())
+            .into_response();
+    };
+    if let Some(&vertex) = request.remaining.iter().find(|&&v| v >= number_of_vertices) {
+        return RoutingError::GraphMismatch(format!("vertex {} does not exist", vertex))
+            .into_response();
+    }
+
+    if let Err(error) = check_service_area(&state, "position", request.position) {
+        return error.into_response();
+    }
+    let position = match snap_vertex(&engine, &state, "position", request.position) {
+        Ok(position) => position,
+        Err(failure) => return failure.into_response(),
+    };
+    if let Some(index) = request.remaining.iter().position(|&v| v == position) {
+        let body = json!({ "status": "on_route", "vertex": position, "index": index });
+        return Response::builder().body(body.to_string());
+    }
+
+    let mut properties = Map::new();
+    properties.insert("status".to_string(), "rerouted".into());
+    let options = RouteOptions::default();
+    match compute_route(
+        &engine,
+        &state,
+        position,
+        destination,
+        &options,
+        None,
+        properties,
+    ) {
+        Ok((collection, _)) => Response::builder().body(collection.to_string()),
+        Err(failure) => failure.into_response(),
+    }
+}
+
+/// Streams the positions of a vehicle driving the route between two vertices as server-sent
+/// `position` events, ending with an `arrived` event.
+fn handle_debug_drive(query: DriveQuery, engine: Arc<Engine>) -> Box<dyn warp::Reply> {
+    let number_of_vertices = engine.graph.number_of_vertices() as u32;
+    if query.from >= number_of_vertices || query.to >= number_of_vertices {
+        return Box::new(
+            RoutingError::GraphMismatch(format!("vertex ids must be below {}", number_of_vertices))
+                .into_json_reply(),
+        );
+    }
+    if query.speed.is_nan() || query.speed <= 0.0 || query.interval_ms == 0 {
+        return Box::new(
+            RoutingError::InvalidRequest("speed and interval_ms must be positive".to_string())
+                .into_json_reply(),
+        );
+    }
+
+    let request = match path_request(query.from, query.to) {
+        Ok(request) => request,
+        Err(error) => return Box::new(error.into_json_reply()),
+    };
+    let Some(path) = engine.ch.get_shortest_path(&request) else {
+        return Box::new(RoutingError::Unreachable.into_json_reply());
+    };
+    let coordinates = vertex_coordinates(&engine.fmi, &path.vertices);
+    let step = query.speed * query.interval_ms as f64 / 1000.0;
+    let positions = positions(engine.distance_model, &coordinates, step);
+
+    let number_of_positions = positions.len();
+    let mut index = 0;
+    let interval = tokio::time::interval(Duration::from_millis(query.interval_ms));
+    let events = IntervalStream::new(interval)
+        .take(number_of_positions)
+        .map(move |_| {
+            let position = positions[index];
+            index += 1;
+            let name = if index == number_of_positions {
+                "arrived"
+            } else {
+                "position"
+            };
+            let data = json!({ "index": index - 1, "coordinate": position });
+            Ok::<Event, Infallible>(Event::default().event(name).data(data.to_string()))
+        });
+    Box::new(warp::sse::reply(warp::sse::keep_alive().stream(events)))
+}
+
+fn config_json(state: &ServerState) -> Value {
+    json!({
+        "hl_percentage": state.canary.hl_percentage(),
+        "mirror_fraction": state.mirror.fraction(),
+        "threads": {
+            "snap": state.pools.snap.size(),
+            "route": state.pools.route.size(),
+            "serialize": state.pools.serialize.size(),
+        },
+    })
+}
+
+/// Resolves and loads the given artifacts in the background and swaps them in once ready.
+/// Until then, and if resolving or loading fails, requests are answered from the current
+/// engine.
+fn handle_reload(
+    request: ReloadRequest,
+    engines: Arc<SharedEngine>,
+    state: Arc<ServerState>,
+) -> impl warp::Reply {
+    let mut artifacts = state.artifacts.clone();
+    if request.data_dir.is_some() || request.bundle.is_some() {
+        artifacts.data_dir = request.data_dir;
+        artifacts.bundle = request.bundle;
+    }
+    for (path, new_path) in [
+        (&mut artifacts.gr_path, request.gr_path),
+        (&mut artifacts.co_path, request.co_path),
+        (&mut artifacts.ch_path, request.ch_path),
+        (&mut artifacts.hl_path, request.hl_path),
+    ] {
+        if new_path.is_some() {
+            *path = new_path;
+        }
+    }
+    if !engines.start_reload() {
+        return RoutingError::Conflict("a reload is already running".to_string()).into_json_reply();
+    }
+
+    tracing::info!("reload: loading in the background");
+    tokio::spawn(async move {
+        let start = Instant::now();
+        let loaded = tokio::task::spawn_blocking(move || {
+            let paths = artifacts.resolve().map_err(RoutingError::InvalidSettings)?;
+            Engine::load(&paths)
+        })
+        .await;
+        match loaded {
+            Ok(Ok(engine)) => {
+                tracing::info!(
+                    "reload: swapped in version {:016x} after {}s",
+                    engine.version,
+                    start.elapsed().as_secs()
+                );
+                engines.finish_reload(Some(engine));
+            }
+            Ok(Err(error)) => {
+                tracing::warn!("reload: {}, keeping the current artifacts", error);
+                engines.finish_reload(None);
+            }
+            Err(error) => {
+                tracing::error!("reload: {}, keeping the current artifacts", error);
+                engines.finish_reload(None);
+            }
+        }
+    });
+    let body = json!({ "status": "reloading" });
+    warp::reply::with_status(warp::reply::json(&body), StatusCode::ACCEPTED)
+}
+
+/// Changes the settings that need no reload of the artifacts. The update is validated as a
+/// whole and applied only if every setting is valid.
+fn handle_config_update(
+    update: ConfigUpdate,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> impl warp::Reply {
+    let mut errors = Vec::new();
+    if let Some(hl_percentage) = update.hl_percentage {
+        if hl_percentage > 100 {
+            errors.push("hl_percentage: must be between 0 and 100".to_string());
+        } else if hl_percentage > 0 && engine.hl.is_none() {
+            errors.push("hl_percentage: needs HL, but no .hl file is loaded".to_string());
+        }
+    }
+    if let Some(mirror_fraction) = update.mirror_fraction {
+        if !(0.0..=1.0).contains(&mirror_fraction) {
+            errors.push("mirror_fraction: must be between 0 and 1".to_string());
+        } else if mirror_fraction > 0.0 && state.mirror.targets_algorithm() && engine.hl.is_none() {
+            errors.push("mirror_fraction: mirroring to HL needs an .hl file".to_string());
+        }
+    }
+    if !errors.is_empty() {
+        return RoutingError::InvalidSettings(errors).into_json_reply();
+    }
+
+    if let Some(hl_percentage) = update.hl_percentage {
+        state.canary.set_hl_percentage(hl_percentage);
+    }
+    if let Some(mirror_fraction) = update.mirror_fraction {
+        state.mirror.set_fraction(mirror_fraction);
+    }
+    let config = config_json(&state);
+    tracing::info!("config updated: {}", config);
+    warp::reply::with_status(warp::reply::json(&config), StatusCode::OK)
+}
+
+fn handle_vertex(id: u32, engine: Arc<Engine>) -> impl warp::Reply {
+    let Some(point) = engine.fmi.points.get(id as usize) else {
+        return RoutingError::NotFound(format!("vertex {} does not exist", id)).into_json_reply();
+    };
+
+    let body = json!({
+        "id": id,
+        "coordinate": round_coordinate(lon_lat(point)),
+        "out_degree": engine.graph.out_edges(id).len(),
+        "in_degree": engine.graph.in_edges(id).len(),
+    });
+    warp::reply::with_status(warp::reply::json(&body), StatusCode::OK)
+}
+
+/// Where a coordinate snaps to, as a Point feature, followed by the edges at the snapped
+/// vertex as LineString features, without searching a route. Meant for hover feedback.
+fn handle_preview(
+    query: PointQuery,
+    engine: Arc<Engine>,
+    state: Arc<ServerState>,
+) -> Result<Response<String>, warp::http::Error> {
+    let coordinate = (query.lon, query.lat);
+    if let Err(error) = RoutingError::check_coordinate("coordinate", coordinate) {
+        return error.into_response();
+    }
+    let snap = state.pools.snap.run(|| {
+        let start = Instant::now();
+        let snap = engine.snapper.snap(coordinate);
+        state.metrics.snap.observe(start.elapsed());
+        snap
+    });
+    let distance = engine.distance(coordinate, snap.coordinate);
+    let snapped_edge = match snap.target {
+        SnapTarget::Edge { edge, .. } => Some(edge),
+        SnapTarget::Vertex(_) => None,
+    };
+
+    let mut properties = Map::new();
+    properties.insert("vertex".to_string(), snap.vertex.into());
+    properties.insert("distance".to_string(), round(distance, 1).into());
+    if let SnapTarget::Edge { edge, offset } = snap.target {
+        properties.insert("edge".to_string(), engine.graph.arc_id(edge).into());
+        properties.insert("offset".to_string(), round(offset, 6).into());
+    }
+    if let Some(max_snap_distance) = state.max_snap_distance {
+        properties.insert(
+            "within_max_snap_distance".to_string(),
+            (distance <= max_snap_distance).into(),
+        );
+    }
+    let mut features = vec![json!({
+        "type": "Feature",
+        "geometry": { "type": "Point", "coordinates": round_coordinate(snap.coordinate) },
+        "properties": properties,
+    })];
+
+    let graph = &engine.graph;
+    let incident = graph
+        .out_edges(snap.vertex)
+        .iter()
+        .map(|&edge| (edge, "out"))
+        .chain(graph.in_edges(snap.vertex).iter().map(|&edge| (edge, "in")));
+    for (id, direction) in incident {
+        let edge = &graph.edges[id as usize];
+        let mut properties = Map::new();
+        properties.insert("edge".to_string(), graph.arc_id(id).into());
+        properties.insert("direction".to_string(), direction.into());
+        properties.insert("source".to_string(), edge.source.into());
+        properties.insert("target".to_string(), edge.target.into());
+        properties.insert("weight".to_string(), edge.weight.into());
+        properties.insert(
+            "length".to_string(),
+            engine.edge_lengths[id as usize].into(),
+        );
+        properties.insert("snapped".to_string(), (snapped_edge == Some(id)).into());
+        features.push(linestring_feature(
+            &[
+                engine.coordinate(edge.source),
+                engine.coordinate(edge.target),
+            ],
+            properties,
+        ));
+    }
+
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(feature_collection(features).to_string())
+}
+
+/// Searches with `algorithm`, or the canary arm for this pair if none is given, and returns
+/// the FeatureCollection and the weight. The path finders cannot stop at a cost bound, so
+/// `max_cost` is checked after the search and only saves building the geometry.
+fn compute_route(
+    engine: &Engine,
+    state: &ServerState,
+    from: u32,
+    to: u32,
+    options: &RouteOptions,
+    snaps: Option<(Snap, Snap)>,
+    properties: Map<String, Value>,
+) -> Result<(Value, u32), RoutingError> {
+    let start = Instant::now();
+    let found = find_path(engine, state, from, to, options.algorithm)?;
+    let route = &found.route;
+    if let Some(max_cost) = options.max_cost.filter(|&max_cost| route.weight > max_cost) {
+        tracing::info!(
+            from,
+            to,
+            weight = route.weight,
+            max_cost,
+            "exceeds max_cost"
+        );
+        return Err(RoutingError::ExceedsBudget {
+            weight: route.weight,
+            max_cost,
+        });
+    }
+
+    let feature = route_feature(engine, state, route, options, snaps, properties);
+    let weight = route.weight;
+    let mut collection = feature_collection(vec![feature]);
+    collection["metadata"] = route_metadata(engine, options, &[found], start.elapsed());
+    Ok((collection, weight))
+}
+
+/// Starts and ends the geometry at the snapped points of edge snaps. Where the path runs
+/// along the snapped edge, the part before the snapped point is cut off, otherwise the
+/// stretch between the snapped point and the vertex is added. Weights stay those of the
+/// vertices.
+fn split_at_snaps(
+    engine: &Engine,
+    vertices: &[u32],
+    coordinates: &mut Vec<(f64, f64)>,
+    from_snap: Snap,
+    to_snap: Snap,
+) {
+    let other_end = |snap: &Snap| match snap.target {
+        SnapTarget::Edge { edge, .. } => {
+            let edge = &engine.graph.edges[edge as usize];
+            Some(if edge.source == snap.vertex {
+                edge.target
+            } else {
+                edge.source
+            })
+        }
+        SnapTarget::Vertex(_) => None,
+    };
+    if coordinates.is_empty() {
+        return;
+    }
+    if let Some(other) = other_end(&to_snap) {
+        if vertices.len() >= 2 && vertices[vertices.len() - 2] == other {
+            *coordinates.last_mut().unwrap() = to_snap.coordinate;
+        } else {
+            coordinates.push(to_snap.coordinate);
+        }
+    }
+    if let Some(other) = other_end(&from_snap) {
+        if vertices.len() >= 2 && vertices[1] == other {
+            coordinates[0] = from_snap.coordinate;
+        } else {
+            coordinates.insert(0, from_snap.coordinate);
+        }
+    }
+}
+
+/// Path from the cache or from the chosen path finder, recording canary statistics.
+fn find_path(
+    engine: &Engine,
+    state: &ServerState,
+    from: u32,
+    to: u32,
+    algorithm: Option<Arm>,
+) -> Result<FoundPath, RoutingError> {
+    let canary = &state.canary;
+    let (arm, path_finder) = match (algorithm, canary.arm(from, to), &engine.hl) {
+        (Some(Arm::Hl), _, None) => return Err(RoutingError::HlUnavailable),
+        (Some(Arm::Hl), _, Some(hl)) | (None, Arm::Hl, Some(hl)) => (Arm::Hl, hl),
+        _ => (Arm::Ch, &engine.ch),
+    };
+
+    if state.chaos.algorithm_fails() {
+        return Err(RoutingError::Internal(format!(
+            "injected {:?} failure",
+            arm
+        )));
+    }
+    state.chaos.delay();
+
+    let start = Instant::now();
+    // an explicitly chosen algorithm is always asked, so its paths and times are its own
+    let cached = algorithm
+        .is_none()
+        .then(|| state.route_cache.get(engine.version, from, to))
+        .flatten();
+    let cache_hit = cached.is_some();
+    let route = match cached {
+        Some(route) => Some(route),
+        None => {
+            let request = path_request(from, to)?;
+            let route = state.pools.route.run(|| {
+                let start = Instant::now();
+                let path = path_finder.get_shortest_path(&request);
+                state.metrics.query.observe(start.elapsed());
+                path.map(|path| CachedRoute {
+                    vertices: path.vertices,
+                    weight: path.weight,
+                })
+            });
+            canary.record(arm, start.elapsed(), route.is_some());
+            if let Some(route) = &route {
+                state
+                    .route_cache
+                    .insert(engine.version, from, to, route.clone());
+            }
+            route
+        }
+    };
+    let time = start.elapsed();
+
+    let span = tracing::Span::current();
+    span.record("from", from);
+    span.record("to", to);
+    span.record("algorithm", tracing::field::debug(arm));
+    let Some(route) = route else {
+        tracing::info!(from, to, "no path");
+        return Err(RoutingError::Unreachable);
+    };
+    span.record("weight", route.weight);
+    tracing::info!(
+        from,
+        to,
+        weight = route.weight,
+        took_ms = time.as_secs_f64() * 1000.0,
+        algorithm = ?arm,
+        cached = cache_hit,
+        "route"
+    );
+    Ok(FoundPath {
+        route,
+        arm,
+        cached: cache_hit,
+    })
+}
+
+/// A path and how `find_path` got it.
+struct FoundPath {
+    route: CachedRoute,
+    arm: Arm,
+    cached: bool,
+}
+
+/// `metadata` of route responses: how the paths were found, on which graph, with which
+/// options and how long it took, so a client report can be understood without the logs.
+fn route_metadata(
+    engine: &Engine,
+    options: &RouteOptions,
+    found: &[FoundPath],
+    compute_time: Duration,
+) -> Value {
+    let algorithm = match found.first() {
+        Some(first) if found.iter().all(|found| found.arm == first.arm) => json!(first.arm),
+        Some(_) => "mixed".into(),
+        None => Value::Null,
+    };
+    let mut applied = Map::new();
+    if let Some(max_cost) = options.max_cost {
+        applied.insert("max_cost".to_string(), max_cost.into());
+    }
+    if let Some(algorithm) = options.algorithm {
+        applied.insert("algorithm".to_string(), json!(algorithm));
+    }
+    if let Some(viewport) = &options.viewport {
+        applied.insert(
+            "viewport".to_string(),
+            json!([
+                viewport.min_lon,
+                viewport.min_lat,
+                viewport.max_lon,
+                viewport.max_lat
+            ]),
+        );
+    }
+    if let Some(range) = &options.geometry_range {
+        applied.insert("geometry_range".to_string(), json!([range.from, range.to]));
+    }
+    if options.annotations.edge_ids {
+        applied.insert("annotations".to_string(), json!(["edge_ids"]));
+    }
+    applied.insert(
+        "format".to_string(),
+        json!(options.format.unwrap_or_default()),
+    );
+    json!({
+        "algorithm": algorithm,
+        "cached": !found.is_empty() && found.iter().all(|found| found.cached),
+        "graph_version": format!("{:016x}", engine.version),
+        "options": applied,
+        "compute_ms": round(compute_time.as_secs_f64() * 1000.0, 3),
+    })
+}
+
+/// Properties of every route feature, so clients do not have to re-derive them from the
+/// geometry.
+#[derive(Serialize)]
+struct RouteSummary<'a> {
+    weight: u32,
+    /// Meters along the path under the distance model, also with `geometry_range`.
+    length: f64,
+    /// Coordinates of the snapped start and end vertex.
+    start: Option<(f64, f64)>,
+    end: Option<(f64, f64)>,
+    vertices: &'a [u32],
+}
+
+/// The route as a GeoJSON feature with its summary and the optional annotations.
+fn route_feature(
+    engine: &Engine,
+    state: &ServerState,
+    route: &CachedRoute,
+    options: &RouteOptions,
+    snaps: Option<(Snap, Snap)>,
+    mut properties: Map<String, Value>,
+) -> Value {
+    let start = Instant::now();
+    let mut coordinates = vertex_coordinates(&engine.fmi, &route.vertices);
+    if let Some((from_snap, to_snap)) = snaps {
+        split_at_snaps(
+            engine,
+            &route.vertices,
+            &mut coordinates,
+            from_snap,
+            to_snap,
+        );
+    }
+    let length = path_length(engine.distance_model, &coordinates);
+    let summary = RouteSummary {
+        weight: route.weight,
+        length: round(length, 1),
+        start: coordinates.first().copied().map(round_coordinate),
+        end: coordinates.last().copied().map(round_coordinate),
+        vertices: &route.vertices,
+    };
+    if let Value::Object(summary) = serde_json::to_value(summary).unwrap() {
+        properties.extend(summary);
+    }
+    if options.annotations.edge_ids {
+        let edge_ids: Option<Vec<u32>> = route
+            .vertices
+            .windows(2)
+            .map(|pair| {
+                let edge = engine.graph.edge_between(pair[0], pair[1])?;
+                Some(engine.graph.arc_id(edge))
+            })
+            .collect();
+        properties.insert("edge_ids".to_string(), json!(edge_ids));
+    }
+    if let Some(regions) = &state.regions {
+        let traversed = regions.traversed(engine.distance_model, &coordinates);
+        properties.insert("regions".to_string(), traversed);
+    }
+    if let Some(emissions_model) = &state.emissions_model {
+        properties.insert("co2_g".to_string(), emissions_model.estimate(length));
+    }
+    if let Some(range) = &options.geometry_range {
+        properties.insert("geometry_range".to_string(), json!([range.from, range.to]));
+        coordinates = range.slice(engine.distance_model, &coordinates);
+    }
+    let merge = |line: Vec<(f64, f64)>| {
+        if options.full_geometry {
+            line
+        } else {
+            merge_collinear(&line)
+        }
+    };
+    let feature = match &options.viewport {
+        Some(viewport) => {
+            properties.insert("clipped".to_string(), true.into());
+            let lines: Vec<Vec<(f64, f64)>> =
+                viewport.clip(&coordinates).into_iter().map(merge).collect();
+            lines_feature(&lines, properties)
+        }
+        None => linestring_feature(&merge(coordinates), properties),
+    };
+    state.metrics.geometry.observe(start.elapsed());
+    feature
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::fixtures::load_fixtures;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        serve: ServeArgs,
+    }
+
+    fn fixtures_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+    }
+
+    /// Serves the four vertex graph in tests/fixtures with CH only, the graph
+    /// tests/fixtures/record.sh records the fixtures with.
+    fn tiny_server() -> BoxedFilter<(Box<dyn Reply>,)> {
+        let args = Cli::parse_from([
+            "serve".into(),
+            "--gr-path".into(),
+            fixtures_dir().join("tiny.gr").into_os_string(),
+            "--co-path".into(),
+            fixtures_dir().join("tiny.co").into_os_string(),
+            "--ch-path".into(),
+            fixtures_dir().join("tiny.ch").into_os_string(),
+        ])
+        .serve;
+        let engine = Engine::load(&args.artifacts.resolve().unwrap()).unwrap();
+        let state = Arc::new(ServerState::new(&args, &engine));
+        filters(Arc::new(SharedEngine::new(Arc::new(engine))), state, &args)
+    }
+
+    #[tokio::test]
+    async fn answers_the_recorded_requests_identically() {
+        let server = tiny_server();
+        let fixtures = load_fixtures(&fixtures_dir().join("route")).unwrap();
+        assert!(!fixtures.is_empty());
+        for (path, fixture) in fixtures {
+            let mut request = warp::test::request()
+                .method("POST")
+                .path(&format!("/route?{}", fixture.query))
+                .json(&fixture.body);
+            if let Some(accept) = &fixture.accept {
+                request = request.header("accept", accept);
+            }
+            let response = request.reply(&server).await;
+            let body = String::from_utf8(response.body().to_vec()).unwrap();
+            if let Err(difference) = fixture.check(response.status().as_u16(), &body) {
+                panic!("{}: {}", path.display(), difference);
+            }
+        }
+    }
+}
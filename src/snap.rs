@@ -0,0 +1,273 @@
+use std::collections::{HashMap, HashSet};
+
+use osm_converter::sphere::{
+    geometry::point::Point, graph::graph::Fmi,
+    spatial_partition::point_spatial_partition::PointSpatialPartition,
+};
+
+use crate::{geo::lon_lat, graph::Graph};
+
+/// What a snap point stands for.
+#[derive(Clone, Copy, Debug)]
+pub enum SnapTarget {
+    Vertex(u32),
+    /// Interpolated point at `offset` (0 at the source, 1 at the target) along an edge.
+    Edge {
+        edge: u32,
+        offset: f64,
+    },
+}
+
+/// Result of snapping a query coordinate.
+#[derive(Clone, Copy, Debug)]
+pub struct Snap {
+    /// Routing vertex the query starts or ends at.
+    pub vertex: u32,
+    pub target: SnapTarget,
+    /// `(lon, lat)` of the snap point.
+    pub coordinate: (f64, f64),
+}
+
+/// Side of the grid cells edges are indexed in, in degrees (about 1km).
+const EDGE_CELL_DEGREES: f64 = 0.01;
+
+/// Maps query coordinates to the nearest point on the graph.
+pub struct Snapper {
+    point_grid: PointSpatialPartition,
+    point_id_map: HashMap<Point, usize>,
+    /// Routing vertex and target of every snap point. A point on an edge is routed from the
+    /// closer of the two vertices.
+    targets: Vec<(u32, SnapTarget)>,
+    /// `(lon, lat)` of every vertex.
+    coordinates: Vec<(f64, f64)>,
+    edges: Vec<(u32, u32)>,
+    /// Edges passing through each grid cell.
+    edge_grid: HashMap<(i32, i32), Vec<u32>>,
+}
+
+impl Snapper {
+    /// With `spacing` (meters), points are interpolated along every edge longer than it, so
+    /// snapping follows the road shape where vertices are sparse.
+    pub fn new(
+        fmi: &Fmi,
+        graph: &Graph,
+        edge_lengths: &[u32],
+        spacing: Option<f64>,
+        partition_depth: usize,
+    ) -> Snapper {
+        let mut points = Vec::new();
+        let mut targets = Vec::new();
+        if let Some(spacing) = spacing {
+            for (id, edge) in graph.edges.iter().enumerate() {
+                let steps = (edge_lengths[id] as f64 / spacing).ceil() as usize;
+                let source = lon_lat(&fmi.points[edge.source as usize]);
+                let target = lon_lat(&fmi.points[edge.target as usize]);
+                for step in 1..steps {
+                    let offset = step as f64 / steps as f64;
+                    let (lon, lat) = interpolate(source, target, offset);
+                    points.push(Point::from_coordinate(lat, lon));
+                    let vertex = if offset < 0.5 {
+                        edge.source
+                    } else {
+                        edge.target
+                    };
+                    targets.push((
+                        vertex,
+                        SnapTarget::Edge {
+                            edge: id as u32,
+                            offset,
+                        },
+                    ));
+                }
+            }
+        }
+        let first_vertex = points.len();
+        points.extend(fmi.points.iter().cloned());
+        targets.extend((0..fmi.points.len() as u32).map(|id| (id, SnapTarget::Vertex(id))));
+
+        let mut point_grid = PointSpatialPartition::new_root(partition_depth);
+        point_grid.add_points(&points);
+
+        // The first entry for a position wins: duplicate vertices map to the smallest id, see
+        // `canonical_vertices`, and vertices win over interpolated points.
+        let mut point_id_map = HashMap::new();
+        for id in (first_vertex..points.len()).chain(0..first_vertex) {
+            point_id_map.entry(points[id].clone()).or_insert(id);
+        }
+
+        let coordinates: Vec<(f64, f64)> = fmi.points.iter().map(lon_lat).collect();
+        let edges: Vec<(u32, u32)> = graph
+            .edges
+            .iter()
+            .map(|edge| (edge.source, edge.target))
+            .collect();
+        let mut edge_grid: HashMap<(i32, i32), Vec<u32>> = HashMap::new();
+        for (id, &(source, target)) in edges.iter().enumerate() {
+            let (source, target) = (coordinates[source as usize], coordinates[target as usize]);
+            let steps = ((target.1 - source.1)
+                .abs()
+                .max(lon_delta(source.0, target.0).abs())
+                / (EDGE_CELL_DEGREES / 2.0))
+                .ceil() as usize;
+            let mut cells = HashSet::new();
+            for step in 0..=steps {
+                let offset = step as f64 / steps.max(1) as f64;
+                cells.insert(cell(interpolate(source, target, offset)));
+            }
+            for cell in cells {
+                edge_grid.entry(cell).or_default().push(id as u32);
+            }
+        }
+
+        Snapper {
+            point_grid,
+            point_id_map,
+            targets,
+            coordinates,
+            edges,
+            edge_grid,
+        }
+    }
+
+    /// `coordinate` is `(lon, lat)`.
+    pub fn nearest(&self, coordinate: (f64, f64)) -> u32 {
+        self.snap(coordinate).vertex
+    }
+
+    /// Like `nearest`, but also tells whether the hit lies on an edge and where. The query is
+    /// projected onto the edges near it and near the closest indexed point, so a point beside
+    /// the middle of a long segment snaps onto that segment.
+    pub fn snap(&self, coordinate: (f64, f64)) -> Snap {
+        let point = Point::from_coordinate(coordinate.1, coordinate.0);
+        let nearest_point = self.point_grid.get_nearest(&point).unwrap();
+        let (vertex, target) = self.targets[*self.point_id_map.get(&nearest_point).unwrap()];
+        let nearest = Snap {
+            vertex,
+            target,
+            coordinate: lon_lat(&nearest_point),
+        };
+
+        let mut candidates: HashSet<u32> = HashSet::new();
+        let (x, y) = cell(coordinate);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                if let Some(edges) = self.edge_grid.get(&(x + dx, y + dy)) {
+                    candidates.extend(edges);
+                }
+            }
+        }
+        match target {
+            SnapTarget::Vertex(vertex) => candidates.extend(self.incident_edges(vertex)),
+            SnapTarget::Edge { edge, .. } => {
+                candidates.insert(edge);
+            }
+        }
+
+        let mut best = (local_distance(coordinate, nearest.coordinate), nearest);
+        for edge in candidates {
+            let (source, target) = self.edges[edge as usize];
+            let from = self.coordinates[source as usize];
+            let to = self.coordinates[target as usize];
+            let offset = project(coordinate, from, to);
+            let projected = interpolate(from, to, offset);
+            let distance = local_distance(coordinate, projected);
+            if distance >= best.0 {
+                continue;
+            }
+            let snap = if offset <= 0.0 {
+                self.vertex_snap(source)
+            } else if offset >= 1.0 {
+                self.vertex_snap(target)
+            } else {
+                Snap {
+                    vertex: if offset < 0.5 { source } else { target },
+                    target: SnapTarget::Edge { edge, offset },
+                    coordinate: projected,
+                }
+            };
+            best = (distance, snap);
+        }
+        best.1
+    }
+
+    fn vertex_snap(&self, vertex: u32) -> Snap {
+        Snap {
+            vertex,
+            target: SnapTarget::Vertex(vertex),
+            coordinate: self.coordinates[vertex as usize],
+        }
+    }
+
+    fn incident_edges(&self, vertex: u32) -> Vec<u32> {
+        let (x, y) = cell(self.coordinates[vertex as usize]);
+        self.edge_grid
+            .get(&(x, y))
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|&edge| {
+                let (source, target) = self.edges[edge as usize];
+                source == vertex || target == vertex
+            })
+            .collect()
+    }
+}
+
+fn cell(coordinate: (f64, f64)) -> (i32, i32) {
+    (
+        (coordinate.0 / EDGE_CELL_DEGREES).floor() as i32,
+        (coordinate.1 / EDGE_CELL_DEGREES).floor() as i32,
+    )
+}
+
+fn interpolate(from: (f64, f64), to: (f64, f64), offset: f64) -> (f64, f64) {
+    (
+        interpolate_lon(from.0, to.0, offset),
+        from.1 + (to.1 - from.1) * offset,
+    )
+}
+
+/// Position of `point` projected onto the segment, clamped to 0..=1. Uses an equirectangular
+/// approximation around `point`, which is exact enough at snapping distances.
+fn project(point: (f64, f64), from: (f64, f64), to: (f64, f64)) -> f64 {
+    let scale = point.1.to_radians().cos();
+    let (ax, ay) = (lon_delta(point.0, from.0) * scale, from.1 - point.1);
+    let (bx, by) = (lon_delta(point.0, to.0) * scale, to.1 - point.1);
+    let (dx, dy) = (bx - ax, by - ay);
+    let squared_length = dx * dx + dy * dy;
+    if squared_length == 0.0 {
+        return 0.0;
+    }
+    (-(ax * dx + ay * dy) / squared_length).clamp(0.0, 1.0)
+}
+
+/// Squared equirectangular distance in degrees, only good for comparing nearby points.
+fn local_distance(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let dx = lon_delta(from.0, to.0) * from.1.to_radians().cos();
+    let dy = to.1 - from.1;
+    dx * dx + dy * dy
+}
+
+/// `to - from` the shorter way around the globe.
+fn lon_delta(from: f64, to: f64) -> f64 {
+    let mut delta = to - from;
+    if delta > 180.0 {
+        delta -= 360.0;
+    } else if delta < -180.0 {
+        delta += 360.0;
+    }
+    delta
+}
+
+/// Interpolates the shorter way around, so edges crossing the antimeridian are not sampled
+/// across the whole globe.
+fn interpolate_lon(from: f64, to: f64, offset: f64) -> f64 {
+    let lon = from + lon_delta(from, to) * offset;
+    if lon > 180.0 {
+        lon - 360.0
+    } else if lon < -180.0 {
+        lon + 360.0
+    } else {
+        lon
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+
+use crate::error::RoutingError;
+
+/// Where artifacts are read from and written to. Every load and save of an artifact goes
+/// through a store, so new backends only need to implement this trait.
+pub trait ArtifactStore {
+    fn open(&self, location: &Path) -> Result<Box<dyn Read>, RoutingError>;
+
+    fn create(&self, location: &Path) -> Result<Box<dyn Write>, RoutingError>;
+
+    /// A local file with the contents of the artifact, for readers that only take paths, like
+    /// the .gr/.co reader of osm_converter.
+    fn local_path(&self, location: &Path) -> Result<PathBuf, RoutingError>;
+}
+
+/// Files on the local file system.
+pub struct LocalStore;
+
+impl ArtifactStore for LocalStore {
+    fn open(&self, location: &Path) -> Result<Box<dyn Read>, RoutingError> {
+        let file = File::open(location).map_err(|error| {
+            RoutingError::File(format!("cannot open '{}' ({})", location.display(), error))
+        })?;
+        Ok(Box::new(BufReader::new(file)))
+    }
+
+    fn create(&self, location: &Path) -> Result<Box<dyn Write>, RoutingError> {
+        if let Some(parent) = location.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|error| RoutingError::File(error.to_string()))?;
+        }
+        let file = File::create(location).map_err(|error| {
+            RoutingError::File(format!(
+                "cannot create '{}' ({})",
+                location.display(),
+                error
+            ))
+        })?;
+        Ok(Box::new(BufWriter::new(file)))
+    }
+
+    fn local_path(&self, location: &Path) -> Result<PathBuf, RoutingError> {
+        Ok(location.to_path_buf())
+    }
+}
+
+/// Read-only artifacts behind http(s) URLs. `local_path` downloads into `cache_dir` and
+/// revalidates the copy with the server on every later call.
+pub struct HttpStore {
+    cache_dir: PathBuf,
+}
+
+/// `ETag` and `Last-Modified` of a download, kept next to it for conditional requests.
+#[derive(Default, Serialize, Deserialize)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl Validators {
+    fn of(response: &reqwest::blocking::Response) -> Validators {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Validators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+}
+
+/// Tells apart the temporary files of concurrent downloads within one process.
+static DOWNLOADS: AtomicU64 = AtomicU64::new(0);
+
+impl HttpStore {
+    pub fn new() -> HttpStore {
+        let cache_dir = env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(env::temp_dir)
+            .join("fapra_submission");
+        HttpStore { cache_dir }
+    }
+
+    /// GET of `url`, conditional if `cached` is given. `None` if the cached copy is current.
+    /// The blocking client must not run on a tokio worker, so every request gets a thread.
+    fn get(
+        url: String,
+        cached: Option<Validators>,
+    ) -> Result<Option<reqwest::blocking::Response>, RoutingError> {
+        std::thread::spawn(move || {
+            let mut request = reqwest::blocking::Client::new().get(&url);
+            if let Some(cached) = cached {
+                if let Some(etag) = cached.etag {
+                    request = request.header(IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = cached.last_modified {
+                    request = request.header(IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+            let response = request.send().map_err(|error| {
+                RoutingError::File(format!("cannot fetch '{}' ({})", url, error))
+            })?;
+            if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+                return Ok(None);
+            }
+            response
+                .error_for_status()
+                .map(Some)
+                .map_err(|error| RoutingError::File(format!("cannot fetch '{}' ({})", url, error)))
+        })
+        .join()
+        .unwrap()
+    }
+}
+
+impl Default for HttpStore {
+    fn default() -> HttpStore {
+        HttpStore::new()
+    }
+}
+
+impl ArtifactStore for HttpStore {
+    fn open(&self, location: &Path) -> Result<Box<dyn Read>, RoutingError> {
+        let response = Self::get(location.to_string_lossy().to_string(), None)?;
+        Ok(Box::new(response.unwrap()))
+    }
+
+    fn create(&self, location: &Path) -> Result<Box<dyn Write>, RoutingError> {
+        Err(RoutingError::File(format!(
+            "'{}' is read-only",
+            location.display()
+        )))
+    }
+
+    fn local_path(&self, location: &Path) -> Result<PathBuf, RoutingError> {
+        let url = location.to_string_lossy().to_string();
+        let file_name = url.rsplit('/').next().filter(|name| !name.is_empty());
+        let Some(file_name) = file_name else {
+            return Err(RoutingError::File(format!(
+                "'{}' does not name a file",
+                url
+            )));
+        };
+        // keyed by the whole URL, as different URLs often end in the same file name
+        let mut hash = Fnv1a::new();
+        hash.update(url.as_bytes());
+        let local_path = self
+            .cache_dir
+            .join(format!("{:016x}-{}", hash.finish(), file_name));
+        let mut validators_path = local_path.clone().into_os_string();
+        validators_path.push(".validators");
+        let validators_path = PathBuf::from(validators_path);
+
+        let cached = local_path.is_file().then(|| {
+            fs::read(&validators_path)
+                .ok()
+                .and_then(|validators| serde_json::from_slice(&validators).ok())
+                .unwrap_or_default()
+        });
+        let is_cached = cached.is_some();
+        let mut response = match Self::get(url.clone(), cached) {
+            Ok(Some(response)) => response,
+            Ok(None) => return Ok(local_path),
+            Err(error) if is_cached => {
+                tracing::warn!("{}, using the copy at {}", error, local_path.display());
+                return Ok(local_path);
+            }
+            Err(error) => return Err(error),
+        };
+
+        tracing::info!("downloading {} to {}", url, local_path.display());
+        let validators = Validators::of(&response);
+        // download to a name of its own first, so an interrupted download is not reused and
+        // concurrent ones do not mix
+        let mut partial_path = local_path.clone().into_os_string();
+        partial_path.push(format!(
+            ".{}.{}.partial",
+            process::id(),
+            DOWNLOADS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let partial_path = PathBuf::from(partial_path);
+        let mut writer = LocalStore.create(&partial_path)?;
+        io::copy(&mut response, &mut writer)
+            .map_err(|error| RoutingError::File(error.to_string()))?;
+        writer
+            .flush()
+            .map_err(|error| RoutingError::File(error.to_string()))?;
+        drop(writer);
+        // validators of the old copy must not stay with the new one
+        let _ = fs::remove_file(&validators_path);
+        fs::rename(&partial_path, &local_path)
+            .map_err(|error| RoutingError::File(error.to_string()))?;
+        fs::write(&validators_path, serde_json::to_vec(&validators).unwrap())
+            .map_err(|error| RoutingError::File(error.to_string()))?;
+        Ok(local_path)
+    }
+}
+
+pub fn is_url(location: &Path) -> bool {
+    let location = location.to_string_lossy();
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+pub fn store_for(location: &Path) -> Box<dyn ArtifactStore> {
+    if is_url(location) {
+        Box::new(HttpStore::new())
+    } else {
+        Box::new(LocalStore)
+    }
+}
+
+/// 64-bit FNV-1a, stable across runs and Rust versions unlike `DefaultHasher`.
+pub struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for Fnv1a {
+    fn default() -> Fnv1a {
+        Fnv1a::new()
+    }
+}
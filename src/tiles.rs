@@ -0,0 +1,180 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    artifacts::{expand_tilde, ArtifactArgs},
+    engine::Engine,
+    geo::Viewport,
+    isochrone::{isochrones, MAX_BANDS},
+    pools::Pool,
+    storage::store_for,
+};
+
+/// Meters per degree of latitude.
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+#[derive(Args, Debug)]
+pub struct IsochroneTilesArgs {
+    #[command(flatten)]
+    pub artifacts: ArtifactArgs,
+    /// Meters between grid origins. A lookup is answered with the closest one
+    #[arg(long, default_value_t = 1000.0)]
+    pub spacing: f64,
+    /// Cost bounds of the bands, comma separated
+    #[arg(long, value_delimiter = ',', default_value = "300,600,900")]
+    pub costs: Vec<u32>,
+    /// `min_lon,min_lat,max_lon,max_lat` covered by the grid. Defaults to all vertices
+    #[arg(long, value_parser = Viewport::parse)]
+    pub bbox: Option<Viewport>,
+    /// Path of the tile file, loaded by `serve --isochrone-tiles`
+    #[arg(short, long)]
+    pub out_path: PathBuf,
+}
+
+impl IsochroneTilesArgs {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = self.artifacts.validate();
+        if self.spacing.is_nan() || self.spacing <= 0.0 {
+            errors.push(format!(
+                "--spacing: {} is not a positive number of meters",
+                self.spacing
+            ));
+        }
+        if self.costs.is_empty() || self.costs.len() > MAX_BANDS {
+            errors.push(format!("--costs: must have 1 to {} bands", MAX_BANDS));
+        }
+        errors
+    }
+}
+
+/// Isochrones precomputed on a regular lon/lat grid, written by `isochrone-tiles`.
+#[derive(Serialize, Deserialize)]
+pub struct IsochroneTiles {
+    pub version: u64,
+    pub costs: Vec<u32>,
+    min_lon: f64,
+    min_lat: f64,
+    lon_step: f64,
+    lat_step: f64,
+    /// FeatureCollection body per `(column, row)` of the grid. Origins without a road within
+    /// one spacing have none.
+    tiles: HashMap<(u32, u32), String>,
+}
+
+impl IsochroneTiles {
+    /// Body for the grid origin closest to `(lon, lat)`.
+    pub fn get(&self, (lon, lat): (f64, f64)) -> Option<&str> {
+        let column = ((lon - self.min_lon) / self.lon_step).round();
+        let row = ((lat - self.min_lat) / self.lat_step).round();
+        if column < 0.0 || row < 0.0 {
+            return None;
+        }
+        self.tiles
+            .get(&(column as u32, row as u32))
+            .map(String::as_str)
+    }
+}
+
+/// Snaps every grid origin and computes its isochrones on all cores. The grid is square in
+/// meters at the middle latitude of the bounding box.
+pub fn isochrone_tiles(args: &IsochroneTilesArgs) {
+    let engine = Engine::load_or_exit(&args.artifacts.resolve_or_exit());
+    let bbox = args.bbox.unwrap_or_else(|| {
+        let coordinates =
+            (0..engine.fmi.points.len() as u32).map(|vertex| engine.coordinate(vertex));
+        coordinates.fold(
+            Viewport {
+                min_lon: f64::INFINITY,
+                min_lat: f64::INFINITY,
+                max_lon: f64::NEG_INFINITY,
+                max_lat: f64::NEG_INFINITY,
+            },
+            |bbox, (lon, lat)| Viewport {
+                min_lon: bbox.min_lon.min(lon),
+                min_lat: bbox.min_lat.min(lat),
+                max_lon: bbox.max_lon.max(lon),
+                max_lat: bbox.max_lat.max(lat),
+            },
+        )
+    });
+
+    let lat_step = args.spacing / METERS_PER_DEGREE;
+    let middle = ((bbox.min_lat + bbox.max_lat) / 2.0)
+        .to_radians()
+        .cos()
+        .max(0.01);
+    let lon_step = lat_step / middle;
+    let columns = ((bbox.max_lon - bbox.min_lon) / lon_step).ceil().max(0.0) as u32 + 1;
+    let rows = ((bbox.max_lat - bbox.min_lat) / lat_step).ceil().max(0.0) as u32 + 1;
+    let cells: Vec<(u32, u32)> = (0..columns)
+        .flat_map(|column| (0..rows).map(move |row| (column, row)))
+        .collect();
+    println!(
+        "computing isochrones for {} origins ({} x {})",
+        cells.len(),
+        columns,
+        rows
+    );
+
+    let cores = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let bodies = Pool::new(cores).map(&cells, |&(column, row)| {
+        let origin = (
+            bbox.min_lon + column as f64 * lon_step,
+            bbox.min_lat + row as f64 * lat_step,
+        );
+        let snap = engine.snapper.snap(origin);
+        if engine.distance(origin, snap.coordinate) > args.spacing {
+            return None;
+        }
+        let mut body = isochrones(snap.vertex, &args.costs, &engine.fmi, &engine.graph);
+        body["origin"] = json!(origin);
+        body["vertex"] = json!(snap.vertex);
+        Some(body.to_string())
+    });
+
+    let mut costs = args.costs.clone();
+    costs.sort_unstable();
+    costs.dedup();
+    let tiles = IsochroneTiles {
+        version: engine.version,
+        costs,
+        min_lon: bbox.min_lon,
+        min_lat: bbox.min_lat,
+        lon_step,
+        lat_step,
+        tiles: cells
+            .into_iter()
+            .zip(bodies)
+            .filter_map(|(cell, body)| Some((cell, body?)))
+            .collect(),
+    };
+
+    let out_path = expand_tilde(&args.out_path);
+    let writer = store_for(&out_path).create(&out_path).unwrap();
+    bincode::serialize_into(writer, &tiles).unwrap();
+    println!("wrote {} tiles", tiles.tiles.len());
+}
+
+/// Reads a tile file. One written for other artifacts is ignored, as its vertices would be
+/// wrong.
+pub fn load_isochrone_tiles(path: &Path, version: u64) -> Option<IsochroneTiles> {
+    let reader = store_for(path).open(path).unwrap();
+    let tiles: IsochroneTiles = bincode::deserialize_from(reader).unwrap();
+    if tiles.version != version {
+        println!(
+            "isochrone tiles '{}' were written for other artifacts, ignoring them",
+            path.display()
+        );
+        return None;
+    }
+    println!("loaded {} isochrone tiles", tiles.tiles.len());
+    Some(tiles)
+}
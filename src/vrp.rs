@@ -0,0 +1,662 @@
+use serde::{Deserialize, Serialize};
+
+use crate::geo::CoordinateOrder;
+
+pub const MAX_STOPS: usize = 99;
+
+/// Body of POST /vrp. Every vehicle starts and ends at the depot.
+#[derive(Deserialize)]
+pub struct VrpRequest {
+    pub depot: (f64, f64),
+    pub vehicles: Vec<Vehicle>,
+    pub stops: Vec<Stop>,
+    /// `(pickup, delivery)` stop indices. The demand of the pickup is carried to the delivery
+    /// by the same vehicle, the demand of the delivery is ignored.
+    #[serde(default)]
+    pub pairs: Vec<(usize, usize)>,
+    #[serde(default)]
+    pub coordinate_order: Option<CoordinateOrder>,
+}
+
+#[derive(Clone, Default, Deserialize)]
+pub struct Vehicle {
+    pub capacity: u32,
+    /// Longest tour in weight units, including waiting and breaks.
+    #[serde(default)]
+    pub max_duration: Option<u32>,
+    /// Driving time after which a break of `break_duration` is due. Breaks are taken at the
+    /// stop before the leg that would exceed it.
+    #[serde(default)]
+    pub break_after: Option<u32>,
+    #[serde(default)]
+    pub break_duration: u32,
+}
+
+#[derive(Deserialize)]
+pub struct Stop {
+    pub coordinate: (f64, f64),
+    /// Delivered from the depot, unless the stop is part of a pair.
+    pub demand: u32,
+    /// Earliest and latest arrival in weight units after leaving the depot. Arriving early
+    /// means waiting.
+    #[serde(default)]
+    pub time_window: Option<(u32, u32)>,
+}
+
+pub struct Tour {
+    pub vehicle: usize,
+    /// Stop indices in visiting order.
+    pub stops: Vec<usize>,
+    /// Highest load along the tour.
+    pub load: u64,
+    pub legs: Vec<Leg>,
+}
+
+/// A drive between two stops, `None` being the depot, or a break at `from == to`.
+#[derive(Clone, Serialize)]
+pub struct Leg {
+    pub from: Option<usize>,
+    pub to: Option<usize>,
+    pub start: u64,
+    pub duration: u64,
+    #[serde(rename = "break")]
+    pub is_break: bool,
+}
+
+/// Timeline of a feasible tour.
+struct Schedule {
+    peak_load: u64,
+    legs: Vec<Leg>,
+}
+
+/// A stop no vehicle visits, and why.
+#[derive(Serialize)]
+pub struct Unassigned {
+    pub stop: usize,
+    pub reason: String,
+}
+
+pub struct Solution {
+    pub tours: Vec<Tour>,
+    pub unassigned: Vec<Unassigned>,
+}
+
+/// Cost of a missing path, high enough that no heuristic step prefers it.
+const UNREACHABLE: u64 = 1 << 40;
+
+#[derive(Clone, Copy)]
+enum Role {
+    Single,
+    Pickup { delivery: usize },
+    Delivery { pickup: usize },
+}
+
+/// Everything a tour has to satisfy besides the vehicle capacity.
+struct Problem<'a> {
+    weights: &'a [Vec<Option<u32>>],
+    demands: &'a [u32],
+    time_windows: Vec<Option<(u32, u32)>>,
+    roles: Vec<Role>,
+}
+
+impl Problem<'_> {
+    /// Cost between matrix indices, the depot is 0 and stop `i` is `i + 1`.
+    fn cost(&self, from: usize, to: usize) -> u64 {
+        self.weights[from][to].map_or(UNREACHABLE, |weight| weight as u64)
+    }
+
+    fn tour_cost(&self, tour: &[usize]) -> u64 {
+        let nodes: Vec<usize> = std::iter::once(0)
+            .chain(tour.iter().map(|&stop| stop + 1))
+            .chain(std::iter::once(0))
+            .collect();
+        nodes
+            .windows(2)
+            .map(|pair| self.cost(pair[0], pair[1]))
+            .sum()
+    }
+
+    /// Timeline of the tour driven by `vehicle`, or `None` if a time window, the precedence
+    /// of a pair or the maximum duration is violated.
+    fn schedule(&self, tour: &[usize], vehicle: &Vehicle) -> Option<Schedule> {
+        let mut load: u64 = tour
+            .iter()
+            .filter(|&&stop| matches!(self.roles[stop], Role::Single))
+            .map(|&stop| self.demands[stop] as u64)
+            .sum();
+        let mut peak_load = load;
+        let mut legs = Vec::new();
+        let mut time = 0;
+        // driving time since the last break
+        let mut driven = 0;
+        let mut previous = None;
+        let node = |stop: Option<usize>| stop.map_or(0, |stop| stop + 1);
+        for (position, next) in tour
+            .iter()
+            .copied()
+            .map(Some)
+            .chain(std::iter::once(None))
+            .enumerate()
+        {
+            let duration = self.cost(node(previous), node(next));
+            if duration >= UNREACHABLE {
+                return None;
+            }
+            if let Some(break_after) = vehicle.break_after {
+                if driven > 0 && driven + duration > break_after as u64 {
+                    legs.push(Leg {
+                        from: previous,
+                        to: previous,
+                        start: time,
+                        duration: vehicle.break_duration as u64,
+                        is_break: true,
+                    });
+                    time += vehicle.break_duration as u64;
+                    driven = 0;
+                }
+            }
+            legs.push(Leg {
+                from: previous,
+                to: next,
+                start: time,
+                duration,
+                is_break: false,
+            });
+            time += duration;
+            driven += duration;
+            previous = next;
+
+            let Some(stop) = next else {
+                break;
+            };
+            if let Some((earliest, latest)) = self.time_windows[stop] {
+                time = time.max(earliest as u64);
+                if time > latest as u64 {
+                    return None;
+                }
+            }
+            match self.roles[stop] {
+                Role::Single => load -= self.demands[stop] as u64,
+                Role::Pickup { delivery } => {
+                    if !tour[position..].contains(&delivery) {
+                        return None;
+                    }
+                    load += self.demands[stop] as u64;
+                }
+                Role::Delivery { pickup } => {
+                    if !tour[..position].contains(&pickup) {
+                        return None;
+                    }
+                    load -= self.demands[pickup] as u64;
+                }
+            }
+            peak_load = peak_load.max(load);
+        }
+        if vehicle
+            .max_duration
+            .is_some_and(|max_duration| time > max_duration as u64)
+        {
+            return None;
+        }
+        Some(Schedule { peak_load, legs })
+    }
+
+    fn fits(&self, tour: &[usize], vehicle: &Vehicle) -> bool {
+        self.schedule(tour, vehicle)
+            .is_some_and(|schedule| schedule.peak_load <= vehicle.capacity as u64)
+    }
+
+    /// Why `stop` cannot be served even alone, if that is already clear.
+    fn infeasibility(&self, stop: usize, vehicles: &[Vehicle]) -> Option<String> {
+        let max_capacity = vehicles
+            .iter()
+            .map(|vehicle| vehicle.capacity)
+            .max()
+            .unwrap_or(0);
+        if self.cost(0, stop + 1) >= UNREACHABLE || self.cost(stop + 1, 0) >= UNREACHABLE {
+            return Some("not reachable from the depot or cannot return to it".to_string());
+        }
+        let demand = match self.roles[stop] {
+            Role::Delivery { pickup } => self.demands[pickup],
+            _ => self.demands[stop],
+        };
+        if demand > max_capacity {
+            return Some(format!(
+                "demand {} exceeds the largest capacity {}",
+                demand, max_capacity
+            ));
+        }
+        if let Some((_, latest)) = self.time_windows[stop] {
+            let earliest_arrival = self.cost(0, stop + 1);
+            if earliest_arrival > latest as u64 {
+                return Some(format!(
+                    "time window ends at {}, but the earliest arrival is {}",
+                    latest, earliest_arrival
+                ));
+            }
+        }
+        let unlimited = Vehicle {
+            capacity: u32::MAX,
+            ..Vehicle::default()
+        };
+        match self.roles[stop] {
+            Role::Pickup { delivery } if self.schedule(&[stop, delivery], &unlimited).is_none() => {
+                return Some(format!(
+                    "delivery {} cannot be reached in time after the pickup",
+                    delivery
+                ));
+            }
+            Role::Delivery { pickup } if self.schedule(&[pickup, stop], &unlimited).is_none() => {
+                return Some(format!(
+                    "cannot be reached in time after its pickup {}",
+                    pickup
+                ));
+            }
+            _ => {}
+        }
+        let alone = match self.roles[stop] {
+            Role::Single => vec![stop],
+            Role::Pickup { delivery } => vec![stop, delivery],
+            Role::Delivery { pickup } => vec![pickup, stop],
+        };
+        if !vehicles
+            .iter()
+            .any(|vehicle| self.schedule(&alone, vehicle).is_some())
+        {
+            return Some("no vehicle can visit it within its maximum duration".to_string());
+        }
+        None
+    }
+}
+
+/// Plans tours for `weights` between the depot (index 0) and the stops (index `stop + 1`).
+/// Without pairs and time windows this is Clarke-Wright savings, otherwise cheapest
+/// insertion that keeps every tour feasible. Both are followed by Or-opt on every tour.
+pub fn solve(
+    weights: &[Vec<Option<u32>>],
+    stops: &[Stop],
+    pairs: &[(usize, usize)],
+    vehicles: &[Vehicle],
+) -> Solution {
+    let demands: Vec<u32> = stops.iter().map(|stop| stop.demand).collect();
+    let mut roles = vec![Role::Single; stops.len()];
+    for &(pickup, delivery) in pairs.iter() {
+        roles[pickup] = Role::Pickup { delivery };
+        roles[delivery] = Role::Delivery { pickup };
+    }
+    let problem = Problem {
+        weights,
+        demands: &demands,
+        time_windows: stops.iter().map(|stop| stop.time_window).collect(),
+        roles,
+    };
+
+    let mut unassigned = Vec::new();
+    let mut feasible = Vec::new();
+    for stop in 0..stops.len() {
+        match problem.infeasibility(stop, vehicles) {
+            Some(reason) => unassigned.push(Unassigned { stop, reason }),
+            None => feasible.push(stop),
+        }
+    }
+    // a pair is only planned if both of its stops can be
+    let dropped: Vec<Unassigned> = pairs
+        .iter()
+        .flat_map(|&(pickup, delivery)| [(pickup, delivery), (delivery, pickup)])
+        .filter(|(stop, partner)| feasible.contains(stop) && !feasible.contains(partner))
+        .map(|(stop, partner)| Unassigned {
+            stop,
+            reason: format!("its paired stop {} cannot be served", partner),
+        })
+        .collect();
+    feasible.retain(|stop| !dropped.iter().any(|dropped| dropped.stop == *stop));
+    unassigned.extend(dropped);
+
+    let constrained = !pairs.is_empty()
+        || stops.iter().any(|stop| stop.time_window.is_some())
+        || vehicles
+            .iter()
+            .any(|vehicle| vehicle.max_duration.is_some() || vehicle.break_after.is_some());
+    let (tours, not_planned) = if constrained {
+        insertion(&problem, &feasible, vehicles)
+    } else {
+        savings(&problem, &feasible, vehicles)
+    };
+    unassigned.extend(not_planned);
+
+    let mut tours: Vec<Tour> = tours
+        .into_iter()
+        .filter(|(_, stops)| !stops.is_empty())
+        .map(|(vehicle, stops)| {
+            let stops = or_opt(&problem, stops, &vehicles[vehicle]);
+            let schedule = problem
+                .schedule(&stops, &vehicles[vehicle])
+                .unwrap_or(Schedule {
+                    peak_load: 0,
+                    legs: Vec::new(),
+                });
+            Tour {
+                vehicle,
+                stops,
+                load: schedule.peak_load,
+                legs: schedule.legs,
+            }
+        })
+        .collect();
+    tours.sort_by_key(|tour| tour.vehicle);
+    unassigned.sort_by_key(|unassigned| unassigned.stop);
+    Solution { tours, unassigned }
+}
+
+/// Clarke-Wright savings for the largest vehicle, then every tour into the smallest free
+/// vehicle it fits.
+fn savings(
+    problem: &Problem,
+    stops: &[usize],
+    vehicles: &[Vehicle],
+) -> (Vec<(usize, Vec<usize>)>, Vec<Unassigned>) {
+    let capacities: Vec<u32> = vehicles.iter().map(|vehicle| vehicle.capacity).collect();
+    let max_capacity = capacities.iter().copied().max().unwrap_or(0) as u64;
+    let number_of_stops = problem.demands.len();
+    let mut tours: Vec<Option<Vec<usize>>> = vec![None; number_of_stops];
+    let mut loads = vec![0u64; number_of_stops];
+    let mut tour_of: Vec<usize> = (0..number_of_stops).collect();
+    for &stop in stops.iter() {
+        tours[stop] = Some(vec![stop]);
+        loads[stop] = problem.demands[stop] as u64;
+    }
+
+    let mut savings: Vec<(u64, usize, usize)> = Vec::new();
+    for &i in stops.iter() {
+        for &j in stops.iter() {
+            let direct = problem.cost(i + 1, j + 1);
+            let saving = (problem.cost(i + 1, 0) + problem.cost(0, j + 1)).saturating_sub(direct);
+            if i != j && direct < UNREACHABLE && saving > 0 {
+                savings.push((saving, i, j));
+            }
+        }
+    }
+    savings.sort_unstable_by(|a, b| b.cmp(a));
+    for (_, i, j) in savings {
+        let (tour_i, tour_j) = (tour_of[i], tour_of[j]);
+        if tour_i == tour_j || loads[tour_i] + loads[tour_j] > max_capacity {
+            continue;
+        }
+        let ends_with_i = tours[tour_i].as_ref().and_then(|tour| tour.last()) == Some(&i);
+        let starts_with_j = tours[tour_j].as_ref().and_then(|tour| tour.first()) == Some(&j);
+        if !ends_with_i || !starts_with_j {
+            continue;
+        }
+        let appended = tours[tour_j].take().unwrap();
+        for &stop in appended.iter() {
+            tour_of[stop] = tour_i;
+        }
+        tours[tour_i].as_mut().unwrap().extend(appended);
+        loads[tour_i] += loads[tour_j];
+    }
+
+    let mut tours: Vec<(u64, Vec<usize>)> = tours
+        .into_iter()
+        .enumerate()
+        .filter_map(|(id, tour)| Some((loads[id], tour?)))
+        .collect();
+    // heaviest tours first, each into the smallest free vehicle it fits
+    tours.sort_by_key(|(load, _)| std::cmp::Reverse(*load));
+    let mut free: Vec<usize> = (0..capacities.len()).collect();
+    free.sort_by_key(|&vehicle| capacities[vehicle]);
+    let mut assigned = Vec::new();
+    let mut unassigned = Vec::new();
+    for (load, stops) in tours {
+        match free
+            .iter()
+            .position(|&vehicle| capacities[vehicle] as u64 >= load)
+        {
+            Some(position) => assigned.push((free.remove(position), stops)),
+            None => unassigned.extend(stops.into_iter().map(|stop| Unassigned {
+                stop,
+                reason: "no vehicle is left for its tour".to_string(),
+            })),
+        }
+    }
+    (assigned, unassigned)
+}
+
+/// Inserts stops, pairs as a whole, where they add the least cost while every tour stays
+/// feasible. Stops with the earliest deadline go first.
+fn insertion(
+    problem: &Problem,
+    stops: &[usize],
+    vehicles: &[Vehicle],
+) -> (Vec<(usize, Vec<usize>)>, Vec<Unassigned>) {
+    let mut units: Vec<Vec<usize>> = stops
+        .iter()
+        .filter_map(|&stop| match problem.roles[stop] {
+            Role::Single => Some(vec![stop]),
+            Role::Pickup { delivery } => Some(vec![stop, delivery]),
+            Role::Delivery { .. } => None,
+        })
+        .collect();
+    let deadline = |unit: &Vec<usize>| {
+        unit.iter()
+            .filter_map(|&stop| problem.time_windows[stop].map(|(_, latest)| latest))
+            .min()
+            .unwrap_or(u32::MAX)
+    };
+    units.sort_by_key(|unit| (deadline(unit), std::cmp::Reverse(problem.demands[unit[0]])));
+
+    // one tour per vehicle
+    let mut tours: Vec<Vec<usize>> = vec![Vec::new(); vehicles.len()];
+    let mut unassigned = Vec::new();
+    for unit in units {
+        // (added cost, vehicle, new stops of its tour)
+        let mut best: Option<(u64, usize, Vec<usize>)> = None;
+        for (vehicle, tour) in tours.iter().enumerate() {
+            let base = problem.tour_cost(tour);
+            for first in 0..=tour.len() {
+                let lasts = if unit.len() == 2 {
+                    first..tour.len() + 1
+                } else {
+                    0..1
+                };
+                for last in lasts {
+                    let mut candidate = tour.clone();
+                    candidate.insert(first, unit[0]);
+                    if unit.len() == 2 {
+                        candidate.insert(last + 1, unit[1]);
+                    }
+                    let added = problem.tour_cost(&candidate).saturating_sub(base);
+                    let better = best.as_ref().is_none_or(|(cost, _, _)| added < *cost);
+                    if better && problem.fits(&candidate, &vehicles[vehicle]) {
+                        best = Some((added, vehicle, candidate));
+                    }
+                }
+            }
+        }
+        match best {
+            Some((_, vehicle, candidate)) => tours[vehicle] = candidate,
+            None => unassigned.extend(unit.into_iter().map(|stop| {
+                Unassigned {
+                    stop,
+                    reason: "no vehicle can serve it within capacities, time windows, pickup \
+                             order and working time"
+                        .to_string(),
+                }
+            })),
+        }
+    }
+    (tours.into_iter().enumerate().collect(), unassigned)
+}
+
+/// Moves segments of one to three stops to their cheapest position until nothing improves,
+/// keeping the tour feasible.
+fn or_opt(problem: &Problem, mut tour: Vec<usize>, vehicle: &Vehicle) -> Vec<usize> {
+    let mut best = problem.tour_cost(&tour);
+    let mut improved = true;
+    while improved {
+        improved = false;
+        'search: for length in 1..=tour.len().min(3) {
+            for start in 0..=tour.len() - length {
+                let mut rest = tour.clone();
+                let segment: Vec<usize> = rest.drain(start..start + length).collect();
+                for position in 0..=rest.len() {
+                    if position == start {
+                        continue;
+                    }
+                    let mut candidate = rest.clone();
+                    candidate.splice(position..position, segment.iter().copied());
+                    let candidate_cost = problem.tour_cost(&candidate);
+                    if candidate_cost < best && problem.fits(&candidate, vehicle) {
+                        best = candidate_cost;
+                        tour = candidate;
+                        improved = true;
+                        break 'search;
+                    }
+                }
+            }
+        }
+    }
+    tour
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Depot and two stops, 10 between the depot and a stop and 5 between the stops.
+    fn weights() -> Vec<Vec<Option<u32>>> {
+        vec![
+            vec![Some(0), Some(10), Some(10)],
+            vec![Some(10), Some(0), Some(5)],
+            vec![Some(10), Some(5), Some(0)],
+        ]
+    }
+
+    fn problem<'a>(weights: &'a [Vec<Option<u32>>], demands: &'a [u32]) -> Problem<'a> {
+        Problem {
+            weights,
+            demands,
+            time_windows: vec![None; demands.len()],
+            roles: vec![Role::Single; demands.len()],
+        }
+    }
+
+    /// `(from, to, start, duration, is_break)` of a leg.
+    type Timed = (Option<usize>, Option<usize>, u64, u64, bool);
+
+    fn timeline(schedule: &Schedule) -> Vec<Timed> {
+        schedule
+            .legs
+            .iter()
+            .map(|leg| (leg.from, leg.to, leg.start, leg.duration, leg.is_break))
+            .collect()
+    }
+
+    #[test]
+    fn schedules_legs_back_to_back() {
+        let weights = weights();
+        let problem = problem(&weights, &[2, 3]);
+        let schedule = problem.schedule(&[0, 1], &Vehicle::default()).unwrap();
+        assert_eq!(schedule.peak_load, 5);
+        assert_eq!(
+            timeline(&schedule),
+            vec![
+                (None, Some(0), 0, 10, false),
+                (Some(0), Some(1), 10, 5, false),
+                (Some(1), None, 15, 10, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn waits_for_the_time_window() {
+        let weights = weights();
+        let mut problem = problem(&weights, &[2, 3]);
+        problem.time_windows[1] = Some((20, 30));
+        let schedule = problem.schedule(&[0, 1], &Vehicle::default()).unwrap();
+        assert_eq!(timeline(&schedule)[2], (Some(1), None, 20, 10, false));
+
+        problem.time_windows[1] = Some((0, 12));
+        assert!(problem.schedule(&[0, 1], &Vehicle::default()).is_none());
+    }
+
+    #[test]
+    fn takes_breaks_before_the_leg_that_would_exceed_the_driving_time() {
+        let weights = weights();
+        let problem = problem(&weights, &[2, 3]);
+        let vehicle = Vehicle {
+            break_after: Some(12),
+            break_duration: 3,
+            ..Vehicle::default()
+        };
+        let schedule = problem.schedule(&[0, 1], &vehicle).unwrap();
+        assert_eq!(
+            timeline(&schedule),
+            vec![
+                (None, Some(0), 0, 10, false),
+                (Some(0), Some(0), 10, 3, true),
+                (Some(0), Some(1), 13, 5, false),
+                (Some(1), Some(1), 18, 3, true),
+                (Some(1), None, 21, 10, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn limits_the_duration() {
+        let weights = weights();
+        let problem = problem(&weights, &[2, 3]);
+        let vehicle = |max_duration| Vehicle {
+            max_duration: Some(max_duration),
+            ..Vehicle::default()
+        };
+        assert!(problem.schedule(&[0, 1], &vehicle(25)).is_some());
+        assert!(problem.schedule(&[0, 1], &vehicle(24)).is_none());
+    }
+
+    #[test]
+    fn carries_pickups_to_their_delivery() {
+        let weights = weights();
+        let mut problem = problem(&weights, &[4, 0]);
+        problem.roles = vec![Role::Pickup { delivery: 1 }, Role::Delivery { pickup: 0 }];
+        let schedule = problem.schedule(&[0, 1], &Vehicle::default()).unwrap();
+        assert_eq!(schedule.peak_load, 4);
+        assert!(problem.schedule(&[1, 0], &Vehicle::default()).is_none());
+    }
+
+    #[test]
+    fn fails_on_a_missing_path() {
+        let mut weights = weights();
+        weights[1][2] = None;
+        let problem = problem(&weights, &[2, 3]);
+        assert!(problem.schedule(&[0, 1], &Vehicle::default()).is_none());
+        assert!(problem.schedule(&[1, 0], &Vehicle::default()).is_some());
+    }
+
+    #[test]
+    fn solves_small_problems() {
+        let stop = |demand| Stop {
+            coordinate: (0.0, 0.0),
+            demand,
+            time_window: None,
+        };
+        let vehicle = Vehicle {
+            capacity: 5,
+            ..Vehicle::default()
+        };
+        let vehicles = [vehicle];
+        let solution = solve(&weights(), &[stop(2), stop(3)], &[], &vehicles);
+        assert_eq!(solution.tours.len(), 1);
+        assert_eq!(solution.tours[0].load, 5);
+        assert_eq!(solution.tours[0].legs.len(), 3);
+        assert!(solution.unassigned.is_empty());
+
+        let solution = solve(&weights(), &[stop(2), stop(6)], &[], &vehicles);
+        assert_eq!(solution.tours[0].stops, vec![0]);
+        assert_eq!(solution.unassigned.len(), 1);
+        assert_eq!(solution.unassigned[0].stop, 1);
+        assert_eq!(
+            solution.unassigned[0].reason,
+            "demand 6 exceeds the largest capacity 5"
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use crate::graph::Graph;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Route {
+    pub vertices: Vec<u32>,
+    pub edges: Vec<u32>,
+    pub weight: u32,
+}
+
+/// Yen's k shortest loopless paths over a shortest path oracle, in the server the CH or HL
+/// path finder. `shortest_path(from, to)` returns the vertices of a shortest path.
+///
+/// A spur path is the oracle's path whenever that avoids the vertices and edges banned for
+/// it, which it does for most spur vertices. Otherwise it is found with A* on the raw graph,
+/// with the oracle's distances to `target` as potentials, computed only for the vertices the
+/// search reaches. Banning edges only makes distances longer, so the potentials stay
+/// consistent for every spur search. Both rely on the oracle being exact on `graph`, which
+/// holds as the artifacts are built from the same .gr file, unless it was sanitized.
+pub fn k_shortest_paths(
+    graph: &Graph,
+    shortest_path: impl Fn(u32, u32) -> Option<Vec<u32>>,
+    source: u32,
+    target: u32,
+    k: usize,
+) -> Vec<Route> {
+    let mut routes = Vec::new();
+    let Some(first) = oracle_route(graph, &shortest_path, source, target) else {
+        return routes;
+    };
+    routes.push(first);
+
+    let mut potentials: HashMap<u32, u32> = HashMap::new();
+    let mut potential = |vertex: u32| {
+        *potentials.entry(vertex).or_insert_with(|| {
+            oracle_route(graph, &shortest_path, vertex, target)
+                .map_or(u32::MAX, |route| route.weight)
+        })
+    };
+
+    let mut candidates: Vec<Route> = Vec::new();
+    while routes.len() < k {
+        let previous = routes.last().unwrap().clone();
+        for j in 0..previous.edges.len() {
+            let spur_vertex = previous.vertices[j];
+            let root_vertices = &previous.vertices[..=j];
+            let root_edges = &previous.edges[..j];
+
+            let banned_edges: HashSet<u32> = routes
+                .iter()
+                .filter(|route| route.vertices.len() > j && &route.vertices[..=j] == root_vertices)
+                .map(|route| route.edges[j])
+                .collect();
+            let banned_vertices: HashSet<u32> = root_vertices[..j].iter().copied().collect();
+
+            let spur = oracle_route(graph, &shortest_path, spur_vertex, target)
+                .filter(|route| {
+                    route.edges.iter().all(|edge| !banned_edges.contains(edge))
+                        && route
+                            .vertices
+                            .iter()
+                            .all(|vertex| !banned_vertices.contains(vertex))
+                })
+                .or_else(|| {
+                    a_star(
+                        graph,
+                        spur_vertex,
+                        target,
+                        &mut potential,
+                        &banned_vertices,
+                        &banned_edges,
+                    )
+                });
+            let Some(spur) = spur else {
+                continue;
+            };
+
+            let root_weight = root_edges
+                .iter()
+                .map(|&edge_id| graph.edges[edge_id as usize].weight)
+                .fold(0u32, u32::saturating_add);
+            let mut vertices = root_vertices[..j].to_vec();
+            vertices.extend(spur.vertices);
+            let mut edges = root_edges.to_vec();
+            edges.extend(spur.edges);
+            let candidate = Route {
+                vertices,
+                edges,
+                weight: root_weight.saturating_add(spur.weight),
+            };
+
+            if !candidates.contains(&candidate) && !routes.contains(&candidate) {
+                candidates.push(candidate);
+            }
+        }
+
+        let Some(best) = (0..candidates.len()).min_by_key(|&i| candidates[i].weight) else {
+            break;
+        };
+        routes.push(candidates.swap_remove(best));
+    }
+
+    routes
+}
+
+/// The oracle's path as a route of the raw graph, over the cheapest of parallel edges. `None`
+/// if there is no path or it uses an edge the raw graph does not have.
+fn oracle_route(
+    graph: &Graph,
+    shortest_path: &impl Fn(u32, u32) -> Option<Vec<u32>>,
+    source: u32,
+    target: u32,
+) -> Option<Route> {
+    let vertices = if source == target {
+        vec![source]
+    } else {
+        shortest_path(source, target)?
+    };
+    let edges = vertices
+        .windows(2)
+        .map(|pair| graph.edge_between(pair[0], pair[1]))
+        .collect::<Option<Vec<u32>>>()?;
+    let weight = edges
+        .iter()
+        .map(|&edge_id| graph.edges[edge_id as usize].weight)
+        .fold(0u32, u32::saturating_add);
+    Some(Route {
+        vertices,
+        edges,
+        weight,
+    })
+}
+
+fn a_star(
+    graph: &Graph,
+    source: u32,
+    target: u32,
+    potential: &mut impl FnMut(u32) -> u32,
+    banned_vertices: &HashSet<u32>,
+    banned_edges: &HashSet<u32>,
+) -> Option<Route> {
+    // vertex -> (distance, edge it was reached over)
+    let mut labels: HashMap<u32, (u32, Option<u32>)> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    labels.insert(source, (0, None));
+    queue.push(Reverse((potential(source), 0, source)));
+
+    while let Some(Reverse((_, distance, vertex))) = queue.pop() {
+        if vertex == target {
+            break;
+        }
+        if distance > labels[&vertex].0 {
+            continue;
+        }
+        for &edge_id in graph.out_edges(vertex).iter() {
+            let edge = &graph.edges[edge_id as usize];
+            if banned_edges.contains(&edge_id) || banned_vertices.contains(&edge.target) {
+                continue;
+            }
+            let potential = potential(edge.target);
+            if potential == u32::MAX {
+                continue;
+            }
+            let alternative_distance = distance.saturating_add(edge.weight);
+            let is_better = labels
+                .get(&edge.target)
+                .is_none_or(|&(distance, _)| alternative_distance < distance);
+            if is_better {
+                labels.insert(edge.target, (alternative_distance, Some(edge_id)));
+                queue.push(Reverse((
+                    alternative_distance.saturating_add(potential),
+                    alternative_distance,
+                    edge.target,
+                )));
+            }
+        }
+    }
+
+    let &(weight, _) = labels.get(&target)?;
+    let mut vertices = vec![target];
+    let mut edges = Vec::new();
+    while let Some(edge_id) = labels[vertices.last().unwrap()].1 {
+        edges.push(edge_id);
+        vertices.push(graph.edges[edge_id as usize].source);
+    }
+    vertices.reverse();
+    edges.reverse();
+
+    Some(Route {
+        vertices,
+        edges,
+        weight,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dijkstra::shortest_path_tree, graph::Edge};
+
+    fn graph(edges: &[(u32, u32, u32)]) -> Graph {
+        let edges = edges
+            .iter()
+            .map(|&(source, target, weight)| Edge {
+                source,
+                target,
+                weight,
+            })
+            .collect();
+        Graph::from_edges(edges, 0)
+    }
+
+    /// Dijkstra standing in for the CH and HL path finders.
+    fn dijkstra(graph: &Graph) -> impl Fn(u32, u32) -> Option<Vec<u32>> + '_ {
+        move |source, target| {
+            let tree = shortest_path_tree(graph, source, None);
+            if tree.distances[target as usize] == u32::MAX {
+                return None;
+            }
+            let mut vertices = vec![target];
+            while let Some(edge_id) = tree.predecessors[*vertices.last().unwrap() as usize] {
+                vertices.push(graph.edges[edge_id as usize].source);
+            }
+            vertices.reverse();
+            Some(vertices)
+        }
+    }
+
+    fn diamond() -> Graph {
+        graph(&[
+            (0, 1, 1),
+            (1, 3, 1),
+            (0, 2, 2),
+            (2, 3, 2),
+            (0, 3, 5),
+            (1, 2, 1),
+        ])
+    }
+
+    #[test]
+    fn finds_the_loopless_paths_by_weight() {
+        let graph = diamond();
+        let routes = k_shortest_paths(&graph, dijkstra(&graph), 0, 3, 10);
+        let weights: Vec<u32> = routes.iter().map(|route| route.weight).collect();
+        assert_eq!(weights, vec![2, 4, 4, 5]);
+        assert_eq!(routes[0].vertices, vec![0, 1, 3]);
+        let mut second_and_third = vec![routes[1].vertices.clone(), routes[2].vertices.clone()];
+        second_and_third.sort();
+        assert_eq!(second_and_third, vec![vec![0, 1, 2, 3], vec![0, 2, 3]]);
+        assert_eq!(routes[3].vertices, vec![0, 3]);
+        for route in routes.iter() {
+            assert_eq!(graph.path_weight(&route.vertices), Ok(route.weight));
+            assert_eq!(route.edges.len() + 1, route.vertices.len());
+        }
+    }
+
+    #[test]
+    fn stops_after_k_paths() {
+        let graph = diamond();
+        let routes = k_shortest_paths(&graph, dijkstra(&graph), 0, 3, 2);
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].weight, 2);
+        assert_eq!(routes[1].weight, 4);
+    }
+
+    #[test]
+    fn finds_nothing_without_a_path() {
+        let graph = diamond();
+        assert!(k_shortest_paths(&graph, dijkstra(&graph), 3, 0, 3).is_empty());
+    }
+
+    #[test]
+    fn prefers_the_lightest_parallel_edge() {
+        let graph = graph(&[(0, 1, 4), (0, 1, 1), (1, 2, 1)]);
+        let routes = k_shortest_paths(&graph, dijkstra(&graph), 0, 2, 3);
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].edges, vec![1, 2]);
+        assert_eq!(routes[1].edges, vec![0, 2]);
+        assert_eq!(routes[1].weight, 5);
+    }
+
+    #[test]
+    fn a_star_saturates_instead_of_overflowing() {
+        let graph = graph(&[(0, 1, u32::MAX - 1), (1, 2, 1)]);
+        let route = a_star(
+            &graph,
+            0,
+            2,
+            &mut |vertex| if vertex == 2 { 0 } else { u32::MAX - 1 },
+            &HashSet::new(),
+            &HashSet::new(),
+        )
+        .unwrap();
+        assert_eq!(route.vertices, vec![0, 1, 2]);
+        assert_eq!(route.weight, u32::MAX);
+    }
+}
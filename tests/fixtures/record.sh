@@ -0,0 +1,23 @@
+#!/bin/sh
+# Records tests/fixtures/route with `serve --record-dir` on the tiny graph. tiny.ch is built
+# from tiny.gr with faster_paths (make create_ch there) before running this.
+set -e
+cd "$(dirname "$0")"
+rm -rf route
+cargo build --release
+../../target/release/fapra_submission serve \
+    --gr-path tiny.gr --co-path tiny.co --ch-path tiny.ch \
+    --port 3031 --record-dir route &
+server=$!
+trap 'kill $server' EXIT
+
+route() {
+    curl --silent --output /dev/null --retry 30 --retry-connrefused \
+        --header 'content-type: application/json' \
+        --data "$2" "http://localhost:3031/route?$1"
+}
+
+route 'format=coordinates&full_geometry=true' '{"from": [9.0, 48.0], "to": [9.6, 48.3]}'
+route 'format=coordinates&full_geometry=true' '{"from": [9.6, 48.3], "to": [9.0, 48.0]}'
+route 'format=polyline&full_geometry=true' '{"from": [9.0, 48.0], "to": [9.6, 48.3]}'
+route 'algorithm=hl' '{"from": [9.0, 48.0], "to": [9.6, 48.3]}'